@@ -20,7 +20,7 @@ fn main() {
     let content = std::fs::read(rom).expect("failed to read rom file");
     let rom = Rom::new(&content).unwrap();
 
-    let mut core = NesNoveCore::new(rom);
+    let mut core = NesNoveCore::new(rom).unwrap();
     core.reset();
     core.pc = 0xC000;
 
@@ -0,0 +1,29 @@
+extern crate structopt;
+
+use std::error::Error;
+
+use nove_core::cartridge::import_nes20db;
+use structopt::StructOpt;
+
+/// Converts the NES 2.0 database of the NESdev wiki (nes20db.xml) into the game database
+/// bundled with the emulator, src/cartridge/database.txt
+#[derive(Debug, StructOpt)]
+struct Args {
+    /// The nes20db.xml file
+    input: String,
+    /// Database file to write
+    #[structopt(default_value = "nove_core/src/cartridge/database.txt")]
+    output: String,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::from_args();
+    let xml = std::fs::read_to_string(&args.input)?;
+    let database = import_nes20db(&xml);
+    println!(
+        "{} games imported",
+        database.lines().filter(|l| !l.starts_with('#')).count()
+    );
+    std::fs::write(&args.output, database)?;
+    Ok(())
+}
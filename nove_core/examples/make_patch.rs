@@ -0,0 +1,61 @@
+extern crate structopt;
+
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+
+use nove_core::patch;
+use structopt::StructOpt;
+
+/// Creates the patch turning an original ROM into a modified one
+#[derive(Debug, StructOpt)]
+struct Args {
+    /// Original ROM
+    original: String,
+    /// Modified ROM
+    modified: String,
+    /// Patch file to write
+    output: String,
+    /// Patch format, ips or bps. Taken from the extension of the output by default.
+    #[structopt(short, long)]
+    format: Option<Format>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ips,
+    Bps,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ips" => Ok(Format::Ips),
+            "bps" => Ok(Format::Bps),
+            _ => Err(format!("unsupported patch format: {s}")),
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::from_args();
+    let format = match args.format {
+        Some(format) => format,
+        None => Path::new(&args.output)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .parse()?,
+    };
+
+    let original = std::fs::read(&args.original)?;
+    let modified = std::fs::read(&args.modified)?;
+    let patch = match format {
+        Format::Ips => patch::create_ips(&original, &modified)?,
+        Format::Bps => patch::create_bps(&original, &modified),
+    };
+    std::fs::write(&args.output, patch)?;
+    Ok(())
+}
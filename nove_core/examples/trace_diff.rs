@@ -0,0 +1,294 @@
+extern crate structopt;
+
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use structopt::StructOpt;
+
+/// Streams two CPU traces and reports the first instruction where they diverge
+#[derive(Debug, StructOpt)]
+struct Args {
+    /// Trace under test, usually the output of the `cpu_trace` example
+    left: String,
+    /// Reference trace (nestest or Mesen format)
+    right: String,
+    /// Number of preceding lines to print before the divergent one
+    #[structopt(short, long, default_value = "5")]
+    context: usize,
+    /// Fields to skip in the comparison: pc, bytes, disasm, a, x, y, p, sp, ppu, cyc
+    #[structopt(short, long)]
+    ignore: Vec<Field>,
+    /// Mask applied to P before comparing it, e.g. `0xcf` skips the B and unused bits
+    #[structopt(long, default_value = "0xff", parse(try_from_str = parse_hex))]
+    p_mask: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Pc,
+    Bytes,
+    Disasm,
+    A,
+    X,
+    Y,
+    P,
+    Sp,
+    Ppu,
+    Cyc,
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "pc" => Ok(Field::Pc),
+            "bytes" => Ok(Field::Bytes),
+            "disasm" => Ok(Field::Disasm),
+            "a" => Ok(Field::A),
+            "x" => Ok(Field::X),
+            "y" => Ok(Field::Y),
+            "p" => Ok(Field::P),
+            "sp" | "s" => Ok(Field::Sp),
+            "ppu" => Ok(Field::Ppu),
+            "cyc" | "cycle" => Ok(Field::Cyc),
+            _ => Err(format!("unknown trace field: {s}")),
+        }
+    }
+}
+
+fn parse_hex(s: &str) -> Result<u8, String> {
+    u8::from_str_radix(s.trim_start_matches("0x"), 16).map_err(|e| e.to_string())
+}
+
+/// Instruction state normalized from any of the supported trace formats
+#[derive(Debug, Default)]
+struct TraceLine {
+    pc: u16,
+    bytes: Vec<u8>,
+    disasm: String,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    sp: u8,
+    ppu: Option<(u16, u16)>,
+    cyc: Option<u64>,
+}
+
+impl TraceLine {
+    /// Parses both nestest (`C000  4C F5 C5  JMP $C5F5 ... A:00 X:00 Y:00 P:24 SP:FD`) and
+    /// Mesen (`C000  $4C $F5 $C5  JMP $C5F5 ... A:00 X:00 Y:00 S:FD P:nvUbdIzc`) lines
+    fn parse(line: &str) -> Option<Self> {
+        let regs_start = line.find("A:")?;
+        let (instr, regs) = line.split_at(regs_start);
+
+        let mut tokens = instr.split_whitespace();
+        let pc = u16::from_str_radix(tokens.next()?.trim_end_matches(':'), 16).ok()?;
+
+        let mut trace = TraceLine {
+            pc,
+            ..Default::default()
+        };
+        let mut disasm = Vec::new();
+        for token in tokens {
+            let hex = token.trim_start_matches('$');
+            match u8::from_str_radix(hex, 16) {
+                Ok(byte) if hex.len() == 2 && disasm.is_empty() => trace.bytes.push(byte),
+                _ => disasm.push(token),
+            }
+        }
+        // Mesen prefixes every hex value with `$`, nestest only the operands
+        trace.disasm = disasm.join(" ").replace('$', "").to_ascii_uppercase();
+
+        let mut tokens = regs.split_whitespace();
+        while let Some(token) = tokens.next() {
+            let Some((key, val)) = token.split_once(':') else {
+                continue;
+            };
+            match key {
+                "A" => trace.a = parse_hex(val).ok()?,
+                "X" => trace.x = parse_hex(val).ok()?,
+                "Y" => trace.y = parse_hex(val).ok()?,
+                "SP" | "S" => trace.sp = parse_hex(val).ok()?,
+                "P" => trace.p = parse_hex(val).or_else(|_| parse_flags(val)).ok()?,
+                "PPU" => {
+                    // nestest pads the scanline, so the value can be split in several tokens
+                    let mut ppu = val.to_string();
+                    while !ppu.contains(',') || ppu.ends_with(',') {
+                        ppu.push_str(tokens.next()?);
+                    }
+                    let (scanline, dot) = ppu.split_once(',')?;
+                    trace.ppu = Some((scanline.trim().parse().ok()?, dot.trim().parse().ok()?));
+                }
+                "CYC" | "Cycle" => trace.cyc = val.parse().ok(),
+                _ => {}
+            }
+        }
+        Some(trace)
+    }
+
+    fn diff(&self, other: &TraceLine, args: &Args) -> Vec<(Field, String, String)> {
+        let mut diff = Vec::new();
+        let mut check = |field: Field, left: String, right: String| {
+            if !args.ignore.contains(&field) && left != right {
+                diff.push((field, left, right));
+            }
+        };
+        check(Field::Pc, hex16(self.pc), hex16(other.pc));
+        check(
+            Field::Bytes,
+            hex_bytes(&self.bytes),
+            hex_bytes(&other.bytes),
+        );
+        check(Field::Disasm, self.disasm.clone(), other.disasm.clone());
+        check(Field::A, hex8(self.a), hex8(other.a));
+        check(Field::X, hex8(self.x), hex8(other.x));
+        check(Field::Y, hex8(self.y), hex8(other.y));
+        check(
+            Field::P,
+            flags(self.p & args.p_mask),
+            flags(other.p & args.p_mask),
+        );
+        check(Field::Sp, hex8(self.sp), hex8(other.sp));
+        // timing is only compared when both traces record it
+        if let (Some(left), Some(right)) = (self.ppu, other.ppu) {
+            check(Field::Ppu, format!("{left:?}"), format!("{right:?}"));
+        }
+        if let (Some(left), Some(right)) = (self.cyc, other.cyc) {
+            check(Field::Cyc, left.to_string(), right.to_string());
+        }
+        diff
+    }
+}
+
+/// Mesen prints P as `NV-BDIZC` letters, uppercase when the flag is set
+fn parse_flags(flags: &str) -> Result<u8, String> {
+    if flags.len() != 8 {
+        return Err(format!("invalid flags: {flags}"));
+    }
+    Ok(flags
+        .chars()
+        .fold(0, |acc, c| acc << 1 | c.is_ascii_uppercase() as u8))
+}
+
+fn flags(p: u8) -> String {
+    "NV-BDIZC"
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if p & (0b1000_0000 >> i) != 0 { c } else { '.' })
+        .collect::<String>()
+        + &format!(" ({p:02X})")
+}
+
+fn hex8(val: u8) -> String {
+    format!("{val:02X}")
+}
+
+fn hex16(val: u16) -> String {
+    format!("{val:04X}")
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| hex8(*b)).collect::<Vec<_>>().join(" ")
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.pad(&format!("{self:?}").to_ascii_uppercase())
+    }
+}
+
+/// Iterates over the instructions of a trace, skipping lines that are not instructions
+struct Trace {
+    lines: Lines<BufReader<File>>,
+    line_number: usize,
+}
+
+impl Trace {
+    fn open(path: &str) -> Self {
+        let file = File::open(path).unwrap_or_else(|e| panic!("failed to open {path}: {e}"));
+        Self {
+            lines: BufReader::new(file).lines(),
+            line_number: 0,
+        }
+    }
+}
+
+impl Iterator for Trace {
+    type Item = (usize, String, TraceLine);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for line in self.lines.by_ref() {
+            self.line_number += 1;
+            let line = line.expect("failed to read trace line");
+            if let Some(trace) = TraceLine::parse(&line) {
+                return Some((self.line_number, line, trace));
+            }
+        }
+        None
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::from_args();
+
+    let mut left = Trace::open(&args.left);
+    let mut right = Trace::open(&args.right);
+    let mut history = VecDeque::with_capacity(args.context + 1);
+    let mut instructions = 0;
+
+    loop {
+        let (l, r) = match (left.next(), right.next()) {
+            (Some(l), Some(r)) => (l, r),
+            (None, None) => {
+                println!("traces match ({instructions} instructions)");
+                return ExitCode::SUCCESS;
+            }
+            (l, _) => {
+                let (shorter, path) = if l.is_none() {
+                    (&args.left, &args.right)
+                } else {
+                    (&args.right, &args.left)
+                };
+                println!("{shorter} ended after {instructions} instructions, {path} continues");
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let diff = l.2.diff(&r.2, &args);
+        if !diff.is_empty() {
+            println!(
+                "divergence at instruction {} ({}:{} / {}:{})",
+                instructions + 1,
+                args.left,
+                l.0,
+                args.right,
+                r.0
+            );
+            println!();
+            for line in &history {
+                println!("  {line}");
+            }
+            println!("< {}", l.1);
+            println!("> {}", r.1);
+            println!();
+            for (field, left, right) in diff {
+                println!("{field:>6}: {left:<24} {right}");
+            }
+            return ExitCode::FAILURE;
+        }
+
+        if args.context > 0 {
+            if history.len() == args.context {
+                history.pop_front();
+            }
+            history.push_back(l.1);
+        }
+        instructions += 1;
+    }
+}
@@ -21,7 +21,13 @@ pub mod ppu {
     pub const VRAM_END: u16 = 0x2fff;
 }
 
+pub mod input {
+    pub const JOYPAD_1: u16 = 0x4016;
+    pub const JOYPAD_2: u16 = 0x4017;
+}
+
 pub mod rom {
+    pub const CARTRIDGE_START: u16 = 0x4020;
     pub const PRG_ROM_START: u16 = 0x8000;
     pub const PRG_ROM_END: u16 = 0xffff;
 }
@@ -1,17 +1,46 @@
+use crate::addresses::ppu::{VRAM_END, VRAM_START};
 use crate::exception::NoveError;
+use crate::mapper::Mapper;
 use crate::Program;
+use log::warn;
+use sha1::{Digest, Sha1};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub use builder::RomBuilder;
+pub use database::{import_nes20db, Correction};
+pub use fds::is_fds_image;
+
+mod builder;
+mod database;
+mod fds;
+mod unif;
 
 const NES_TAG: [u8; 4] = [b'N', b'E', b'S', 0x1a];
 const HEADER_SIZE: usize = 16;
 const TRAINER_SIZE: usize = 512;
 const PRG_ROM_PAGE_SIZE: usize = 16384; // 16kB
 const CHR_ROM_PAGE_SIZE: usize = 8192; //  8kB
+const PRG_RAM_PAGE_SIZE: usize = 8192; //  8kB
+const NAMETABLE_SIZE: u16 = 1024; // 1KiB
+const NES2_ID_MASK: u8 = 0b0000_1100;
+const NES2_ID: u8 = 0b0000_1000;
+/// Most significant nibble of the NES 2.0 ROM sizes telling they use an exponent and multiplier
+const EXPONENT_SIZE: usize = 0xf;
+/// NES 2.0 RAM sizes are shift counts of 64 bytes
+const RAM_SIZE_UNIT: usize = 64;
+
+/// Cartridge shared between the CPU bus and the PPU
+pub type Cartridge = Rc<RefCell<dyn Mapper>>;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub enum Mirroring {
     Vertical,
+    #[default]
     Horizontal,
     FourScreen,
+    SingleScreenLower,
+    SingleScreenUpper,
 }
 
 impl From<u8> for Mirroring {
@@ -24,39 +53,260 @@ impl From<u8> for Mirroring {
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl Mirroring {
+    /// Index in the VRAM of the console of a nametable address ($2000-$2FFF)
+    pub fn vram_addr(&self, addr: u16) -> usize {
+        let vram = (addr & VRAM_END) - VRAM_START;
+        let name_table = vram / NAMETABLE_SIZE;
+        let bank = match self {
+            Mirroring::Vertical => name_table % 2,
+            Mirroring::Horizontal => name_table / 2,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+            Mirroring::FourScreen => name_table,
+        };
+        (bank * NAMETABLE_SIZE + vram % NAMETABLE_SIZE) as usize
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum HeaderFormat {
+    #[default]
+    INes,
+    Nes2,
+    Unif,
+    Fds,
+}
+
+/// CPU and PPU timing the game was made for
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Timing {
+    #[default]
+    Ntsc,
+    Pal,
+    /// Games that work with any region
+    MultipleRegion,
+    Dendy,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Console {
+    #[default]
+    Nes,
+    /// Arcade system with the type of its PPU and its hardware variant
+    VsSystem {
+        ppu: u8,
+        hardware: u8,
+    },
+    Playchoice10,
+    /// Extended console types of the NES 2.0 headers, like famiclones
+    Extended(u8),
+}
+
+#[derive(Debug, Default, PartialEq)]
 pub struct Rom {
     pub prg_rom: Program,
     pub chr_rom: Program,
-    mapper: u8,
+    pub format: HeaderFormat,
+    pub mapper: u16,
+    /// Variant of the mapper, only defined by NES 2.0 headers
+    pub submapper: u8,
     pub screen_mirroring: Mirroring,
+    /// Volatile PRG RAM
+    pub prg_ram_size: usize,
+    /// PRG RAM kept by the battery
+    pub prg_nvram_size: usize,
+    /// Volatile CHR RAM
+    pub chr_ram_size: usize,
+    /// CHR RAM kept by the battery
+    pub chr_nvram_size: usize,
+    pub battery: bool,
+    /// Mirroring bit of the header, which some boards combine with the four-screen one
+    pub mirroring_bit: bool,
+    pub timing: Timing,
+    pub console: Console,
+    /// Default expansion device of the NES 2.0 headers, zero when unspecified
+    pub expansion_device: u8,
+    /// Header fields overridden by the game database
+    pub corrections: Vec<Correction>,
+    /// Sides of the disks of the Famicom Disk System images
+    pub disk_sides: Vec<Program>,
+    /// Code loaded at $7000-$71FF of the PRG RAM, used by some hacked dumps. Empty when the
+    /// file has no trainer.
+    pub trainer: Program,
 }
 
 impl Rom {
     pub fn new(raw: &Program) -> Result<Rom, NoveError> {
-        if raw[0..4] != NES_TAG {
+        let mut rom = if raw.starts_with(unif::MAGIC) {
+            unif::parse(raw)?
+        } else if is_fds_image(raw) {
+            return Err(NoveError::FdsBiosRequired);
+        } else {
+            Self::ines(raw)?
+        };
+        rom.corrections = database::correct(&mut rom);
+        for correction in &rom.corrections {
+            warn!("wrong header, corrected {correction}");
+        }
+        Ok(rom)
+    }
+
+    /// Parses an iNES or NES 2.0 file
+    fn ines(raw: &[u8]) -> Result<Rom, NoveError> {
+        if !raw.starts_with(&NES_TAG) || raw.len() < HEADER_SIZE {
             return Err(NoveError::WrongRomFormat);
         }
+        let (mut rom, prg_rom_size, chr_rom_size) = match raw[7] & NES2_ID_MASK {
+            NES2_ID => Self::nes2_header(raw).ok_or(NoveError::WrongRomFormat)?,
+            0 if raw[12..HEADER_SIZE].iter().all(|&byte| byte == 0) => Self::ines_header(raw),
+            // archaic iNES, where old tools left garbage like "DiskDude!" after the byte 6
+            _ => {
+                let mut header = [0; HEADER_SIZE];
+                header[..7].copy_from_slice(&raw[..7]);
+                Self::ines_header(&header)
+            }
+        };
+
+        let trainer_size = if raw[6] & 0b100 != 0 { TRAINER_SIZE } else { 0 };
 
-        if ((raw[7] >> 2) & 0b11) != 0 {
+        // the sizes are checked against the file before allocating any of the memories
+        let prg_rom_start = HEADER_SIZE + trainer_size;
+        let chr_rom_start = prg_rom_start
+            .checked_add(prg_rom_size)
+            .ok_or(NoveError::WrongRomFormat)?;
+        let chr_rom_end = chr_rom_start
+            .checked_add(chr_rom_size)
+            .ok_or(NoveError::WrongRomFormat)?;
+        if raw.len() < chr_rom_end {
             return Err(NoveError::WrongRomFormat);
         }
 
+        rom.trainer = raw[HEADER_SIZE..prg_rom_start].to_vec();
+        rom.prg_rom = raw[prg_rom_start..chr_rom_start].to_vec();
+        rom.chr_rom = raw[chr_rom_start..chr_rom_end].to_vec();
+        Ok(rom)
+    }
+
+    /// Size of the PRG RAM, volatile or not, the mappers have to map
+    pub fn prg_ram_total(&self) -> usize {
+        self.prg_ram_size + self.prg_nvram_size
+    }
+
+    /// CRC32 of the PRG and CHR ROM, the key of the game database
+    pub fn crc32(&self) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&self.prg_rom);
+        hasher.update(&self.chr_rom);
+        hasher.finalize()
+    }
+
+    /// SHA-1 of the PRG and CHR ROM
+    pub fn sha1(&self) -> [u8; 20] {
+        let mut hasher = Sha1::new();
+        hasher.update(&self.prg_rom);
+        hasher.update(&self.chr_rom);
+        hasher.finalize().into()
+    }
+
+    /// Size of the CHR RAM, volatile or not, of the boards without CHR ROM
+    pub fn chr_ram_total(&self) -> usize {
+        self.chr_ram_size + self.chr_nvram_size
+    }
+
+    /// Fields common to both header formats
+    fn common_header(raw: &[u8]) -> Self {
+        Rom {
+            mapper: ((raw[7] & 0b1111_0000) | (raw[6] >> 4)) as u16,
+            screen_mirroring: raw[6].into(),
+            battery: raw[6] & 0b10 != 0,
+            mirroring_bit: raw[6] & 0b1 != 0,
+            ..Default::default()
+        }
+    }
+
+    /// Parses an iNES header, with the PRG and CHR ROM sizes it tells
+    fn ines_header(raw: &[u8]) -> (Self, usize, usize) {
         let prg_rom_size = raw[4] as usize * PRG_ROM_PAGE_SIZE;
         let chr_rom_size = raw[5] as usize * CHR_ROM_PAGE_SIZE;
+        let mut rom = Self::common_header(raw);
+        // a zero value is used for 8kB for compatibility
+        let prg_ram_size = raw[8].max(1) as usize * PRG_RAM_PAGE_SIZE;
+        if rom.battery {
+            rom.prg_nvram_size = prg_ram_size;
+        } else {
+            rom.prg_ram_size = prg_ram_size;
+        }
+        if chr_rom_size == 0 {
+            rom.chr_ram_size = CHR_ROM_PAGE_SIZE;
+        }
+        if raw[9] & 0b1 != 0 {
+            rom.timing = Timing::Pal;
+        }
+        rom.console = match raw[7] & 0b11 {
+            1 => Console::VsSystem {
+                ppu: 0,
+                hardware: 0,
+            },
+            2 => Console::Playchoice10,
+            _ => Console::Nes,
+        };
+        (rom, prg_rom_size, chr_rom_size)
+    }
 
-        let trainer = raw[6] & 0b100 != 0;
+    /// Parses a NES 2.0 header, with the PRG and CHR ROM sizes it tells. `None` when the
+    /// sizes don't fit in memory.
+    fn nes2_header(raw: &[u8]) -> Option<(Self, usize, usize)> {
+        let prg_rom_size = nes2_rom_size(raw[4], raw[9] & 0x0f, PRG_ROM_PAGE_SIZE)?;
+        let chr_rom_size = nes2_rom_size(raw[5], raw[9] >> 4, CHR_ROM_PAGE_SIZE)?;
+        let rom = Rom {
+            format: HeaderFormat::Nes2,
+            mapper: ((raw[8] & 0x0f) as u16) << 8 | ((raw[7] & 0xf0) | (raw[6] >> 4)) as u16,
+            submapper: raw[8] >> 4,
+            prg_ram_size: nes2_ram_size(raw[10] & 0x0f),
+            prg_nvram_size: nes2_ram_size(raw[10] >> 4),
+            chr_ram_size: nes2_ram_size(raw[11] & 0x0f),
+            chr_nvram_size: nes2_ram_size(raw[11] >> 4),
+            timing: match raw[12] & 0b11 {
+                0 => Timing::Ntsc,
+                1 => Timing::Pal,
+                2 => Timing::MultipleRegion,
+                _ => Timing::Dendy,
+            },
+            console: match raw[7] & 0b11 {
+                0 => Console::Nes,
+                1 => Console::VsSystem {
+                    ppu: raw[13] & 0x0f,
+                    hardware: raw[13] >> 4,
+                },
+                2 => Console::Playchoice10,
+                _ => Console::Extended(raw[13] & 0x0f),
+            },
+            expansion_device: raw[15] & 0b0011_1111,
+            ..Self::common_header(raw)
+        };
+        Some((rom, prg_rom_size, chr_rom_size))
+    }
+}
 
-        let prg_rom_start = HEADER_SIZE + if trainer { TRAINER_SIZE } else { 0 };
-        let chr_rom_start = prg_rom_start + prg_rom_size;
-        let chr_rom_end = chr_rom_start + chr_rom_size;
+/// ROM size of a NES 2.0 header from its least significant byte and most significant nibble.
+/// When the nibble is $F the byte is an exponent and a multiplier instead: `EEEE EEMM`.
+fn nes2_rom_size(lsb: u8, msb: u8, page_size: usize) -> Option<usize> {
+    if msb as usize == EXPONENT_SIZE {
+        let multiplier = (lsb & 0b11) as usize * 2 + 1;
+        1usize
+            .checked_shl((lsb >> 2) as u32)?
+            .checked_mul(multiplier)
+    } else {
+        Some(((msb as usize) << 8 | lsb as usize) * page_size)
+    }
+}
 
-        Ok(Rom {
-            prg_rom: raw[prg_rom_start..chr_rom_start].to_vec(),
-            chr_rom: raw[chr_rom_start..chr_rom_end].to_vec(),
-            mapper: (raw[7] & 0b1111_0000) | (raw[6] >> 4),
-            screen_mirroring: raw[6].into(),
-        })
+/// RAM size of a NES 2.0 header, zero or 64 bytes shifted left by the value
+fn nes2_ram_size(shift: u8) -> usize {
+    match shift {
+        0 => 0,
+        shift => RAM_SIZE_UNIT << shift,
     }
 }
 
@@ -65,6 +315,96 @@ mod test {
     use super::*;
     use crate::exception::NoveError;
 
+    fn header(bytes: [u8; 12]) -> Vec<u8> {
+        let mut raw = NES_TAG.to_vec();
+        raw.extend(bytes);
+        raw
+    }
+
+    #[test]
+    fn ines_header() {
+        let mut raw = header([2, 0, 0x43, 0x10, 0, 1, 0, 0, 0, 0, 0, 0]);
+        raw.resize(HEADER_SIZE + 2 * PRG_ROM_PAGE_SIZE, 0);
+        let rom = Rom::new(&raw).unwrap();
+        assert_eq!(rom.format, HeaderFormat::INes);
+        assert_eq!(rom.mapper, 0x14);
+        assert_eq!(rom.prg_rom.len(), 2 * PRG_ROM_PAGE_SIZE);
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+        assert_eq!(
+            (rom.prg_ram_size, rom.prg_nvram_size),
+            (0, PRG_RAM_PAGE_SIZE)
+        );
+        assert_eq!(rom.chr_ram_size, CHR_ROM_PAGE_SIZE);
+        assert_eq!(rom.timing, Timing::Pal);
+    }
+
+    #[test]
+    fn nes2_header() {
+        let mut raw = header([
+            0x02, 0x01, 0x12, 0x39, 0x54, 0x00, 0x07, 0x70, 0x03, 0x21, 0x00, 0x01,
+        ]);
+        raw.resize(HEADER_SIZE + 2 * PRG_ROM_PAGE_SIZE + CHR_ROM_PAGE_SIZE, 0);
+        let rom = Rom::new(&raw).unwrap();
+        assert_eq!(rom.format, HeaderFormat::Nes2);
+        assert_eq!(rom.mapper, 0x431);
+        assert_eq!(rom.submapper, 5);
+        assert_eq!(rom.prg_rom.len(), 2 * PRG_ROM_PAGE_SIZE);
+        assert_eq!(rom.chr_rom.len(), CHR_ROM_PAGE_SIZE);
+        assert_eq!((rom.prg_ram_size, rom.prg_nvram_size), (0x2000, 0));
+        assert_eq!((rom.chr_ram_size, rom.chr_nvram_size), (0, 0x2000));
+        assert_eq!(rom.timing, Timing::Dendy);
+        assert_eq!(
+            rom.console,
+            Console::VsSystem {
+                ppu: 1,
+                hardware: 2
+            }
+        );
+        assert_eq!(rom.expansion_device, 1);
+    }
+
+    #[test]
+    fn nes2_sizes() {
+        assert_eq!(
+            nes2_rom_size(0x02, 0x1, PRG_ROM_PAGE_SIZE),
+            Some(0x102 * 0x4000)
+        );
+        // 2^10 * 3
+        assert_eq!(
+            nes2_rom_size(0b0010_1001, 0xf, PRG_ROM_PAGE_SIZE),
+            Some(3072)
+        );
+        assert_eq!(nes2_rom_size(0xff, 0xf, CHR_ROM_PAGE_SIZE), None);
+        assert_eq!(nes2_ram_size(0), 0);
+        assert_eq!(nes2_ram_size(7), 0x2000);
+    }
+
+    #[test]
+    fn trainer() {
+        let rom = RomBuilder::new()
+            .prg_rom(vec![0xbb; PRG_ROM_PAGE_SIZE])
+            .trainer(vec![0xaa; TRAINER_SIZE])
+            .rom()
+            .unwrap();
+        assert_eq!(rom.trainer, vec![0xaa; TRAINER_SIZE]);
+        assert_eq!(rom.prg_rom, vec![0xbb; PRG_ROM_PAGE_SIZE]);
+
+        let mapper = crate::mapper::load(rom).unwrap();
+        assert_eq!(mapper.borrow_mut().read_prg(0x7000), Some(0xaa));
+        assert_eq!(mapper.borrow_mut().read_prg(0x7200), Some(0));
+    }
+
+    #[test]
+    fn hashes() {
+        let rom = Rom {
+            prg_rom: b"12345".to_vec(),
+            chr_rom: b"6789".to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(rom.crc32(), 0xcbf43926);
+        assert_eq!(rom.sha1()[..4], [0xf7, 0xc3, 0xbc, 0x1d]);
+    }
+
     #[test]
     fn invalid_format() {
         assert_eq!(
@@ -76,4 +416,36 @@ mod test {
             Rom::new(&vec![b'N', b'E', b'S', 0x1a, 0, 0, 0, 4])
         );
     }
+
+    #[test]
+    fn archaic_ines_header() {
+        let mut raw = NES_TAG.to_vec();
+        raw.extend([1, 1, 0x11]);
+        raw.extend(b"DiskDude!");
+        raw.resize(HEADER_SIZE + PRG_ROM_PAGE_SIZE + CHR_ROM_PAGE_SIZE, 0);
+        let rom = Rom::new(&raw).unwrap();
+        assert_eq!(rom.format, HeaderFormat::INes);
+        assert_eq!(rom.mapper, 1);
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+        assert_eq!(rom.console, Console::Nes);
+        assert_eq!(rom.timing, Timing::Ntsc);
+        assert_eq!(rom.prg_ram_size, PRG_RAM_PAGE_SIZE);
+
+        // iNES headers with something in the last bytes are archaic too
+        raw[7..HEADER_SIZE].fill(0);
+        raw[7] = 0x20;
+        raw[15] = 1;
+        assert_eq!(Rom::new(&raw).unwrap().mapper, 1);
+    }
+
+    #[test]
+    fn sizes_larger_than_file() {
+        // 2^62 bytes of PRG ROM in the exponent notation
+        let mut raw = header([0xf8, 0, 0, 0x08, 0, 0x0f, 0, 0, 0, 0, 0, 0]);
+        raw.resize(32, 0);
+        assert_eq!(Rom::new(&raw), Err(NoveError::WrongRomFormat));
+        // 2^63 * 7 overflows the size
+        raw[4] = 0xff;
+        assert_eq!(Rom::new(&raw), Err(NoveError::WrongRomFormat));
+    }
 }
@@ -0,0 +1,381 @@
+use crate::cartridge::{Console, HeaderFormat, Mirroring, Rom, Timing};
+use crate::cartridge::{CHR_ROM_PAGE_SIZE, EXPONENT_SIZE, HEADER_SIZE, NES2_ID, NES_TAG};
+use crate::cartridge::{PRG_RAM_PAGE_SIZE, PRG_ROM_PAGE_SIZE, RAM_SIZE_UNIT, TRAINER_SIZE};
+use crate::exception::NoveError;
+use crate::Program;
+
+/// Largest page count of the NES 2.0 ROM sizes, as $F in the most significant nibble switches
+/// to the exponent notation
+const MAX_NES2_PAGES: usize = 0xeff;
+const MAX_NES2_RAM_SHIFT: u32 = 0xf;
+/// Largest page count of the iNES ROM sizes, a single byte
+const MAX_INES_PAGES: usize = 0xff;
+
+/// Builds iNES and NES 2.0 files from their memories and settings, to make synthetic cartridges
+/// or write a parsed `Rom` back. The iNES format can't tell some of the settings, like the
+/// CHR RAM size, which are lost when building it. Cartridges it can't describe at all, with
+/// a submapper, a mapper over 255 or too large ROMs, are built as NES 2.0 instead.
+///
+/// ```
+/// use nove_core::cartridge::{HeaderFormat, Mirroring, RomBuilder};
+///
+/// let rom = RomBuilder::new()
+///     .format(HeaderFormat::Nes2)
+///     .mapper(4)
+///     .prg_rom(vec![0; 0x8000])
+///     .chr_rom(vec![0; 0x2000])
+///     .mirroring(Mirroring::Vertical)
+///     .rom()
+///     .unwrap();
+/// assert_eq!(rom.mapper, 4);
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RomBuilder {
+    format: HeaderFormat,
+    prg_rom: Program,
+    chr_rom: Program,
+    trainer: Program,
+    mapper: u16,
+    submapper: u8,
+    mirroring: Mirroring,
+    battery: bool,
+    prg_ram_size: usize,
+    prg_nvram_size: usize,
+    chr_ram_size: usize,
+    chr_nvram_size: usize,
+    timing: Timing,
+    console: Console,
+    expansion_device: u8,
+}
+
+impl RomBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Header format of the file, iNES unless it's NES 2.0. ROMs too large for iNES are
+    /// written as NES 2.0 anyway.
+    pub fn format(mut self, format: HeaderFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn prg_rom(mut self, prg_rom: Program) -> Self {
+        self.prg_rom = prg_rom;
+        self
+    }
+
+    pub fn chr_rom(mut self, chr_rom: Program) -> Self {
+        self.chr_rom = chr_rom;
+        self
+    }
+
+    /// Trainer of the file, padded or cut to its 512 bytes
+    pub fn trainer(mut self, trainer: Program) -> Self {
+        self.trainer = trainer;
+        self
+    }
+
+    pub fn mapper(mut self, mapper: u16) -> Self {
+        self.mapper = mapper;
+        self
+    }
+
+    pub fn submapper(mut self, submapper: u8) -> Self {
+        self.submapper = submapper;
+        self
+    }
+
+    /// Mirroring of the header, where the single screen layouts are written as horizontal
+    pub fn mirroring(mut self, mirroring: Mirroring) -> Self {
+        self.mirroring = mirroring;
+        self
+    }
+
+    pub fn battery(mut self, battery: bool) -> Self {
+        self.battery = battery;
+        self
+    }
+
+    pub fn prg_ram(mut self, size: usize) -> Self {
+        self.prg_ram_size = size;
+        self
+    }
+
+    pub fn prg_nvram(mut self, size: usize) -> Self {
+        self.prg_nvram_size = size;
+        self
+    }
+
+    pub fn chr_ram(mut self, size: usize) -> Self {
+        self.chr_ram_size = size;
+        self
+    }
+
+    pub fn chr_nvram(mut self, size: usize) -> Self {
+        self.chr_nvram_size = size;
+        self
+    }
+
+    pub fn timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    pub fn console(mut self, console: Console) -> Self {
+        self.console = console;
+        self
+    }
+
+    pub fn expansion_device(mut self, expansion_device: u8) -> Self {
+        self.expansion_device = expansion_device;
+        self
+    }
+
+    /// Parses the built file like any other
+    pub fn rom(&self) -> Result<Rom, NoveError> {
+        Rom::new(&self.build())
+    }
+
+    /// Serializes the file: header, trainer, PRG ROM and CHR ROM. The ROMs are padded with
+    /// zeros to the next size the header can tell.
+    pub fn build(&self) -> Program {
+        let mut raw = NES_TAG.to_vec();
+        raw.resize(HEADER_SIZE, 0);
+        let ines_pages = [
+            self.prg_rom.len().div_ceil(PRG_ROM_PAGE_SIZE),
+            self.chr_rom.len().div_ceil(CHR_ROM_PAGE_SIZE),
+        ];
+        // ROMs iNES can't describe are written as NES 2.0 whatever the format asked for
+        let nes2_only = ines_pages.iter().any(|&pages| pages > MAX_INES_PAGES)
+            || self.mapper > 0xff
+            || self.submapper != 0;
+        let (prg_rom_size, chr_rom_size) = if self.format == HeaderFormat::Nes2 || nes2_only {
+            self.nes2_header(&mut raw)
+        } else {
+            self.ines_header(&mut raw)
+        };
+
+        raw[6] |= (self.mapper as u8 & 0x0f) << 4;
+        raw[7] |= self.mapper as u8 & 0xf0;
+        raw[6] |= match self.mirroring {
+            Mirroring::Vertical => 0b0001,
+            Mirroring::FourScreen => 0b1000,
+            _ => 0,
+        };
+        if self.battery {
+            raw[6] |= 0b0010;
+        }
+        if !self.trainer.is_empty() {
+            raw[6] |= 0b0100;
+            let start = raw.len();
+            raw.extend(&self.trainer);
+            raw.resize(start + TRAINER_SIZE, 0);
+        }
+        raw[7] |= match self.console {
+            Console::Nes => 0,
+            Console::VsSystem { .. } => 1,
+            Console::Playchoice10 => 2,
+            Console::Extended(_) => 3,
+        };
+
+        for (rom, size) in [(&self.prg_rom, prg_rom_size), (&self.chr_rom, chr_rom_size)] {
+            let start = raw.len();
+            raw.extend(rom);
+            raw.resize(start + size, 0);
+        }
+        raw
+    }
+
+    /// Writes the iNES fields of the header, returning the PRG and CHR ROM sizes it tells
+    fn ines_header(&self, raw: &mut [u8]) -> (usize, usize) {
+        let prg_pages = self.prg_rom.len().div_ceil(PRG_ROM_PAGE_SIZE);
+        let chr_pages = self.chr_rom.len().div_ceil(CHR_ROM_PAGE_SIZE);
+        raw[4] = prg_pages as u8;
+        raw[5] = chr_pages as u8;
+        let prg_ram = self.prg_ram_size + self.prg_nvram_size;
+        // zero is read as 8 KiB for compatibility, so it's kept for the most common size
+        raw[8] = match prg_ram {
+            0..=PRG_RAM_PAGE_SIZE => 0,
+            _ => prg_ram.div_ceil(PRG_RAM_PAGE_SIZE) as u8,
+        };
+        raw[9] = (self.timing == Timing::Pal) as u8;
+        (prg_pages * PRG_ROM_PAGE_SIZE, chr_pages * CHR_ROM_PAGE_SIZE)
+    }
+
+    /// Writes the NES 2.0 fields of the header, returning the PRG and CHR ROM sizes it tells
+    fn nes2_header(&self, raw: &mut [u8]) -> (usize, usize) {
+        let (prg_lsb, prg_msb, prg_rom_size) = nes2_rom_size(self.prg_rom.len(), PRG_ROM_PAGE_SIZE);
+        let (chr_lsb, chr_msb, chr_rom_size) = nes2_rom_size(self.chr_rom.len(), CHR_ROM_PAGE_SIZE);
+        raw[4] = prg_lsb;
+        raw[5] = chr_lsb;
+        raw[7] = NES2_ID;
+        raw[8] = self.submapper << 4 | (self.mapper >> 8) as u8 & 0x0f;
+        raw[9] = chr_msb << 4 | prg_msb;
+        raw[10] = nes2_ram_shift(self.prg_nvram_size) << 4 | nes2_ram_shift(self.prg_ram_size);
+        raw[11] = nes2_ram_shift(self.chr_nvram_size) << 4 | nes2_ram_shift(self.chr_ram_size);
+        raw[12] = match self.timing {
+            Timing::Ntsc => 0,
+            Timing::Pal => 1,
+            Timing::MultipleRegion => 2,
+            Timing::Dendy => 3,
+        };
+        raw[13] = match self.console {
+            Console::VsSystem { ppu, hardware } => hardware << 4 | ppu & 0x0f,
+            Console::Extended(console) => console & 0x0f,
+            _ => 0,
+        };
+        raw[15] = self.expansion_device & 0b0011_1111;
+        (prg_rom_size, chr_rom_size)
+    }
+}
+
+impl From<&Rom> for RomBuilder {
+    /// Builder of the file of a parsed ROM. UNIF and FDS images are written as NES 2.0.
+    fn from(rom: &Rom) -> Self {
+        Self {
+            format: match rom.format {
+                HeaderFormat::INes => HeaderFormat::INes,
+                _ => HeaderFormat::Nes2,
+            },
+            prg_rom: rom.prg_rom.clone(),
+            chr_rom: rom.chr_rom.clone(),
+            trainer: rom.trainer.clone(),
+            mapper: rom.mapper,
+            submapper: rom.submapper,
+            mirroring: rom.screen_mirroring,
+            battery: rom.battery,
+            prg_ram_size: rom.prg_ram_size,
+            prg_nvram_size: rom.prg_nvram_size,
+            chr_ram_size: rom.chr_ram_size,
+            chr_nvram_size: rom.chr_nvram_size,
+            timing: rom.timing,
+            console: rom.console,
+            expansion_device: rom.expansion_device,
+        }
+    }
+}
+
+/// Least significant byte and most significant nibble of a NES 2.0 ROM size, and the size they
+/// tell. Sizes that aren't a count of pages use the exponent notation when they can.
+fn nes2_rom_size(size: usize, page_size: usize) -> (u8, u8, usize) {
+    let pages = size.div_ceil(page_size);
+    if size.is_multiple_of(page_size) && pages <= MAX_NES2_PAGES {
+        return (pages as u8, (pages >> 8) as u8, size);
+    }
+    // size = 2^exponent * (multiplier * 2 + 1)
+    let exponent = size.trailing_zeros().min(0x3f);
+    let multiplier = (size >> exponent) / 2;
+    if multiplier <= 0b11 {
+        return (
+            (exponent << 2) as u8 | multiplier as u8,
+            EXPONENT_SIZE as u8,
+            size,
+        );
+    }
+    let pages = pages.min(MAX_NES2_PAGES);
+    (pages as u8, (pages >> 8) as u8, pages * page_size)
+}
+
+/// Shift count of a NES 2.0 RAM size, rounding it up to the next size it can tell
+fn nes2_ram_shift(size: usize) -> u8 {
+    if size == 0 {
+        return 0;
+    }
+    let units = size.div_ceil(RAM_SIZE_UNIT).next_power_of_two();
+    (units.trailing_zeros()).clamp(1, MAX_NES2_RAM_SHIFT) as u8
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ines() {
+        let raw = RomBuilder::new()
+            .mapper(0x42)
+            .prg_rom(vec![1; 0x4000])
+            .chr_rom(vec![2; 0x1000])
+            .mirroring(Mirroring::Vertical)
+            .battery(true)
+            .timing(Timing::Pal)
+            .build();
+        assert_eq!(raw[..10], [b'N', b'E', b'S', 0x1a, 1, 1, 0x23, 0x40, 0, 1]);
+        assert_eq!(raw.len(), HEADER_SIZE + 0x4000 + 0x2000);
+
+        let rom = Rom::new(&raw).unwrap();
+        assert_eq!(rom.chr_rom[..0x1000], [2; 0x1000]);
+        assert_eq!(rom.chr_rom[0x1000..], [0; 0x1000]);
+        assert_eq!(RomBuilder::from(&rom).build(), raw);
+    }
+
+    #[test]
+    fn large_ines() {
+        let rom = RomBuilder::new()
+            .prg_rom(vec![1; 0x4000])
+            .chr_rom(vec![2; 0x100 * CHR_ROM_PAGE_SIZE])
+            .rom()
+            .unwrap();
+        assert_eq!(rom.format, HeaderFormat::Nes2);
+        assert_eq!(rom.prg_rom.len(), 0x4000);
+        assert_eq!(rom.chr_rom.len(), 0x100 * CHR_ROM_PAGE_SIZE);
+    }
+
+    #[test]
+    fn nes2_only_mappers() {
+        let rom = RomBuilder::new().mapper(0x123).rom().unwrap();
+        assert_eq!((rom.format, rom.mapper), (HeaderFormat::Nes2, 0x123));
+        let rom = RomBuilder::new().mapper(4).submapper(1).rom().unwrap();
+        assert_eq!(rom.format, HeaderFormat::Nes2);
+        assert_eq!((rom.mapper, rom.submapper), (4, 1));
+    }
+
+    #[test]
+    fn nes2_round_trip() {
+        let builder = RomBuilder::new()
+            .format(HeaderFormat::Nes2)
+            .mapper(0x123)
+            .submapper(2)
+            .prg_rom(vec![1; 0x8000])
+            .chr_rom(vec![2; 0x2000])
+            .trainer(vec![3; TRAINER_SIZE])
+            .mirroring(Mirroring::FourScreen)
+            .prg_ram(0x2000)
+            .prg_nvram(0x800)
+            .chr_nvram(0x400)
+            .timing(Timing::Dendy)
+            .console(Console::VsSystem {
+                ppu: 3,
+                hardware: 1,
+            })
+            .expansion_device(5);
+        let rom = builder.rom().unwrap();
+        assert_eq!(rom.format, HeaderFormat::Nes2);
+        assert_eq!((rom.mapper, rom.submapper), (0x123, 2));
+        assert_eq!(rom.trainer, vec![3; TRAINER_SIZE]);
+        assert_eq!(rom.screen_mirroring, Mirroring::FourScreen);
+        assert_eq!((rom.prg_ram_size, rom.prg_nvram_size), (0x2000, 0x800));
+        assert_eq!((rom.chr_ram_size, rom.chr_nvram_size), (0, 0x400));
+        assert_eq!(rom.timing, Timing::Dendy);
+        assert_eq!(rom.expansion_device, 5);
+        assert_eq!(RomBuilder::from(&rom), builder);
+    }
+
+    #[test]
+    fn nes2_sizes() {
+        assert_eq!(nes2_rom_size(0x8000, PRG_ROM_PAGE_SIZE), (2, 0, 0x8000));
+        assert_eq!(
+            nes2_rom_size(0x100 * 0x4000, PRG_ROM_PAGE_SIZE),
+            (0, 1, 0x400000)
+        );
+        // 2^10 * 3
+        assert_eq!(
+            nes2_rom_size(3072, PRG_ROM_PAGE_SIZE),
+            (0b0010_1001, 0xf, 3072)
+        );
+        assert_eq!(nes2_rom_size(0x4001, PRG_ROM_PAGE_SIZE), (2, 0, 0x8000));
+        assert_eq!(nes2_ram_shift(0x2000), 7);
+        assert_eq!(nes2_ram_shift(0x1800), 7);
+        assert_eq!(nes2_ram_shift(1), 1);
+    }
+}
@@ -0,0 +1,332 @@
+use crate::cartridge::{Mirroring, Rom, Timing};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+
+lazy_static! {
+    static ref GAMES: HashMap<u32, Game> = parse(include_str!("database.txt"));
+}
+
+/// Comment at the start of the database, describing its columns
+const DATABASE_HEADER: &str = "\
+# Games of the NES 2.0 database (nes20db.xml), converted by the import_nes20db example.
+# Entries are keyed by the CRC32 of the PRG and CHR ROM, in this order and without header or
+# trainer. The SHA-1, when present, has to match too. RAM sizes are in bytes and the timing
+# uses the values of the NES 2.0 header: 0 NTSC, 1 PAL, 2 multiple region, 3 Dendy.
+#
+# crc32 sha1 mapper submapper mirroring battery prg_ram prg_nvram chr_ram chr_nvram timing name
+";
+
+/// Board of a game as described by the database
+#[derive(Debug, PartialEq)]
+struct Game {
+    sha1: Option<[u8; 20]>,
+    mapper: u16,
+    submapper: u8,
+    mirroring: Mirroring,
+    battery: bool,
+    prg_ram_size: usize,
+    prg_nvram_size: usize,
+    chr_ram_size: usize,
+    chr_nvram_size: usize,
+    timing: Timing,
+    name: String,
+}
+
+/// Field of the header overridden by the database
+#[derive(Debug, Clone, PartialEq)]
+pub struct Correction {
+    pub field: &'static str,
+    pub header: String,
+    pub database: String,
+}
+
+impl Display for Correction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.header, self.database)
+    }
+}
+
+/// Overrides the header fields of the ROM with the ones of its game in the database,
+/// returning the fields that differed
+pub fn correct(rom: &mut Rom) -> Vec<Correction> {
+    correct_with(&GAMES, rom)
+}
+
+fn correct_with(games: &HashMap<u32, Game>, rom: &mut Rom) -> Vec<Correction> {
+    let Some(game) = games.get(&rom.crc32()) else {
+        return Vec::new();
+    };
+    if game.sha1.is_some_and(|sha1| sha1 != rom.sha1()) {
+        return Vec::new();
+    }
+    log::info!("found {} in the database", game.name);
+
+    let mut corrections = Vec::new();
+    let c = &mut corrections;
+    fix(c, "mapper", &mut rom.mapper, game.mapper);
+    fix(c, "submapper", &mut rom.submapper, game.submapper);
+    if fix(c, "mirroring", &mut rom.screen_mirroring, game.mirroring) {
+        rom.mirroring_bit = game.mirroring == Mirroring::Vertical;
+    }
+    fix(c, "battery", &mut rom.battery, game.battery);
+    fix(c, "PRG RAM", &mut rom.prg_ram_size, game.prg_ram_size);
+    fix(c, "PRG NVRAM", &mut rom.prg_nvram_size, game.prg_nvram_size);
+    fix(c, "CHR RAM", &mut rom.chr_ram_size, game.chr_ram_size);
+    fix(c, "CHR NVRAM", &mut rom.chr_nvram_size, game.chr_nvram_size);
+    fix(c, "timing", &mut rom.timing, game.timing);
+    corrections
+}
+
+/// Sets the header field to the value of the database, recording the change when they differ
+fn fix<T: PartialEq + Debug>(
+    corrections: &mut Vec<Correction>,
+    field: &'static str,
+    header: &mut T,
+    database: T,
+) -> bool {
+    if *header == database {
+        return false;
+    }
+    corrections.push(Correction {
+        field,
+        header: format!("{header:?}"),
+        database: format!("{database:?}"),
+    });
+    *header = database;
+    true
+}
+
+/// Parses the lines of the database, skipping the comments and the malformed entries
+fn parse(database: &str) -> HashMap<u32, Game> {
+    database
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(parse_game)
+        .collect()
+}
+
+fn parse_game(line: &str) -> Option<(u32, Game)> {
+    let mut fields = line.splitn(12, ' ');
+    let mut next = || fields.next();
+    let crc32 = u32::from_str_radix(next()?, 16).ok()?;
+    let game = Game {
+        sha1: match next()? {
+            "-" => None,
+            sha1 => Some(parse_sha1(sha1)?),
+        },
+        mapper: next()?.parse().ok()?,
+        submapper: next()?.parse().ok()?,
+        mirroring: match next()? {
+            "H" => Mirroring::Horizontal,
+            "V" => Mirroring::Vertical,
+            "4" => Mirroring::FourScreen,
+            _ => return None,
+        },
+        battery: next()? == "1",
+        prg_ram_size: next()?.parse().ok()?,
+        prg_nvram_size: next()?.parse().ok()?,
+        chr_ram_size: next()?.parse().ok()?,
+        chr_nvram_size: next()?.parse().ok()?,
+        timing: match next()? {
+            "0" => Timing::Ntsc,
+            "1" => Timing::Pal,
+            "2" => Timing::MultipleRegion,
+            "3" => Timing::Dendy,
+            _ => return None,
+        },
+        name: next()?.to_string(),
+    };
+    Some((crc32, game))
+}
+
+/// Converts the games of nes20db.xml into the lines of the database. The games with a trainer
+/// or other ROMs, whose hashes don't cover only the PRG and CHR ROM, and the ones with a
+/// mirroring the database can't tell are skipped.
+pub fn import_nes20db(xml: &str) -> String {
+    let games = xml
+        .split("<game>")
+        .skip(1)
+        .filter_map(|game| game.split("</game>").next())
+        .filter_map(import_game);
+    let mut database = DATABASE_HEADER.to_string();
+    for game in games {
+        database.push_str(&game);
+        database.push('\n');
+    }
+    database
+}
+
+/*
+   Game of nes20db.xml, the RAM elements only present when the board has them
+
+   <game>
+     <!-- Super Mario Bros. (World).nes -->
+     <rom size="40960" crc32="3337EC46" sha1="EA343F4E..."/>
+     <prgrom size="32768" crc32="5CF548D3" sha1="..." sum16="..."/>
+     <chrrom size="8192" crc32="867B51AD" sha1="..." sum16="..."/>
+     <prgram size="8192"/>
+     <pcb mapper="0" submapper="0" mirroring="V" battery="0"/>
+     <console type="0" region="0"/>
+   </game>
+*/
+fn import_game(game: &str) -> Option<String> {
+    if element(game, "trainer").is_some() || element(game, "miscrom").is_some() {
+        return None;
+    }
+    let rom = element(game, "rom")?;
+    let pcb = element(game, "pcb")?;
+    let size = |name| element(game, name).and_then(|ram| attribute(ram, "size"));
+    let region = element(game, "console").and_then(|console| attribute(console, "region"));
+    let name = game.split("<!--").nth(1)?.split("-->").next()?.trim();
+    let name = name.rsplit(['\\', '/']).next()?;
+
+    let line = format!(
+        "{} {} {} {} {} {} {} {} {} {} {} {}",
+        attribute(rom, "crc32")?.to_ascii_lowercase(),
+        attribute(rom, "sha1").map_or("-".into(), str::to_ascii_lowercase),
+        attribute(pcb, "mapper")?,
+        attribute(pcb, "submapper").unwrap_or("0"),
+        attribute(pcb, "mirroring")?,
+        attribute(pcb, "battery").unwrap_or("0"),
+        size("prgram").unwrap_or("0"),
+        size("prgnvram").unwrap_or("0"),
+        size("chrram").unwrap_or("0"),
+        size("chrnvram").unwrap_or("0"),
+        region.unwrap_or("0"),
+        name.strip_suffix(".nes").unwrap_or(name),
+    );
+    // only the lines the database can read back
+    parse_game(&line).map(|_| line)
+}
+
+/// Attributes of the first element with the tag
+fn element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{tag} "))? + tag.len() + 1;
+    xml[start..].split('>').next()
+}
+
+fn attribute<'a>(element: &'a str, name: &str) -> Option<&'a str> {
+    let start = element.find(&format!(" {name}=\""))? + name.len() + 3;
+    element[start..].split('"').next()
+}
+
+fn parse_sha1(text: &str) -> Option<[u8; 20]> {
+    if text.len() != 40 {
+        return None;
+    }
+    let mut sha1 = [0; 20];
+    for (i, byte) in sha1.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(text.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(sha1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rom() -> Rom {
+        Rom {
+            prg_rom: vec![1; 0x4000],
+            chr_rom: vec![2; 0x2000],
+            mapper: 4,
+            screen_mirroring: Mirroring::Horizontal,
+            prg_ram_size: 0x2000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn bundled_database() {
+        let smb = GAMES.get(&0x3337ec46).unwrap();
+        assert_eq!(smb.name, "Super Mario Bros. (World)");
+        assert_eq!(smb.mirroring, Mirroring::Vertical);
+        assert!(correct(&mut rom()).is_empty());
+    }
+
+    #[test]
+    fn corrections() {
+        let mut rom = rom();
+        let line = format!("{:08x} - 1 0 V 1 0 8192 0 0 1 Test", rom.crc32());
+        let games = parse(&line);
+        let corrections = correct_with(&games, &mut rom);
+        assert_eq!(corrections.len(), 6);
+        assert_eq!(corrections[0].to_string(), "mapper: 4 -> 1");
+        assert_eq!(rom.mapper, 1);
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+        assert!(rom.mirroring_bit);
+        assert!(rom.battery);
+        assert_eq!((rom.prg_ram_size, rom.prg_nvram_size), (0, 0x2000));
+        assert_eq!(rom.timing, Timing::Pal);
+
+        // a match of the CRC32 with a different SHA-1 is a different game
+        let mut rom = self::rom();
+        let line = format!(
+            "{:08x} {} 1 0 V 1 0 8192 0 0 1 Test",
+            rom.crc32(),
+            "00".repeat(20)
+        );
+        assert!(correct_with(&parse(&line), &mut rom).is_empty());
+        assert_eq!(rom.mapper, 4);
+    }
+
+    #[test]
+    fn nes20db() {
+        let rom = rom();
+        let xml = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<nes20db date="2024-01-01">
+<game>
+  <!-- Licensed\Super Mario Bros. (World).nes -->
+  <rom size="40960" crc32="3337EC46" sha1="EA343F4E445A9050D4B4FBAC2C77D0693B1D0922"/>
+  <prgrom size="32768" crc32="5CF548D3"/>
+  <chrrom size="8192" crc32="867B51AD"/>
+  <pcb mapper="0" submapper="0" mirroring="V" battery="0"/>
+  <console type="0" region="0"/>
+</game>
+<game>
+  <!-- Test (Europe).nes -->
+  <rom size="24576" crc32="{:08X}"/>
+  <prgnvram size="8192"/>
+  <pcb mapper="1" submapper="0" mirroring="V" battery="1"/>
+  <console type="0" region="1"/>
+</game>
+<game>
+  <!-- Trainer.nes -->
+  <rom size="25088" crc32="00000001"/>
+  <trainer size="512" crc32="00000002"/>
+  <pcb mapper="0" submapper="0" mirroring="H" battery="0"/>
+</game>
+<game>
+  <!-- Mapper controlled mirroring.nes -->
+  <rom size="24576" crc32="00000003"/>
+  <pcb mapper="0" submapper="0" mirroring="1" battery="0"/>
+</game>
+</nes20db>"#,
+            rom.crc32()
+        );
+        let database = import_nes20db(&xml);
+        assert!(database.starts_with(DATABASE_HEADER));
+        let lines: Vec<&str> = database[DATABASE_HEADER.len()..].lines().collect();
+        assert_eq!(lines.len(), 2);
+        // the bundled entry is the same line the importer writes
+        assert!(include_str!("database.txt").contains(lines[0]));
+        assert_eq!(
+            lines[1],
+            format!("{:08x} - 1 0 V 1 0 8192 0 0 1 Test (Europe)", rom.crc32())
+        );
+
+        let mut rom = rom;
+        let corrections = correct_with(&parse(&database), &mut rom);
+        assert_eq!(corrections.len(), 6);
+        assert_eq!((rom.mapper, rom.prg_nvram_size), (1, 0x2000));
+    }
+
+    #[test]
+    fn malformed_entries() {
+        assert!(parse("# comment\n\n0000000g - 0 0 H 0 0 0 0 0 0 Bad CRC").is_empty());
+        assert!(parse("00000000 - 0 0 X 0 0 0 0 0 0 Bad mirroring").is_empty());
+        assert_eq!(parse("00000000 - 0 0 H 0 0 0 0 0 0 Good").len(), 1);
+    }
+}
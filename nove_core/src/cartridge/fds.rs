@@ -0,0 +1,99 @@
+use crate::cartridge::{HeaderFormat, Rom, CHR_ROM_PAGE_SIZE};
+use crate::exception::NoveError;
+use crate::Program;
+
+/// Header of the fwNES images, followed by the number of sides and padding
+pub const FDS_TAG: &[u8] = b"FDS\x1a";
+const FDS_HEADER_SIZE: usize = 16;
+/// Disk info block at the start of every side of the headerless images
+pub const DISK_INFO: &[u8] = b"\x01*NINTENDO-HVC*";
+/// Data of a disk side in the images, without the gaps and CRCs of the real disks
+pub const SIDE_SIZE: usize = 65500;
+pub const BIOS_SIZE: usize = 0x2000;
+const PRG_RAM_SIZE: usize = 0x8000;
+/// Mapper number reserved by iNES for the Famicom Disk System
+pub const FDS_MAPPER: u16 = 20;
+
+/// Tells if a file is a Famicom Disk System image, with or without header
+pub fn is_fds_image(raw: &[u8]) -> bool {
+    raw.starts_with(FDS_TAG) || raw.starts_with(DISK_INFO)
+}
+
+impl Rom {
+    /// Builds the ROM of a Famicom Disk System image. The RAM adapter runs the disks with the
+    /// BIOS of the console, which is the PRG ROM of the cartridge.
+    pub fn fds(image: &[u8], bios: &[u8]) -> Result<Rom, NoveError> {
+        if bios.len() != BIOS_SIZE {
+            return Err(NoveError::WrongFdsBios);
+        }
+        let sides = if image.starts_with(FDS_TAG) {
+            &image[FDS_HEADER_SIZE.min(image.len())..]
+        } else {
+            image
+        };
+        if sides.is_empty() || !sides.len().is_multiple_of(SIDE_SIZE) {
+            return Err(NoveError::WrongRomFormat);
+        }
+        let disk_sides: Vec<Program> = sides.chunks(SIDE_SIZE).map(<[u8]>::to_vec).collect();
+        if !disk_sides.iter().all(|side| side.starts_with(DISK_INFO)) {
+            return Err(NoveError::WrongRomFormat);
+        }
+
+        Ok(Rom {
+            prg_rom: bios.to_vec(),
+            format: HeaderFormat::Fds,
+            mapper: FDS_MAPPER,
+            prg_ram_size: PRG_RAM_SIZE,
+            chr_ram_size: CHR_ROM_PAGE_SIZE,
+            disk_sides,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn side() -> Vec<u8> {
+        let mut side = DISK_INFO.to_vec();
+        side.resize(SIDE_SIZE, 0);
+        side
+    }
+
+    #[test]
+    fn fds_image() {
+        let mut image = FDS_TAG.to_vec();
+        image.push(2);
+        image.resize(FDS_HEADER_SIZE, 0);
+        image.extend(side());
+        image.extend(side());
+        assert!(is_fds_image(&image));
+
+        let rom = Rom::fds(&image, &[0; BIOS_SIZE]).unwrap();
+        assert_eq!(rom.format, HeaderFormat::Fds);
+        assert_eq!(rom.mapper, FDS_MAPPER);
+        assert_eq!(rom.disk_sides.len(), 2);
+        assert_eq!(rom.prg_ram_size, PRG_RAM_SIZE);
+        assert_eq!(
+            Rom::fds(&side(), &[0; BIOS_SIZE]).unwrap().disk_sides.len(),
+            1
+        );
+    }
+
+    #[test]
+    fn wrong_images() {
+        assert_eq!(
+            Rom::fds(&side(), &[0; 0x1000]),
+            Err(NoveError::WrongFdsBios)
+        );
+        assert_eq!(
+            Rom::fds(&side()[..0x1000], &[0; BIOS_SIZE]),
+            Err(NoveError::WrongRomFormat)
+        );
+        assert_eq!(
+            Rom::fds(&vec![0; SIDE_SIZE], &[0; BIOS_SIZE]),
+            Err(NoveError::WrongRomFormat)
+        );
+    }
+}
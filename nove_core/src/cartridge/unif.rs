@@ -0,0 +1,249 @@
+use crate::cartridge::{HeaderFormat, Mirroring, Rom, Timing};
+use crate::cartridge::{CHR_ROM_PAGE_SIZE, PRG_RAM_PAGE_SIZE};
+use crate::exception::NoveError;
+
+pub const MAGIC: &[u8] = b"UNIF";
+/// Magic number, revision and padding
+const HEADER_SIZE: usize = 32;
+/// Identifier and length of each chunk
+const CHUNK_HEADER_SIZE: usize = 8;
+/// Prefixes of the board names telling who made them, not needed to pick the mapper
+const BOARD_PREFIXES: [&str; 5] = ["NES-", "HVC-", "UNL-", "BTL-", "BMC-"];
+
+/// Boards of the UNIF files with the mapper and submapper implementing them, and their PRG RAM
+/// when they have more than the usual 8 KiB
+const BOARDS: &[(&str, u16, u8, usize)] = &[
+    ("NROM", 0, 0, 0),
+    ("NROM-128", 0, 0, 0),
+    ("NROM-256", 0, 0, 0),
+    ("SAROM", 1, 0, 0),
+    ("SBROM", 1, 0, 0),
+    ("SCROM", 1, 0, 0),
+    ("SEROM", 1, 0, 0),
+    ("SGROM", 1, 0, 0),
+    ("SKROM", 1, 0, 0),
+    ("SLROM", 1, 0, 0),
+    ("SL1ROM", 1, 0, 0),
+    ("SNROM", 1, 0, 0),
+    ("SOROM", 1, 0, 0x4000),
+    ("SUROM", 1, 0, 0),
+    ("SXROM", 1, 0, 0x8000),
+    ("UNROM", 2, 0, 0),
+    ("UOROM", 2, 0, 0),
+    ("CNROM", 3, 0, 0),
+    ("TBROM", 4, 0, 0),
+    ("TEROM", 4, 0, 0),
+    ("TFROM", 4, 0, 0),
+    ("TGROM", 4, 0, 0),
+    ("TKROM", 4, 0, 0),
+    ("TLROM", 4, 0, 0),
+    ("TNROM", 4, 0, 0),
+    ("TSROM", 4, 0, 0),
+    ("TVROM", 4, 0, 0),
+    ("HKROM", 4, 1, 0),
+    ("EKROM", 5, 0, 0),
+    ("ELROM", 5, 0, 0),
+    ("ETROM", 5, 0, 0x4000),
+    ("EWROM", 5, 0, 0x8000),
+    ("AMROM", 7, 0, 0),
+    ("ANROM", 7, 0, 0),
+    ("AOROM", 7, 0, 0),
+    ("PNROM", 9, 0, 0),
+    ("FJROM", 10, 0, 0),
+    ("FKROM", 10, 0, 0),
+    ("UNROM-512-8", 30, 0, 0),
+    ("UNROM-512-16", 30, 0, 0),
+    ("UNROM-512-32", 30, 0, 0),
+    ("BNROM", 34, 0, 0),
+    ("GNROM", 66, 0, 0),
+    ("MHROM", 66, 0, 0),
+    ("JLROM", 69, 0, 0),
+    ("JSROM", 69, 0, 0),
+];
+
+/*
+   UNIF file, a 32 bytes header followed by chunks of
+
+   4 bytes  identifier
+   4 bytes  length, little endian
+   length   data
+
+   MAPR       board name, null terminated
+   PRG0-PRGF  PRG ROM, concatenated in the order of the chunks
+   CHR0-CHRF  CHR ROM, concatenated in the order of the chunks
+   MIRR       0 horizontal, 1 vertical, 2-3 single screen, 4 four screen, 5 by the mapper
+   BATR       present when the PRG RAM has a battery
+   TVCI       0 NTSC, 1 PAL, 2 both
+*/
+pub fn parse(raw: &[u8]) -> Result<Rom, NoveError> {
+    if raw.len() < HEADER_SIZE {
+        return Err(NoveError::WrongRomFormat);
+    }
+    let mut prg_chunks: [&[u8]; 16] = Default::default();
+    let mut chr_chunks: [&[u8]; 16] = Default::default();
+    let mut board = None;
+    let mut rom = Rom {
+        format: HeaderFormat::Unif,
+        ..Default::default()
+    };
+
+    let mut pos = HEADER_SIZE;
+    while pos < raw.len() {
+        let header = raw
+            .get(pos..pos + CHUNK_HEADER_SIZE)
+            .ok_or(NoveError::WrongRomFormat)?;
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        pos += CHUNK_HEADER_SIZE;
+        let data = pos
+            .checked_add(len)
+            .and_then(|end| raw.get(pos..end))
+            .ok_or(NoveError::WrongRomFormat)?;
+        pos += len;
+
+        match &header[..4] {
+            b"MAPR" => {
+                let name = data.split(|&byte| byte == 0).next().unwrap_or_default();
+                board = Some(String::from_utf8_lossy(name).into_owned());
+            }
+            [b'P', b'R', b'G', n] => {
+                if let Some(i) = chunk_number(*n) {
+                    prg_chunks[i] = data;
+                }
+            }
+            [b'C', b'H', b'R', n] => {
+                if let Some(i) = chunk_number(*n) {
+                    chr_chunks[i] = data;
+                }
+            }
+            b"MIRR" => {
+                rom.screen_mirroring = match data.first() {
+                    Some(1) => Mirroring::Vertical,
+                    Some(2) => Mirroring::SingleScreenLower,
+                    Some(3) => Mirroring::SingleScreenUpper,
+                    Some(4) => Mirroring::FourScreen,
+                    _ => Mirroring::Horizontal,
+                };
+                rom.mirroring_bit = rom.screen_mirroring == Mirroring::Vertical;
+            }
+            b"BATR" => rom.battery = true,
+            b"TVCI" => {
+                rom.timing = match data.first() {
+                    Some(1) => Timing::Pal,
+                    Some(2) => Timing::MultipleRegion,
+                    _ => Timing::Ntsc,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let board = board.ok_or(NoveError::WrongRomFormat)?;
+    let (mapper, submapper, prg_ram_size) =
+        lookup_board(&board).ok_or(NoveError::UnsupportedBoard(board))?;
+    rom.mapper = mapper;
+    rom.submapper = submapper;
+    rom.prg_rom = prg_chunks.concat();
+    rom.chr_rom = chr_chunks.concat();
+    if rom.battery {
+        rom.prg_nvram_size = prg_ram_size;
+    } else {
+        rom.prg_ram_size = prg_ram_size;
+    }
+    if rom.chr_rom.is_empty() {
+        rom.chr_ram_size = CHR_ROM_PAGE_SIZE;
+    }
+    Ok(rom)
+}
+
+/// Mapper, submapper and PRG RAM size of a board
+fn lookup_board(name: &str) -> Option<(u16, u8, usize)> {
+    let name = BOARD_PREFIXES
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name);
+    BOARDS
+        .iter()
+        .find(|(board, ..)| board.eq_ignore_ascii_case(name))
+        .map(|&(_, mapper, submapper, prg_ram_size)| {
+            (mapper, submapper, prg_ram_size.max(PRG_RAM_PAGE_SIZE))
+        })
+}
+
+/// Number of the PRG and CHR chunks, an hexadecimal digit
+fn chunk_number(digit: u8) -> Option<usize> {
+    match digit {
+        b'0'..=b'9' => Some((digit - b'0') as usize),
+        b'A'..=b'F' => Some((digit - b'A') as usize + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn unif(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut raw = MAGIC.to_vec();
+        raw.extend(7u32.to_le_bytes());
+        raw.resize(HEADER_SIZE, 0);
+        for (id, data) in chunks {
+            raw.extend(*id);
+            raw.extend((data.len() as u32).to_le_bytes());
+            raw.extend(*data);
+        }
+        raw
+    }
+
+    #[test]
+    fn chunks() {
+        let raw = unif(&[
+            (b"MAPR", b"NES-SXROM\0"),
+            (b"PRG1", &[2; 0x10]),
+            (b"PRG0", &[1; 0x10]),
+            (b"CHR0", &[3; 0x20]),
+            (b"MIRR", &[1]),
+            (b"BATR", &[1]),
+            (b"TVCI", &[1]),
+            (b"READ", b"unknown chunks are skipped"),
+        ]);
+        let rom = parse(&raw).unwrap();
+        assert_eq!(rom.format, HeaderFormat::Unif);
+        assert_eq!(rom.mapper, 1);
+        assert_eq!(rom.prg_rom[..0x10], [1; 0x10]);
+        assert_eq!(rom.prg_rom[0x10..], [2; 0x10]);
+        assert_eq!(rom.chr_rom, vec![3; 0x20]);
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+        assert!(rom.battery);
+        assert_eq!((rom.prg_ram_size, rom.prg_nvram_size), (0, 0x8000));
+        assert_eq!(rom.chr_ram_size, 0);
+        assert_eq!(rom.timing, Timing::Pal);
+    }
+
+    #[test]
+    fn boards() {
+        assert_eq!(lookup_board("HVC-HKROM"), Some((4, 1, PRG_RAM_PAGE_SIZE)));
+        assert_eq!(
+            lookup_board("UNROM-512-32"),
+            Some((30, 0, PRG_RAM_PAGE_SIZE))
+        );
+
+        let raw = unif(&[(b"MAPR", b"UNL-UNKNOWN\0"), (b"PRG0", &[0; 0x10])]);
+        assert_eq!(
+            parse(&raw),
+            Err(NoveError::UnsupportedBoard("UNL-UNKNOWN".to_string()))
+        );
+        let raw = unif(&[(b"PRG0", &[0; 0x10])]);
+        assert_eq!(parse(&raw), Err(NoveError::WrongRomFormat));
+        let mut raw = unif(&[(b"MAPR", b"NES-NROM-256\0")]);
+        raw.truncate(raw.len() - 2);
+        assert_eq!(parse(&raw), Err(NoveError::WrongRomFormat));
+    }
+
+    #[test]
+    fn load_rom() {
+        let raw = unif(&[(b"MAPR", b"NES-UNROM\0"), (b"PRG0", &[0; 0x8000])]);
+        let rom = Rom::new(&raw).unwrap();
+        assert_eq!(rom.chr_ram_size, CHR_ROM_PAGE_SIZE);
+        assert!(crate::mapper::load(rom).is_ok());
+    }
+}
@@ -81,9 +81,10 @@ macro_rules! displace {
         $core.update_zn(val);
     }};
     ($core:expr, $displacement:expr, mem:$addr:expr) => {{
-        let val = $core.memory.read($addr);
-        let (val, carry) = $displacement.displace(val);
+        let prev = $core.memory.read($addr);
+        let (val, carry) = $displacement.displace(prev);
         $core.ps.set_bit(StatusFlag::Carry, carry);
+        $core.memory.write($addr, prev);
         $core.memory.write($addr, val);
         $core.update_zn(val);
     }};
@@ -146,8 +147,7 @@ impl<M: Memory> NoveCore<M> {
             CPX => compare!(self, x, addr),
             CPY => compare!(self, y, addr),
             DCP => {
-                self.memory
-                    .write(addr, self.memory.read(addr).wrapping_sub(1));
+                self.memory.update(addr, |prev| prev.wrapping_sub(1));
                 compare!(self, a, addr);
             }
             DEC => update_mem!(self, addr, wrapping_sub),
@@ -314,13 +314,20 @@ impl<M: Memory> NoveCore<M> {
     }
 
     fn handle_interrupt(&mut self) -> InterruptFlag {
-        let interruption = self.interruption.replace(InterruptFlag::None);
-        if interruption == InterruptFlag::NMI {
+        let mut interruption = self.interruption.replace(InterruptFlag::None);
+        // the IRQ line is level triggered, it's serviced while held and not masked
+        if interruption == InterruptFlag::None
+            && self.memory.irq()
+            && self.ps.is_lowered(StatusFlag::Interrupt)
+        {
+            interruption = InterruptFlag::IRQ;
+        }
+        if let InterruptFlag::NMI | InterruptFlag::IRQ = interruption {
             self.stack_push_u16(self.pc);
             self.stack_push(interruption.mask(self.ps.get_for_push()));
 
             self.ps.raise(StatusFlag::Interrupt);
-            self.memory.tick(2);
+            self.memory.tick(interruption.cycles());
             self.pc = self.memory.read_u16(interruption.addr());
         }
         interruption
@@ -348,7 +355,9 @@ impl<M: Memory> NoveCore<M> {
     }
 
     fn isb(&mut self, addr: u16) {
-        let result = self.memory.read(addr).wrapping_add(1);
+        let prev = self.memory.read(addr);
+        let result = prev.wrapping_add(1);
+        self.memory.write(addr, prev);
         self.memory.write(addr, result);
         let diff = self.sbc(result);
         op_and_assign!(self, a.assign, diff);
@@ -452,23 +461,44 @@ fn page_crossed(left: u16, right: u16) -> bool {
 }
 
 impl NesNoveCore {
-    pub fn new(rom: Rom) -> Self {
+    pub fn new(rom: Rom) -> Result<Self, NoveError> {
         let interruption = Rc::new(RefCell::new(InterruptFlag::None));
-        Self {
+        Ok(Self {
             pc: Default::default(),
             sp: Default::default(),
             a: Default::default(),
             x: Default::default(),
             y: Default::default(),
             ps: Default::default(),
-            memory: Bus::new(rom, interruption.clone()),
+            memory: Bus::new(rom, interruption.clone())?,
             interruption,
-        }
+        })
     }
 
     pub fn render(&self) -> Frame {
         self.memory.ppu.borrow().render()
     }
+
+    /// Takes the audio samples produced since the last call, at `SAMPLE_RATE`
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.memory.take_audio_samples()
+    }
+
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        self.memory.save_data()
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        self.memory.load_save_data(data)
+    }
+
+    pub fn disk_sides(&self) -> usize {
+        self.memory.disk_sides()
+    }
+
+    pub fn insert_disk(&mut self, side: Option<usize>) {
+        self.memory.insert_disk(side)
+    }
 }
 
 impl Core6502 {
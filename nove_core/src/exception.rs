@@ -2,8 +2,22 @@ use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
 pub enum NoveError {
-    #[error("iNES is the only supported format")]
+    #[error("only iNES, NES 2.0 and UNIF ROMs are supported")]
     WrongRomFormat,
+    #[error("unsupported UNIF board: {0}")]
+    UnsupportedBoard(String),
+    #[error("Famicom Disk System images need the BIOS of the console")]
+    FdsBiosRequired,
+    #[error("the Famicom Disk System BIOS must be 8 KiB")]
+    WrongFdsBios,
     #[error("wrong op_code: {0:02x}")]
     WrongOpCode(u8),
+    #[error("unsupported mapper: {0}")]
+    UnsupportedMapper(u16),
+    #[error("only IPS, UPS and BPS patches are supported")]
+    WrongPatchFormat,
+    #[error("checksum of the {0} doesn't match the patch")]
+    PatchChecksum(&'static str),
+    #[error("IPS patches can't address images over 16 MiB")]
+    PatchTooLarge,
 }
@@ -5,6 +5,7 @@ pub enum InterruptFlag {
     #[default]
     None,
     NMI,
+    IRQ,
     BRK, // todo handle
 }
 
@@ -12,6 +13,7 @@ impl InterruptFlag {
     pub fn cycles(&self) -> u8 {
         match self {
             InterruptFlag::NMI => 2,
+            InterruptFlag::IRQ => 7,
             _ => panic!("requesting cycles of no flag"),
         }
     }
@@ -19,6 +21,7 @@ impl InterruptFlag {
     pub fn addr(&self) -> u16 {
         match self {
             InterruptFlag::NMI => 0xfffa,
+            InterruptFlag::IRQ => 0xfffe,
             _ => panic!("requesting address of no flag"),
         }
     }
@@ -27,7 +30,7 @@ impl InterruptFlag {
         let brk: u8 = StatusFlag::Break.into();
         let one: u8 = StatusFlag::One.into();
         match self {
-            InterruptFlag::NMI => val & !brk | one,
+            InterruptFlag::NMI | InterruptFlag::IRQ => val & !brk | one,
             _ => panic!("requesting mask of no flag"),
         }
     }
@@ -6,7 +6,9 @@ mod exception;
 mod flag_register;
 pub(crate) mod instruction;
 pub mod interrupt;
+pub mod mapper;
 pub mod memory;
+pub mod patch;
 mod ppu;
 mod register;
 
@@ -14,3 +16,5 @@ pub type Program = Vec<u8>;
 
 pub const WIDTH: u32 = 256;
 pub const HEIGHT: u32 = 240;
+/// Samples per second of the audio output
+pub const SAMPLE_RATE: u32 = 44_100;
@@ -0,0 +1,309 @@
+use crate::cartridge::{Cartridge, Mirroring, Rom};
+use crate::exception::NoveError;
+use axrom::Axrom;
+use bnrom::Bnrom;
+use cnrom::Cnrom;
+use fcg::Fcg;
+use fds::Fds;
+use fme7::Fme7;
+use four_screen::FourScreen;
+use gxrom::Gxrom;
+use log::warn;
+use mmc1::Mmc1;
+use mmc2::{Chip, Mmc2};
+use mmc3::{Mmc3, Revision};
+use mmc5::Mmc5;
+use namco163::Namco163;
+use nrom::Nrom;
+use std::cell::RefCell;
+use std::rc::Rc;
+use unrom512::Unrom512;
+use uxrom::Uxrom;
+use vrc2::Vrc2;
+use vrc6::Vrc6;
+
+mod axrom;
+mod bnrom;
+mod chr;
+mod cnrom;
+mod eeprom;
+mod fcg;
+mod fds;
+mod fds_audio;
+mod fds_drive;
+mod fme7;
+mod four_screen;
+mod gxrom;
+mod mmc1;
+mod mmc2;
+mod mmc3;
+mod mmc5;
+mod namco163;
+mod nrom;
+mod prg_ram;
+mod sunsoft5b;
+mod unrom512;
+mod uxrom;
+mod vrc2;
+mod vrc6;
+mod vrc_irq;
+
+const PRG_BANK_8K: usize = 0x2000;
+const PRG_BANK_16K: usize = 0x4000;
+const PRG_BANK_32K: usize = 0x8000;
+const CHR_BANK_4K: usize = 0x1000;
+const CHR_BANK_8K: usize = 0x2000;
+
+const PRG_RAM_START: u16 = 0x6000;
+/// Address of PRG RAM where the trainers are loaded
+const TRAINER_START: u16 = 0x7000;
+
+/// Output of a step of volume of the pulse channels of the APU, in the linear approximation
+/// of its mixer. Expansion audio is scaled relative to it.
+const APU_PULSE_STEP: f32 = 0.00752;
+
+/// Hardware of the cartridge in charge of decoding the CPU and PPU addresses into its memories
+pub trait Mapper {
+    /// Reads from the cartridge space of the CPU ($4020-$FFFF), returning `None` when the
+    /// cartridge doesn't drive the data bus for that address. Reading some registers has side
+    /// effects, like acknowledging an IRQ.
+    fn read_prg(&mut self, addr: u16) -> Option<u8>;
+
+    /// Writes to the cartridge space of the CPU, usually to configure the mapper registers
+    fn write_prg(&mut self, addr: u16, value: u8);
+
+    /// Reads from the pattern tables of the PPU ($0000-$1FFF), for the background fetches and
+    /// the PPUDATA accesses. Sprite fetches only reach `notify_ppu_addr`, where boards switching
+    /// banks based on the tiles being fetched have to look.
+    fn read_chr(&mut self, addr: u16) -> u8;
+
+    /// Writes to the pattern tables of the PPU, ignored by cartridges with CHR ROM
+    fn write_chr(&mut self, _addr: u16, _value: u8) {}
+
+    /// Current layout of the nametables
+    fn mirroring(&self) -> Mirroring;
+
+    /// Reads from the nametables of the PPU ($2000-$2FFF). The cartridge decides where they are
+    /// mapped, usually on the `vram` of the console following the mirroring, but boards can
+    /// supply their own bytes.
+    fn read_nametable(&self, addr: u16, vram: &[u8]) -> u8 {
+        vram[self.mirroring().vram_addr(addr)]
+    }
+
+    /// Writes to the nametables of the PPU
+    fn write_nametable(&mut self, addr: u16, value: u8, vram: &mut [u8]) {
+        vram[self.mirroring().vram_addr(addr)] = value;
+    }
+
+    /// Notifies the CPU cycles elapsed since the last tick
+    fn tick(&mut self, _cycles: u8) {}
+
+    /// Notifies each address the PPU puts on its bus, for boards watching the PPU address lines
+    fn notify_ppu_addr(&mut self, _addr: u16) {}
+
+    /// Notifies the CPU writes outside the cartridge space, for boards snooping other registers
+    fn notify_cpu_write(&mut self, _addr: u16, _value: u8) {}
+
+    /// Level of the IRQ line of the cartridge
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Memory kept by the battery of the cartridge, `None` for boards without one
+    fn save_data(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Restores the battery backed memory from the data of a previous `save_data`
+    fn load_save_data(&mut self, _data: &[u8]) {}
+
+    /// Current output of the expansion audio of the cartridge, on the scale of the APU mixer
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+
+    /// Number of disk sides of the Famicom Disk System, zero for the rest of cartridges
+    fn disk_sides(&self) -> usize {
+        0
+    }
+
+    /// Ejects the disk and inserts the given side, if any, once the BIOS notices the change
+    fn insert_disk(&mut self, _side: Option<usize>) {}
+}
+
+/// Builds the mapper indicated by the header of the ROM
+pub fn load(rom: Rom) -> Result<Cartridge, NoveError> {
+    // UNROM 512 keeps its four nametables in its own CHR RAM
+    let four_screen = rom.screen_mirroring == Mirroring::FourScreen && rom.mapper != 30;
+    Ok(match rom.mapper {
+        0 => cartridge(Nrom::new(rom), four_screen),
+        1 => cartridge(Mmc1::new(rom), four_screen),
+        2 => cartridge(Uxrom::new(rom), four_screen),
+        3 => cartridge(Cnrom::new(rom), four_screen),
+        4 => {
+            let revision = match rom.submapper {
+                1 => Revision::Mmc6,
+                4 => Revision::Mmc3A,
+                _ => Revision::Mmc3B,
+            };
+            cartridge(Mmc3::new(rom, revision), four_screen)
+        }
+        5 => cartridge(Mmc5::new(rom), four_screen),
+        7 => cartridge(Axrom::new(rom), four_screen),
+        9 => cartridge(Mmc2::new(rom, Chip::Mmc2), four_screen),
+        10 => cartridge(Mmc2::new(rom, Chip::Mmc4), four_screen),
+        16 | 153 | 157 | 159 => cartridge(Fcg::new(rom), four_screen),
+        19 => cartridge(Namco163::new(rom), four_screen),
+        20 => cartridge(Fds::new(rom), four_screen),
+        21 | 22 | 23 | 25 => cartridge(Vrc2::new(rom), four_screen),
+        24 | 26 => cartridge(Vrc6::new(rom), four_screen),
+        30 => cartridge(Unrom512::new(rom), four_screen),
+        34 => cartridge(Bnrom::new(rom), four_screen),
+        66 => cartridge(Gxrom::new(rom), four_screen),
+        69 => cartridge(Fme7::new(rom), four_screen),
+        mapper => return Err(NoveError::UnsupportedMapper(mapper)),
+    })
+}
+
+/// Shares the mapper, adding the nametables of the cartridge to four-screen boards
+fn cartridge<M: Mapper + 'static>(mapper: M, four_screen: bool) -> Cartridge {
+    if four_screen {
+        Rc::new(RefCell::new(FourScreen::new(mapper)))
+    } else {
+        Rc::new(RefCell::new(mapper))
+    }
+}
+
+/// Index in `memory` of the `addr` inside the selected bank. Both the bank number and the index
+/// wrap around the memory size, like the unconnected address lines of smaller boards do.
+fn bank_addr(memory: &[u8], bank: usize, bank_size: usize, addr: u16) -> usize {
+    (bank * bank_size + (addr as usize % bank_size)) % memory.len()
+}
+
+/// Number of the bank `n` banks before the end of the memory, the last one being 1. Memories
+/// with fewer banks wrap around, as the bank register lines that aren't connected do.
+fn bank_from_end(memory: &[u8], bank_size: usize, n: usize) -> usize {
+    let banks = (memory.len() / bank_size).max(1);
+    (banks - n % banks) % banks
+}
+
+/// Reads the byte of `addr` inside the selected bank, missing memories read as zero
+fn read_bank(memory: &[u8], bank: usize, bank_size: usize, addr: u16) -> u8 {
+    if memory.is_empty() {
+        return 0;
+    }
+    memory[bank_addr(memory, bank, bank_size, addr)]
+}
+
+/// Allocates the PRG RAM of the board, loaded with the trainer of the ROM at $7000-$71FF. The
+/// trainer is written where $7000 lands when the RAM is mirrored across $6000-$7FFF.
+fn new_prg_ram(rom: &Rom, size: usize) -> Vec<u8> {
+    let mut ram = vec![0; size];
+    if rom.trainer.is_empty() {
+        return ram;
+    }
+    let start = (TRAINER_START - PRG_RAM_START) as usize % size.max(1);
+    match ram.get_mut(start..start + rom.trainer.len()) {
+        Some(memory) => memory.copy_from_slice(&rom.trainer),
+        None => warn!("the trainer doesn't fit in {size} bytes of PRG RAM"),
+    }
+    ram
+}
+
+/// Restores a memory from a save, ignoring the bytes that don't fit in it
+fn load_memory(memory: &mut [u8], data: &[u8]) {
+    let len = memory.len().min(data.len());
+    memory[..len].copy_from_slice(&data[..len]);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bank_wrapping() {
+        let memory: Vec<u8> = (0..4).flat_map(|i| vec![i; 0x10]).collect();
+        assert_eq!(read_bank(&memory, 1, 0x10, 0x8005), 1);
+        assert_eq!(read_bank(&memory, 6, 0x10, 0x8005), 2);
+        assert_eq!(read_bank(&[], 1, 0x10, 0x8005), 0);
+        assert_eq!(bank_from_end(&memory, 0x10, 1), 3);
+        assert_eq!(bank_from_end(&memory, 0x10, 6), 2);
+        assert_eq!(bank_from_end(&memory, 0x40, 2), 0);
+        assert_eq!(bank_from_end(&[], 0x10, 1), 0);
+    }
+
+    #[test]
+    fn trainer() {
+        let rom = Rom {
+            trainer: vec![0xaa; 0x200],
+            ..Default::default()
+        };
+        let ram = new_prg_ram(&rom, PRG_BANK_8K);
+        assert_eq!(
+            ram[0x0fff..0x1201],
+            [&[0][..], &[0xaa; 0x200], &[0]].concat()
+        );
+        assert_eq!(new_prg_ram(&rom, 0x400)[..0x200], [0xaa; 0x200]);
+        assert_eq!(new_prg_ram(&rom, 0x100), vec![0; 0x100]);
+    }
+
+    #[test]
+    fn four_screen() {
+        let rom = Rom {
+            prg_rom: vec![0; PRG_BANK_16K],
+            chr_rom: vec![0; CHR_BANK_8K],
+            screen_mirroring: Mirroring::FourScreen,
+            ..Default::default()
+        };
+        let cartridge = load(rom).unwrap();
+        let mut vram = [0; 0x800];
+        cartridge.borrow_mut().write_nametable(0x2c00, 1, &mut vram);
+        assert_eq!(cartridge.borrow().read_nametable(0x2c00, &vram), 1);
+        assert_eq!(cartridge.borrow().read_nametable(0x2400, &vram), 0);
+    }
+
+    #[test]
+    fn small_prg_rom() {
+        // banks fixed from the end of the PRG ROM, which has fewer banks than the mapper slots
+        let prg_rom: Vec<u8> = (0..2).flat_map(|i| vec![i; PRG_BANK_8K]).collect();
+        let fixed_banks = [
+            (9, 0xa000, 1),
+            (9, 0xc000, 0),
+            (9, 0xe000, 1),
+            (22, 0xc000, 0),
+            (22, 0xe000, 1),
+            (24, 0xe000, 1),
+            (69, 0xe000, 1),
+            (19, 0xe000, 1),
+        ];
+        for (mapper, addr, bank) in fixed_banks {
+            let rom = Rom {
+                prg_rom: prg_rom.clone(),
+                mapper,
+                ..Default::default()
+            };
+            let cartridge = load(rom).unwrap();
+            let value = cartridge.borrow_mut().read_prg(addr);
+            assert_eq!(value, Some(bank), "mapper {mapper} at {addr:x}");
+        }
+
+        // and without PRG ROM at all
+        for mapper in [24, 69, 19] {
+            let rom = Rom {
+                mapper,
+                ..Default::default()
+            };
+            let value = load(rom).unwrap().borrow_mut().read_prg(0xfffc);
+            assert_eq!(value, Some(0), "mapper {mapper}");
+        }
+    }
+
+    #[test]
+    fn unsupported_mapper() {
+        let rom = Rom {
+            mapper: 255,
+            ..Default::default()
+        };
+        assert!(matches!(load(rom), Err(NoveError::UnsupportedMapper(255))));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::chr::Chr;
+use crate::mapper::prg_ram::PrgRam;
+use crate::mapper::{read_bank, Mapper, CHR_BANK_8K, PRG_BANK_32K};
+use crate::Program;
+
+const PRG_BANK_MASK: u8 = 0b0000_0111;
+const SCREEN_SELECT: u8 = 0b0001_0000;
+
+/// Mapper 7, switchable 32 KiB PRG bank and single screen mirroring selected by software
+pub struct Axrom {
+    prg_rom: Program,
+    chr: Chr,
+    prg_ram: PrgRam,
+    bank: usize,
+    mirroring: Mirroring,
+}
+
+impl Axrom {
+    pub fn new(rom: Rom) -> Self {
+        Self {
+            chr: Chr::new(&rom),
+            prg_ram: PrgRam::new(&rom),
+            prg_rom: rom.prg_rom,
+            bank: 0,
+            mirroring: Mirroring::SingleScreenLower,
+        }
+    }
+}
+
+impl Mapper for Axrom {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START.. => Some(read_bank(&self.prg_rom, self.bank, PRG_BANK_32K, addr)),
+            _ => self.prg_ram.read(addr),
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        if addr >= PRG_ROM_START {
+            self.bank = (value & PRG_BANK_MASK) as usize;
+            self.mirroring = if value & SCREEN_SELECT == 0 {
+                Mirroring::SingleScreenLower
+            } else {
+                Mirroring::SingleScreenUpper
+            };
+        } else {
+            self.prg_ram.write(addr, value);
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr.read(0, CHR_BANK_8K, addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        self.chr.write(0, CHR_BANK_8K, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.prg_ram.save_data()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.prg_ram.load_save_data(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bank_switching() {
+        let prg_rom: Program = (0..8).flat_map(|i| vec![i; PRG_BANK_32K]).collect();
+        let mut axrom = Axrom::new(Rom {
+            prg_rom,
+            ..Default::default()
+        });
+        assert_eq!(axrom.read_prg(0xffff), Some(0));
+        assert_eq!(axrom.mirroring(), Mirroring::SingleScreenLower);
+        axrom.write_prg(0x8000, 0b0001_0110);
+        assert_eq!(axrom.read_prg(0x8000), Some(6));
+        assert_eq!(axrom.mirroring(), Mirroring::SingleScreenUpper);
+    }
+}
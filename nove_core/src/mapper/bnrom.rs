@@ -0,0 +1,137 @@
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::chr::Chr;
+use crate::mapper::{new_prg_ram, read_bank, Mapper, CHR_BANK_4K, CHR_BANK_8K, PRG_BANK_32K};
+use crate::Program;
+
+const PRG_RAM_START: u16 = 0x6000;
+const PRG_RAM_SIZE: usize = 0x2000;
+const NINA_PRG_BANK: u16 = 0x7ffd;
+const NINA_CHR_BANK_0: u16 = 0x7ffe;
+const NINA_CHR_BANK_1: u16 = 0x7fff;
+
+/// Mapper 34 covers two unrelated boards, told apart by the size of their CHR ROM
+enum Board {
+    /// Switchable 32 KiB PRG bank written on $8000-$FFFF and unbanked CHR, usually RAM
+    Bnrom,
+    /// Registers at $7FFD-$7FFF for a 32 KiB PRG bank and two 4 KiB CHR banks, with PRG RAM
+    Nina001 {
+        prg_ram: Vec<u8>,
+        chr_banks: [usize; 2],
+    },
+}
+
+pub struct Bnrom {
+    prg_rom: Program,
+    chr: Chr,
+    mirroring: Mirroring,
+    prg_bank: usize,
+    board: Board,
+}
+
+impl Bnrom {
+    pub fn new(rom: Rom) -> Self {
+        let board = if rom.chr_rom.len() > CHR_BANK_8K {
+            Board::Nina001 {
+                prg_ram: new_prg_ram(&rom, PRG_RAM_SIZE),
+                chr_banks: [0, 1],
+            }
+        } else {
+            Board::Bnrom
+        };
+        Self {
+            chr: Chr::new(&rom),
+            prg_rom: rom.prg_rom,
+            mirroring: rom.screen_mirroring,
+            prg_bank: 0,
+            board,
+        }
+    }
+}
+
+impl Mapper for Bnrom {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        match (&self.board, addr) {
+            (_, PRG_ROM_START..) => {
+                Some(read_bank(&self.prg_rom, self.prg_bank, PRG_BANK_32K, addr))
+            }
+            (Board::Nina001 { prg_ram, .. }, PRG_RAM_START..) => {
+                Some(prg_ram[(addr - PRG_RAM_START) as usize])
+            }
+            _ => None,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match (&mut self.board, addr) {
+            (Board::Bnrom, PRG_ROM_START..) => self.prg_bank = value as usize,
+            (Board::Nina001 { prg_ram, chr_banks }, PRG_RAM_START..PRG_ROM_START) => {
+                match addr {
+                    NINA_PRG_BANK => self.prg_bank = (value & 1) as usize,
+                    NINA_CHR_BANK_0 => chr_banks[0] = (value & 0x0f) as usize,
+                    NINA_CHR_BANK_1 => chr_banks[1] = (value & 0x0f) as usize,
+                    _ => {}
+                }
+                // the registers overlap the RAM, so the value is also stored
+                prg_ram[(addr - PRG_RAM_START) as usize] = value;
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        match &self.board {
+            Board::Bnrom => self.chr.read(0, CHR_BANK_8K, addr),
+            Board::Nina001 { chr_banks, .. } => {
+                let bank = chr_banks[addr as usize / CHR_BANK_4K];
+                self.chr.read(bank, CHR_BANK_4K, addr)
+            }
+        }
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if let Board::Bnrom = self.board {
+            self.chr.write(0, CHR_BANK_8K, addr, value);
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bnrom() {
+        let prg_rom: Program = (0..4).flat_map(|i| vec![i; PRG_BANK_32K]).collect();
+        let mut bnrom = Bnrom::new(Rom {
+            prg_rom,
+            chr_rom: vec![0; CHR_BANK_8K],
+            ..Default::default()
+        });
+        bnrom.write_prg(0x8000, 3);
+        assert_eq!(bnrom.read_prg(0x8000), Some(3));
+        assert_eq!(bnrom.read_prg(0x6000), None);
+    }
+
+    #[test]
+    fn nina_001() {
+        let prg_rom: Program = (0..2).flat_map(|i| vec![i; PRG_BANK_32K]).collect();
+        let chr_rom: Program = (0..16).flat_map(|i| vec![i; CHR_BANK_4K]).collect();
+        let mut nina = Bnrom::new(Rom {
+            prg_rom,
+            chr_rom,
+            ..Default::default()
+        });
+        nina.write_prg(NINA_PRG_BANK, 1);
+        nina.write_prg(NINA_CHR_BANK_0, 5);
+        nina.write_prg(NINA_CHR_BANK_1, 9);
+        assert_eq!(nina.read_prg(0x8000), Some(1));
+        assert_eq!(nina.read_prg(NINA_CHR_BANK_1), Some(9));
+        assert_eq!(nina.read_chr(0x0000), 5);
+        assert_eq!(nina.read_chr(0x1000), 9);
+    }
+}
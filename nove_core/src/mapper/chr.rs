@@ -0,0 +1,65 @@
+use crate::cartridge::Rom;
+use crate::mapper::{bank_addr, read_bank};
+use crate::Program;
+
+/// Pattern memory of the cartridge. Boards without CHR ROM carry CHR RAM instead, sized by the
+/// header, that the PPU fills through $2007.
+pub struct Chr {
+    data: Program,
+    ram: bool,
+}
+
+impl Chr {
+    pub fn new(rom: &Rom) -> Self {
+        if rom.chr_rom.is_empty() {
+            Self {
+                data: vec![0; rom.chr_ram_total()],
+                ram: true,
+            }
+        } else {
+            Self {
+                data: rom.chr_rom.clone(),
+                ram: false,
+            }
+        }
+    }
+
+    pub fn is_ram(&self) -> bool {
+        self.ram
+    }
+
+    pub fn read(&self, bank: usize, bank_size: usize, addr: u16) -> u8 {
+        read_bank(&self.data, bank, bank_size, addr)
+    }
+
+    /// Writes the byte of `addr` inside the selected bank, ignored when the memory is ROM
+    pub fn write(&mut self, bank: usize, bank_size: usize, addr: u16, value: u8) {
+        if self.ram && !self.data.is_empty() {
+            let i = bank_addr(&self.data, bank, bank_size, addr);
+            self.data[i] = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn chr_ram() {
+        let mut chr = Chr::new(&Rom {
+            chr_ram_size: 0x2000,
+            ..Default::default()
+        });
+        chr.write(1, 0x1000, 0x0123, 0x45);
+        assert_eq!(chr.read(0, 0x2000, 0x1123), 0x45);
+
+        let mut rom = Chr::new(&Rom {
+            chr_rom: vec![0; 0x2000],
+            chr_ram_size: 0x2000,
+            ..Default::default()
+        });
+        rom.write(0, 0x2000, 0x0123, 0x45);
+        assert_eq!(rom.read(0, 0x2000, 0x0123), 0);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::chr::Chr;
+use crate::mapper::prg_ram::PrgRam;
+use crate::mapper::{read_bank, Mapper, CHR_BANK_8K, PRG_BANK_32K};
+use crate::Program;
+
+/// Mapper 3, fixed PRG ROM like NROM and a switchable 8 KiB CHR bank
+pub struct Cnrom {
+    prg_rom: Program,
+    chr: Chr,
+    prg_ram: PrgRam,
+    mirroring: Mirroring,
+    chr_bank: usize,
+}
+
+impl Cnrom {
+    pub fn new(rom: Rom) -> Self {
+        Self {
+            chr: Chr::new(&rom),
+            prg_ram: PrgRam::new(&rom),
+            prg_rom: rom.prg_rom,
+            mirroring: rom.screen_mirroring,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Cnrom {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START.. => Some(read_bank(&self.prg_rom, 0, PRG_BANK_32K, addr)),
+            _ => self.prg_ram.read(addr),
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        if addr >= PRG_ROM_START {
+            self.chr_bank = value as usize;
+        } else {
+            self.prg_ram.write(addr, value);
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr.read(self.chr_bank, CHR_BANK_8K, addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        self.chr.write(self.chr_bank, CHR_BANK_8K, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.prg_ram.save_data()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.prg_ram.load_save_data(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bank_switching() {
+        let chr_rom: Program = (0..4).flat_map(|i| vec![i; CHR_BANK_8K]).collect();
+        let mut cnrom = Cnrom::new(Rom {
+            chr_rom,
+            ..Default::default()
+        });
+        assert_eq!(cnrom.read_chr(0x0000), 0);
+        cnrom.write_prg(0x8000, 2);
+        assert_eq!(cnrom.read_chr(0x1fff), 2);
+        cnrom.write_prg(0xffff, 7);
+        assert_eq!(cnrom.read_chr(0x0000), 3);
+    }
+}
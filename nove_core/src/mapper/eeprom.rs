@@ -0,0 +1,312 @@
+/// Device address of the 24C0X family, followed by the chip select pins and the read bit
+const DEVICE_ADDRESS: u8 = 0b1010_0000;
+const DEVICE_ADDRESS_MASK: u8 = 0b1111_0000;
+const READ: u8 = 0b0000_0001;
+/// The 24C01 sends the read bit after the seven bits of the word address
+const X24C01_READ: u8 = 0b1000_0000;
+const X24C01_ADDRESS_MASK: u8 = 0b0111_1111;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Model {
+    /// 128 bytes with a simplified protocol: no device address, and bits sent LSB first
+    X24C01,
+    /// 256 bytes with the standard I2C protocol
+    C24C02,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Phase {
+    #[default]
+    Idle,
+    Device,
+    Address,
+    Write,
+    Read,
+    /// The EEPROM acknowledges the byte it received
+    Ack,
+    /// The host acknowledges the byte it read to keep reading
+    WaitAck,
+}
+
+/// Serial EEPROM of the 24C0X family driven bit by bit through its I2C clock and data lines
+#[derive(Debug)]
+pub struct Eeprom {
+    model: Model,
+    data: Vec<u8>,
+    phase: Phase,
+    next_phase: Phase,
+    scl: bool,
+    sda: bool,
+    /// Byte being received or sent
+    shift: u8,
+    bits: u8,
+    address: u8,
+    output: bool,
+}
+
+impl Eeprom {
+    pub fn new(model: Model) -> Self {
+        let size = match model {
+            Model::X24C01 => 128,
+            Model::C24C02 => 256,
+        };
+        Self {
+            model,
+            data: vec![0; size],
+            phase: Phase::Idle,
+            next_phase: Phase::Idle,
+            scl: false,
+            sda: false,
+            shift: 0,
+            bits: 0,
+            address: 0,
+            output: true,
+        }
+    }
+
+    /// Level driven by the EEPROM on the data line, released high while it isn't answering
+    pub fn read(&self) -> bool {
+        self.output
+    }
+
+    /// Sets the levels of the clock and data lines
+    pub fn write(&mut self, scl: bool, sda: bool) {
+        if self.scl && scl && self.sda && !sda {
+            self.start();
+        } else if self.scl && scl && !self.sda && sda {
+            self.phase = Phase::Idle;
+            self.output = true;
+        } else if !self.scl && scl {
+            self.rise(sda);
+        } else if self.scl && !scl {
+            self.fall();
+        }
+        self.scl = scl;
+        self.sda = sda;
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn load(&mut self, data: &[u8]) {
+        let len = data.len().min(self.data.len());
+        self.data[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn start(&mut self) {
+        self.phase = match self.model {
+            Model::X24C01 => Phase::Address,
+            Model::C24C02 => Phase::Device,
+        };
+        self.bits = 0;
+        self.output = true;
+    }
+
+    fn mask(&self) -> u8 {
+        match self.model {
+            Model::X24C01 => 1 << self.bits,
+            Model::C24C02 => 0x80 >> self.bits,
+        }
+    }
+
+    /// The data line is sampled while the clock is high
+    fn rise(&mut self, sda: bool) {
+        match self.phase {
+            Phase::Device | Phase::Address | Phase::Write if self.bits < 8 => {
+                if sda {
+                    self.shift |= self.mask();
+                } else {
+                    self.shift &= !self.mask();
+                }
+                self.bits += 1;
+            }
+            Phase::Read if self.bits < 8 => {
+                self.output = self.shift & self.mask() != 0;
+                self.bits += 1;
+            }
+            Phase::Ack => self.output = false,
+            Phase::WaitAck => self.next_phase = if sda { Phase::Idle } else { Phase::Read },
+            _ => {}
+        }
+    }
+
+    /// The phases change while the clock is low
+    fn fall(&mut self) {
+        match self.phase {
+            Phase::Device if self.bits == 8 => {
+                if self.shift & DEVICE_ADDRESS_MASK != DEVICE_ADDRESS {
+                    self.phase = Phase::Idle;
+                    return;
+                }
+                let next = if self.shift & READ != 0 {
+                    Phase::Read
+                } else {
+                    Phase::Address
+                };
+                self.acknowledge(next);
+            }
+            Phase::Address if self.bits == 8 => {
+                let next = match self.model {
+                    Model::X24C01 => {
+                        self.address = self.shift & X24C01_ADDRESS_MASK;
+                        if self.shift & X24C01_READ != 0 {
+                            Phase::Read
+                        } else {
+                            Phase::Write
+                        }
+                    }
+                    Model::C24C02 => {
+                        self.address = self.shift;
+                        Phase::Write
+                    }
+                };
+                self.acknowledge(next);
+            }
+            Phase::Write if self.bits == 8 => {
+                self.data[self.address as usize] = self.shift;
+                self.increment_address();
+                self.acknowledge(Phase::Write);
+            }
+            Phase::Read if self.bits == 8 => {
+                self.increment_address();
+                self.phase = Phase::WaitAck;
+                self.output = true;
+            }
+            Phase::Ack | Phase::WaitAck => {
+                self.phase = self.next_phase;
+                self.bits = 0;
+                self.output = true;
+                if self.phase == Phase::Read {
+                    self.shift = self.data[self.address as usize];
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn acknowledge(&mut self, next: Phase) {
+        self.phase = Phase::Ack;
+        self.next_phase = next;
+    }
+
+    fn increment_address(&mut self) {
+        self.address = ((self.address as usize + 1) % self.data.len()) as u8;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Host side of the I2C bus, sending the bits in the order of the model
+    struct Host<'a> {
+        eeprom: &'a mut Eeprom,
+    }
+
+    impl Host<'_> {
+        fn start(&mut self) {
+            self.eeprom.write(false, true);
+            self.eeprom.write(true, true);
+            self.eeprom.write(true, false);
+            self.eeprom.write(false, false);
+        }
+
+        fn stop(&mut self) {
+            self.eeprom.write(false, false);
+            self.eeprom.write(true, false);
+            self.eeprom.write(true, true);
+        }
+
+        fn clock(&mut self, sda: bool) -> bool {
+            self.eeprom.write(false, sda);
+            self.eeprom.write(true, sda);
+            let bit = self.eeprom.read();
+            self.eeprom.write(false, sda);
+            bit
+        }
+
+        fn bits(&self) -> Vec<u8> {
+            match self.eeprom.model {
+                Model::X24C01 => (0..8).collect(),
+                Model::C24C02 => (0..8).rev().collect(),
+            }
+        }
+
+        /// Sends a byte, returning whether the EEPROM acknowledged it
+        fn send(&mut self, byte: u8) -> bool {
+            for bit in self.bits() {
+                self.clock(byte & (1 << bit) != 0);
+            }
+            !self.clock(true)
+        }
+
+        fn receive(&mut self, ack: bool) -> u8 {
+            let byte = self
+                .bits()
+                .into_iter()
+                .fold(0, |byte, bit| byte | (self.clock(true) as u8) << bit);
+            self.clock(!ack);
+            byte
+        }
+    }
+
+    #[test]
+    fn c24c02_write_and_read() {
+        let mut eeprom = Eeprom::new(Model::C24C02);
+        let mut host = Host {
+            eeprom: &mut eeprom,
+        };
+        host.start();
+        assert!(host.send(DEVICE_ADDRESS));
+        assert!(host.send(0x10));
+        assert!(host.send(0x12));
+        assert!(host.send(0x34));
+        host.stop();
+
+        host.start();
+        assert!(host.send(DEVICE_ADDRESS));
+        assert!(host.send(0x10));
+        host.start();
+        assert!(host.send(DEVICE_ADDRESS | READ));
+        assert_eq!(host.receive(true), 0x12);
+        assert_eq!(host.receive(false), 0x34);
+        host.stop();
+        assert_eq!(eeprom.data()[0x10..0x12], [0x12, 0x34]);
+    }
+
+    #[test]
+    fn c24c02_other_device() {
+        let mut eeprom = Eeprom::new(Model::C24C02);
+        let mut host = Host {
+            eeprom: &mut eeprom,
+        };
+        host.start();
+        assert!(!host.send(0b1011_0000));
+        assert!(!host.send(0x10));
+    }
+
+    #[test]
+    fn x24c01_write_and_read() {
+        let mut eeprom = Eeprom::new(Model::X24C01);
+        let mut host = Host {
+            eeprom: &mut eeprom,
+        };
+        host.start();
+        assert!(host.send(0x7f));
+        assert!(host.send(0x56));
+        assert!(host.send(0x78));
+        host.stop();
+        assert_eq!(eeprom.data()[0x7f], 0x56);
+        assert_eq!(eeprom.data()[0x00], 0x78);
+
+        let mut host = Host {
+            eeprom: &mut eeprom,
+        };
+        host.start();
+        assert!(host.send(X24C01_READ | 0x7f));
+        assert_eq!(host.receive(true), 0x56);
+        assert_eq!(host.receive(false), 0x78);
+        host.stop();
+    }
+}
@@ -0,0 +1,377 @@
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::chr::Chr;
+use crate::mapper::eeprom::{Eeprom, Model};
+use crate::mapper::{
+    load_memory, new_prg_ram, read_bank, Mapper, CHR_BANK_8K, PRG_BANK_16K, PRG_BANK_8K,
+};
+use crate::Program;
+
+const PRG_RAM_START: u16 = 0x6000;
+const CHR_BANK_1K: usize = 0x400;
+const REGISTER_MASK: u16 = 0x000f;
+const PRG_BANK_MASK: u8 = 0b0000_1111;
+/// The mapper 153 selects 256 KiB halves of its PRG ROM with the bit 0 of the CHR registers
+const OUTER_BANK_SHIFT: u8 = 4;
+
+const IRQ_ENABLE: u8 = 0b0000_0001;
+
+/*
+   EEPROM control ($xxxD)
+
+   7  bit  0
+   ---- ----
+   RDC. ....
+   |||
+   ||+------- I2C clock, or PRG RAM enable on the mapper 153
+   |+-------- I2C data
+   +--------- Enable reading the EEPROM data on the bit 4 of $6000-$7FFF
+*/
+const EEPROM_SCL: u8 = 0b0010_0000;
+const EEPROM_SDA: u8 = 0b0100_0000;
+const EEPROM_READ: u8 = 0b1000_0000;
+const EEPROM_OUTPUT: u8 = 0b0001_0000;
+const PRG_RAM_ENABLE: u8 = EEPROM_SCL;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Board {
+    /// FCG-1 and FCG-2, registers at $6000-$7FFF and a counter written directly
+    Fcg,
+    /// LZ93D50, registers at $8000-$FFFF and a counter reloaded from a latch
+    Lz93d50,
+    /// Mapper 16 without submapper, behaving as both chips
+    Unknown,
+}
+
+/// Bandai FCG boards, mappers 16, 153, 157 and 159. Their saves, when they have them, are kept
+/// on a serial EEPROM instead of a battery backed RAM, except the 8 KiB of the mapper 153.
+pub struct Fcg {
+    prg_rom: Program,
+    chr: Chr,
+    prg_ram: Option<Vec<u8>>,
+    board: Board,
+    eeprom: Option<Eeprom>,
+    battery: bool,
+    chr_banks: [u8; 8],
+    prg_bank: u8,
+    mirroring: Mirroring,
+    eeprom_control: u8,
+    irq_enabled: bool,
+    irq_counter: u16,
+    irq_latch: u16,
+    irq_pending: bool,
+}
+
+impl Fcg {
+    pub fn new(rom: Rom) -> Self {
+        let board = match (rom.mapper, rom.submapper) {
+            (16, 4) => Board::Fcg,
+            (16, 0) => Board::Unknown,
+            _ => Board::Lz93d50,
+        };
+        let eeprom = match (rom.mapper, rom.submapper) {
+            (159, _) => Some(Eeprom::new(Model::X24C01)),
+            (157, _) | (16, 5) => Some(Eeprom::new(Model::C24C02)),
+            (16, 0) if rom.battery => Some(Eeprom::new(Model::C24C02)),
+            _ => None,
+        };
+        let prg_ram =
+            (rom.mapper == 153).then(|| new_prg_ram(&rom, rom.prg_ram_total().max(PRG_BANK_8K)));
+        Self {
+            chr: Chr::new(&rom),
+            prg_rom: rom.prg_rom,
+            prg_ram,
+            board,
+            eeprom,
+            battery: rom.battery,
+            chr_banks: [0; 8],
+            prg_bank: 0,
+            mirroring: rom.screen_mirroring,
+            eeprom_control: 0,
+            irq_enabled: false,
+            irq_counter: 0,
+            irq_latch: 0,
+            irq_pending: false,
+        }
+    }
+
+    /// CHR bank of `addr` and its size. The boards with CHR RAM don't bank it.
+    fn chr_bank(&self, addr: u16) -> (usize, usize) {
+        if self.chr.is_ram() {
+            (0, CHR_BANK_8K)
+        } else {
+            (
+                self.chr_banks[addr as usize / CHR_BANK_1K] as usize,
+                CHR_BANK_1K,
+            )
+        }
+    }
+
+    fn has_registers(&self, addr: u16) -> bool {
+        match self.board {
+            Board::Fcg => (PRG_RAM_START..PRG_ROM_START).contains(&addr),
+            Board::Lz93d50 => addr >= PRG_ROM_START,
+            Board::Unknown => addr >= PRG_RAM_START,
+        }
+    }
+
+    fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0..=7 => self.chr_banks[register as usize] = value,
+            8 => self.prg_bank = value,
+            9 => {
+                self.mirroring = match value & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                }
+            }
+            0xa => {
+                self.irq_enabled = value & IRQ_ENABLE != 0;
+                self.irq_pending = false;
+                if self.board != Board::Fcg {
+                    self.irq_counter = self.irq_latch;
+                }
+            }
+            0xb | 0xc => {
+                let shift = (register - 0xb) * 8;
+                let value = (value as u16) << shift;
+                let mask = 0xff00 >> shift;
+                self.irq_latch = (self.irq_latch & mask) | value;
+                if self.board != Board::Lz93d50 {
+                    self.irq_counter = (self.irq_counter & mask) | value;
+                }
+            }
+            _ => {
+                self.eeprom_control = value;
+                if let Some(eeprom) = &mut self.eeprom {
+                    eeprom.write(value & EEPROM_SCL != 0, value & EEPROM_SDA != 0);
+                }
+            }
+        }
+    }
+
+    fn outer_bank(&self) -> usize {
+        match self.prg_ram {
+            Some(_) => {
+                let outer = self.chr_banks[..4]
+                    .iter()
+                    .fold(0, |outer, bank| outer | bank);
+                ((outer & 1) << OUTER_BANK_SHIFT) as usize
+            }
+            None => 0,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.eeprom_control & PRG_RAM_ENABLE != 0
+    }
+}
+
+impl Mapper for Fcg {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            0xc000.. => {
+                let bank = self.outer_bank() | PRG_BANK_MASK as usize;
+                Some(read_bank(&self.prg_rom, bank, PRG_BANK_16K, addr))
+            }
+            PRG_ROM_START.. => {
+                let bank = self.outer_bank() | (self.prg_bank & PRG_BANK_MASK) as usize;
+                Some(read_bank(&self.prg_rom, bank, PRG_BANK_16K, addr))
+            }
+            PRG_RAM_START.. => match (&self.prg_ram, &self.eeprom) {
+                (Some(ram), _) if self.prg_ram_enabled() => {
+                    Some(ram[(addr - PRG_RAM_START) as usize % ram.len()])
+                }
+                (_, Some(eeprom)) if self.eeprom_control & EEPROM_READ != 0 => {
+                    Some(if eeprom.read() { EEPROM_OUTPUT } else { 0 })
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        if self.has_registers(addr) {
+            self.write_register(addr & REGISTER_MASK, value);
+            return;
+        }
+        if addr >= PRG_RAM_START && self.prg_ram_enabled() {
+            if let Some(ram) = &mut self.prg_ram {
+                let len = ram.len();
+                ram[(addr - PRG_RAM_START) as usize % len] = value;
+            }
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        let (bank, size) = self.chr_bank(addr);
+        self.chr.read(bank, size, addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        let (bank, size) = self.chr_bank(addr);
+        self.chr.write(bank, size, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        if !self.irq_enabled {
+            return;
+        }
+        for _ in 0..cycles {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0 {
+                self.irq_pending = true;
+            }
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    /// The EEPROMs keep their contents without battery
+    fn save_data(&self) -> Option<Vec<u8>> {
+        match (&self.eeprom, &self.prg_ram) {
+            (Some(eeprom), _) => Some(eeprom.data().to_vec()),
+            (None, Some(ram)) if self.battery => Some(ram.clone()),
+            _ => None,
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        match (&mut self.eeprom, &mut self.prg_ram) {
+            (Some(eeprom), _) => eeprom.load(data),
+            (None, Some(ram)) => load_memory(ram, data),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fcg(mapper: u16, submapper: u8) -> Fcg {
+        Fcg::new(Rom {
+            prg_rom: (0..32).flat_map(|i| vec![i; PRG_BANK_16K]).collect(),
+            chr_rom: if mapper == 16 {
+                (0..=255).flat_map(|i| vec![i; CHR_BANK_1K]).collect()
+            } else {
+                vec![]
+            },
+            chr_ram_size: if mapper == 16 { 0 } else { CHR_BANK_8K },
+            mapper,
+            submapper,
+            ..Default::default()
+        })
+    }
+
+    /// Sets the I2C lines through the EEPROM control register
+    fn i2c(fcg: &mut Fcg, scl: bool, sda: bool) {
+        let scl = if scl { EEPROM_SCL } else { 0 };
+        let sda = if sda { EEPROM_SDA } else { 0 };
+        fcg.write_prg(0x800d, EEPROM_READ | scl | sda);
+    }
+
+    #[test]
+    fn registers() {
+        let mut fcg4 = fcg(16, 4);
+        fcg4.write_prg(0x6008, 3);
+        fcg4.write_prg(0x6007, 200);
+        fcg4.write_prg(0x6009, 0);
+        assert_eq!(fcg4.read_prg(0x8000), Some(3));
+        assert_eq!(fcg4.read_prg(0xc000), Some(15));
+        assert_eq!(fcg4.read_chr(0x1c00), 200);
+        assert_eq!(fcg4.mirroring(), Mirroring::Vertical);
+
+        let mut lz93d50 = fcg(16, 5);
+        lz93d50.write_prg(0x6008, 3);
+        lz93d50.write_prg(0xfff8, 4);
+        lz93d50.write_prg(0x8009, 2);
+        assert_eq!(lz93d50.read_prg(0x8000), Some(4));
+        assert_eq!(lz93d50.mirroring(), Mirroring::SingleScreenLower);
+    }
+
+    #[test]
+    fn irq() {
+        let mut fcg4 = fcg(16, 4);
+        fcg4.write_prg(0x600b, 3);
+        fcg4.write_prg(0x600a, IRQ_ENABLE);
+        fcg4.tick(2);
+        assert!(!fcg4.irq());
+        fcg4.tick(1);
+        assert!(fcg4.irq());
+        fcg4.write_prg(0x600a, 0);
+        assert!(!fcg4.irq());
+
+        // the LZ93D50 reloads the counter when the IRQ is enabled
+        let mut lz93d50 = fcg(159, 0);
+        lz93d50.write_prg(0x800b, 3);
+        lz93d50.tick(1);
+        lz93d50.write_prg(0x800a, IRQ_ENABLE);
+        lz93d50.tick(2);
+        assert!(!lz93d50.irq());
+        lz93d50.tick(1);
+        assert!(lz93d50.irq());
+    }
+
+    #[test]
+    fn mapper_153_prg_ram_and_outer_bank() {
+        let mut fcg = fcg(153, 0);
+        fcg.write_prg(0x8000, 1);
+        fcg.write_prg(0x8008, 2);
+        assert_eq!(fcg.read_prg(0x8000), Some(18));
+        assert_eq!(fcg.read_prg(0xc000), Some(31));
+
+        fcg.write_prg(0x6000, 1);
+        assert_eq!(fcg.read_prg(0x6000), None);
+        fcg.write_prg(0x800d, PRG_RAM_ENABLE);
+        fcg.write_prg(0x6000, 1);
+        assert_eq!(fcg.read_prg(0x6000), Some(1));
+        fcg.write_chr(0x0001, 2);
+        assert_eq!(fcg.read_chr(0x0001), 2);
+    }
+
+    #[test]
+    fn eeprom_through_registers() {
+        let mut mapper = fcg(159, 0);
+        let send = |mapper: &mut Fcg, bits: &[bool]| {
+            bits.iter().for_each(|bit| {
+                i2c(mapper, false, *bit);
+                i2c(mapper, true, *bit);
+                i2c(mapper, false, *bit);
+            })
+        };
+        // start
+        i2c(&mut mapper, true, true);
+        i2c(&mut mapper, true, false);
+        i2c(&mut mapper, false, false);
+        // write address 1, LSB first, and the byte 0b11
+        send(
+            &mut mapper,
+            &[true, false, false, false, false, false, false, false],
+        );
+        i2c(&mut mapper, false, true);
+        i2c(&mut mapper, true, true);
+        assert_eq!(mapper.read_prg(0x6000), Some(0));
+        i2c(&mut mapper, false, true);
+        send(
+            &mut mapper,
+            &[true, true, false, false, false, false, false, false, true],
+        );
+
+        let save = mapper.save_data().unwrap();
+        assert_eq!(save.len(), 128);
+        assert_eq!(save[1], 0b11);
+        let mut loaded = fcg(159, 0);
+        loaded.load_save_data(&save);
+        assert_eq!(loaded.save_data(), Some(save));
+    }
+}
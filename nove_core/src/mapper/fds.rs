@@ -0,0 +1,254 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::chr::Chr;
+use crate::mapper::fds_audio::FdsAudio;
+use crate::mapper::fds_drive::Drive;
+use crate::mapper::{Mapper, CHR_BANK_8K};
+use crate::Program;
+
+const PRG_RAM_START: u16 = 0x6000;
+const BIOS_START: u16 = 0xe000;
+/// RAM of the adapter at $6000-$DFFF, present whatever the image says
+const PRG_RAM_SIZE: usize = 0x8000;
+
+const TIMER_REPEAT: u8 = 0b0000_0001;
+const TIMER_ENABLE: u8 = 0b0000_0010;
+const DISK_REGISTERS_ENABLE: u8 = 0b0000_0001;
+const SOUND_REGISTERS_ENABLE: u8 = 0b0000_0010;
+const TIMER_IRQ: u8 = 0b0000_0001;
+/// Bits of the external connector of $4033, the battery being good
+const BATTERY_GOOD: u8 = 0b1000_0000;
+
+/// Mapper 20, the RAM adapter of the Famicom Disk System. Games are loaded from the disk into
+/// 32 KiB of PRG RAM and 8 KiB of CHR RAM by the BIOS, at $E000-$FFFF.
+pub struct Fds {
+    bios: Program,
+    prg_ram: Vec<u8>,
+    chr: Chr,
+    drive: Drive,
+    audio: FdsAudio,
+    /// Enables of the disk and sound registers of $4023
+    io_enable: u8,
+    timer_control: u8,
+    timer_reload: u16,
+    timer_counter: u16,
+    timer_irq: bool,
+}
+
+impl Fds {
+    pub fn new(rom: Rom) -> Self {
+        Self {
+            chr: Chr::new(&rom),
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            drive: Drive::new(&rom.disk_sides),
+            bios: rom.prg_rom,
+            audio: Default::default(),
+            io_enable: 0,
+            timer_control: 0,
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_irq: false,
+        }
+    }
+
+    fn write_timer_control(&mut self, value: u8) {
+        self.timer_control = value;
+        if value & TIMER_ENABLE != 0 {
+            self.timer_counter = self.timer_reload;
+        } else {
+            self.timer_irq = false;
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer_control & TIMER_ENABLE == 0 {
+            return;
+        }
+        if self.timer_counter == 0 {
+            self.timer_irq = true;
+            self.timer_counter = self.timer_reload;
+            if self.timer_control & TIMER_REPEAT == 0 {
+                self.timer_control &= !TIMER_ENABLE;
+            }
+        } else {
+            self.timer_counter -= 1;
+        }
+    }
+
+    fn disk_registers(&self) -> bool {
+        self.io_enable & DISK_REGISTERS_ENABLE != 0
+    }
+}
+
+impl Mapper for Fds {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4030 if self.disk_registers() => {
+                let mut status = self.drive.read_status();
+                if self.timer_irq {
+                    status |= TIMER_IRQ;
+                }
+                self.timer_irq = false;
+                Some(status)
+            }
+            0x4031 if self.disk_registers() => Some(self.drive.read_data()),
+            0x4032 if self.disk_registers() => Some(self.drive.read_drive_status()),
+            0x4033 if self.disk_registers() => Some(BATTERY_GOOD),
+            0x4040..=0x409f => self.audio.read(addr),
+            BIOS_START.. => Some(self.bios[(addr - BIOS_START) as usize % self.bios.len()]),
+            PRG_RAM_START.. => Some(self.prg_ram[(addr - PRG_RAM_START) as usize]),
+            _ => None,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4023 => {
+                self.io_enable = value;
+                if !self.disk_registers() {
+                    self.timer_control &= !TIMER_ENABLE;
+                    self.timer_irq = false;
+                }
+            }
+            _ if (0x4020..=0x4026).contains(&addr) && !self.disk_registers() => {}
+            0x4020 => self.timer_reload = (self.timer_reload & 0xff00) | value as u16,
+            0x4021 => self.timer_reload = (self.timer_reload & 0x00ff) | (value as u16) << 8,
+            0x4022 => self.write_timer_control(value),
+            0x4024 => self.drive.write_data(value),
+            0x4025 => self.drive.write_control(value),
+            0x4040..=0x409f if self.io_enable & SOUND_REGISTERS_ENABLE != 0 => {
+                self.audio.write(addr, value)
+            }
+            PRG_RAM_START..BIOS_START => self.prg_ram[(addr - PRG_RAM_START) as usize] = value,
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr.read(0, CHR_BANK_8K, addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        self.chr.write(0, CHR_BANK_8K, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.drive.mirroring()
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        for _ in 0..cycles {
+            self.clock_timer();
+            self.drive.clock();
+        }
+        self.audio.tick(cycles);
+    }
+
+    fn irq(&self) -> bool {
+        self.timer_irq || self.drive.irq()
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.drive.save_data()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.drive.load_save_data(data)
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn disk_sides(&self) -> usize {
+        self.drive.sides()
+    }
+
+    fn insert_disk(&mut self, side: Option<usize>) {
+        self.drive.insert(side)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fds() -> Fds {
+        let mut side = b"\x01*NINTENDO-HVC*".to_vec();
+        side.resize(0x100, 0);
+        Fds::new(Rom {
+            prg_rom: (0..0x2000).map(|i| i as u8).collect(),
+            chr_ram_size: CHR_BANK_8K,
+            disk_sides: vec![side.clone(), side],
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn memory() {
+        let mut fds = fds();
+        assert_eq!(fds.read_prg(0xe0ff), Some(0xff));
+        fds.write_prg(0xe000, 1);
+        assert_eq!(fds.read_prg(0xe000), Some(0));
+        fds.write_prg(0xdfff, 2);
+        assert_eq!(fds.read_prg(0xdfff), Some(2));
+        assert_eq!(fds.read_prg(0x6000), Some(0));
+        fds.write_chr(0x1fff, 3);
+        assert_eq!(fds.read_chr(0x1fff), 3);
+        assert_eq!(fds.read_prg(0x4032), None);
+        assert_eq!(fds.disk_sides(), 2);
+    }
+
+    #[test]
+    fn timer_irq() {
+        let mut fds = fds();
+        fds.write_prg(0x4023, DISK_REGISTERS_ENABLE);
+        fds.write_prg(0x4020, 10);
+        fds.write_prg(0x4023, 0);
+        fds.write_prg(0x4022, TIMER_ENABLE);
+        fds.tick(20);
+        assert!(!fds.irq());
+
+        fds.write_prg(0x4023, DISK_REGISTERS_ENABLE);
+        fds.write_prg(0x4022, TIMER_ENABLE | TIMER_REPEAT);
+        fds.tick(10);
+        assert!(!fds.irq());
+        fds.tick(1);
+        assert!(fds.irq());
+        assert_eq!(
+            fds.read_prg(0x4030).map(|status| status & TIMER_IRQ),
+            Some(1)
+        );
+        assert!(!fds.irq());
+        fds.tick(11);
+        assert!(fds.irq());
+
+        fds.write_prg(0x4022, 0);
+        assert!(!fds.irq());
+    }
+
+    #[test]
+    fn mirroring() {
+        let mut fds = fds();
+        fds.write_prg(0x4023, DISK_REGISTERS_ENABLE);
+        fds.write_prg(0x4025, 0b0000_1000);
+        assert_eq!(fds.mirroring(), Mirroring::Horizontal);
+        fds.write_prg(0x4025, 0);
+        assert_eq!(fds.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn audio() {
+        let mut fds = fds();
+        fds.write_prg(0x4089, 0x80);
+        assert_eq!(fds.read_prg(0x4040), Some(0x40));
+        fds.write_prg(0x4040, 0x3f);
+        assert_eq!(fds.read_prg(0x4040), Some(0x40));
+
+        fds.write_prg(0x4023, SOUND_REGISTERS_ENABLE);
+        fds.write_prg(0x4089, 0x80);
+        fds.write_prg(0x4040, 0x3f);
+        fds.write_prg(0x4089, 0);
+        fds.write_prg(0x4080, 0xa0);
+        assert!(fds.audio_output() > 0.0);
+    }
+}
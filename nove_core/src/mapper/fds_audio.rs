@@ -0,0 +1,337 @@
+use crate::mapper::APU_PULSE_STEP;
+
+const WAVE_SIZE: usize = 64;
+const WAVE_MASK: u8 = 0b0011_1111;
+const MAX_GAIN: u8 = 32;
+const FREQUENCY_HIGH_MASK: u8 = 0b0000_1111;
+const MOD_COUNTER_MASK: u8 = 0b0111_1111;
+/// Change of the modulation counter of each table entry, the fourth resetting it
+const MOD_STEPS: [i8; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+const MOD_RESET: u8 = 4;
+/// Attenuation of the four master volumes, 2/2, 2/3, 2/4 and 2/5
+const MASTER_VOLUMES: [f32; 4] = [1.0, 2.0 / 3.0, 0.5, 0.4];
+const DEFAULT_MASTER_SPEED: u8 = 0xe8;
+/// The wavetable at full volume sounds about 2.4 times louder than an APU pulse
+const FULL_VOLUME: f32 = 2.4 * 15.0 * APU_PULSE_STEP;
+
+/*
+   Envelopes ($4080 and $4084)
+
+   7  bit  0
+   ---- ----
+   DISS SSSS
+   |||| ||||
+   ||++-++++- Speed, or the gain when the envelope is disabled
+   |+-------- Increase the gain, decrease when clear
+   +--------- Disable the envelope
+*/
+const ENVELOPE_DISABLE: u8 = 0b1000_0000;
+const ENVELOPE_INCREASE: u8 = 0b0100_0000;
+const ENVELOPE_SPEED: u8 = 0b0011_1111;
+
+const WAVE_HALT: u8 = 0b1000_0000;
+const ENVELOPES_DISABLE: u8 = 0b0100_0000;
+const MOD_HALT: u8 = 0b1000_0000;
+const WAVE_WRITE: u8 = 0b1000_0000;
+const MASTER_VOLUME: u8 = 0b0000_0011;
+
+#[derive(Debug, Default)]
+struct Envelope {
+    speed: u8,
+    increase: bool,
+    disabled: bool,
+    gain: u8,
+    timer: u32,
+}
+
+impl Envelope {
+    fn write(&mut self, value: u8) {
+        self.speed = value & ENVELOPE_SPEED;
+        self.increase = value & ENVELOPE_INCREASE != 0;
+        self.disabled = value & ENVELOPE_DISABLE != 0;
+        if self.disabled {
+            self.gain = self.speed;
+        }
+        self.timer = 0;
+    }
+
+    fn clock(&mut self, master_speed: u8) {
+        if self.disabled || master_speed == 0 {
+            return;
+        }
+        self.timer += 1;
+        if self.timer < 8 * (self.speed as u32 + 1) * master_speed as u32 {
+            return;
+        }
+        self.timer = 0;
+        if self.increase && self.gain < MAX_GAIN {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+    }
+}
+
+/// Audio of the Famicom Disk System, a wavetable channel of 64 steps with a frequency
+/// modulator and volume and modulation envelopes. Mapped at $4040-$409F.
+#[derive(Debug)]
+pub struct FdsAudio {
+    wave: [u8; WAVE_SIZE],
+    wave_frequency: u16,
+    wave_accumulator: u16,
+    wave_position: usize,
+    /// Wave and envelope halts of $4083
+    wave_control: u8,
+    /// Wave writes and master volume of $4089
+    wave_write: u8,
+    master_speed: u8,
+    volume: Envelope,
+    modulation: Envelope,
+    mod_table: [u8; WAVE_SIZE],
+    mod_frequency: u16,
+    mod_accumulator: u16,
+    mod_position: usize,
+    mod_halt: bool,
+    /// Signed 7 bit counter bending the pitch of the wave
+    mod_counter: i8,
+    /// Last output while the wave is being written
+    held: u8,
+}
+
+impl Default for FdsAudio {
+    fn default() -> Self {
+        Self {
+            wave: [0; WAVE_SIZE],
+            wave_frequency: 0,
+            wave_accumulator: 0,
+            wave_position: 0,
+            wave_control: WAVE_HALT,
+            wave_write: 0,
+            master_speed: DEFAULT_MASTER_SPEED,
+            volume: Default::default(),
+            modulation: Default::default(),
+            mod_table: [0; WAVE_SIZE],
+            mod_frequency: 0,
+            mod_accumulator: 0,
+            mod_position: 0,
+            mod_halt: true,
+            mod_counter: 0,
+            held: 0,
+        }
+    }
+}
+
+impl FdsAudio {
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4040..=0x407f => Some(self.wave[addr as usize - 0x4040] | 0x40),
+            0x4090 => Some(self.volume.gain | 0x40),
+            0x4092 => Some(self.modulation.gain | 0x40),
+            _ => None,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4040..=0x407f if self.wave_write & WAVE_WRITE != 0 => {
+                self.wave[addr as usize - 0x4040] = value & WAVE_MASK;
+            }
+            0x4080 => self.volume.write(value),
+            0x4082 => self.wave_frequency = (self.wave_frequency & 0xf00) | value as u16,
+            0x4083 => {
+                self.wave_frequency =
+                    (self.wave_frequency & 0x0ff) | ((value & FREQUENCY_HIGH_MASK) as u16) << 8;
+                self.wave_control = value;
+                if value & WAVE_HALT != 0 {
+                    self.wave_accumulator = 0;
+                    self.wave_position = 0;
+                }
+                if value & ENVELOPES_DISABLE != 0 {
+                    self.volume.timer = 0;
+                    self.modulation.timer = 0;
+                }
+            }
+            0x4084 => self.modulation.write(value),
+            0x4085 => {
+                // sign extension of the 7 bits
+                self.mod_counter = (((value & MOD_COUNTER_MASK) << 1) as i8) >> 1;
+            }
+            0x4086 => self.mod_frequency = (self.mod_frequency & 0xf00) | value as u16,
+            0x4087 => {
+                self.mod_frequency =
+                    (self.mod_frequency & 0x0ff) | ((value & FREQUENCY_HIGH_MASK) as u16) << 8;
+                self.mod_halt = value & MOD_HALT != 0;
+                if self.mod_halt {
+                    self.mod_accumulator = 0;
+                }
+            }
+            0x4088 if self.mod_halt => {
+                // each write fills two consecutive entries of the table
+                self.mod_table[self.mod_position] = value & 0b111;
+                self.mod_table[self.mod_position + 1] = value & 0b111;
+                self.mod_position = (self.mod_position + 2) % WAVE_SIZE;
+            }
+            0x4089 => {
+                if value & WAVE_WRITE != 0 {
+                    self.held = self.wave[self.wave_position];
+                }
+                self.wave_write = value;
+            }
+            0x408a => self.master_speed = value,
+            _ => {}
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u8) {
+        for _ in 0..cycles {
+            self.clock();
+        }
+    }
+
+    fn clock(&mut self) {
+        let halted = self.wave_control & WAVE_HALT != 0;
+        if !halted && self.wave_control & ENVELOPES_DISABLE == 0 {
+            self.volume.clock(self.master_speed);
+            self.modulation.clock(self.master_speed);
+        }
+
+        if !self.mod_halt && self.mod_frequency > 0 {
+            let (accumulator, overflow) = self.mod_accumulator.overflowing_add(self.mod_frequency);
+            self.mod_accumulator = accumulator;
+            if overflow {
+                self.step_modulator();
+            }
+        }
+
+        if !halted && self.wave_write & WAVE_WRITE == 0 {
+            let (accumulator, overflow) = self.wave_accumulator.overflowing_add(self.pitch());
+            self.wave_accumulator = accumulator;
+            if overflow {
+                self.wave_position = (self.wave_position + 1) % WAVE_SIZE;
+            }
+        }
+    }
+
+    fn step_modulator(&mut self) {
+        let entry = self.mod_table[self.mod_position];
+        self.mod_counter = if entry == MOD_RESET {
+            0
+        } else {
+            // wraps around the 7 bits
+            let counter = self.mod_counter.wrapping_add(MOD_STEPS[entry as usize]);
+            (counter << 1) >> 1
+        };
+        self.mod_position = (self.mod_position + 1) % WAVE_SIZE;
+    }
+
+    /// Frequency of the wave bent by the modulator, following the rounding of the hardware
+    fn pitch(&self) -> u16 {
+        if self.mod_halt {
+            return self.wave_frequency;
+        }
+        let mut temp = self.mod_counter as i32 * self.modulation.gain as i32;
+        let remainder = temp & 0xf;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if self.mod_counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+        temp *= self.wave_frequency as i32;
+        let remainder = temp & 0x3f;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        (self.wave_frequency as i32 + temp).clamp(0, u16::MAX as i32) as u16
+    }
+
+    pub fn output(&self) -> f32 {
+        let sample = if self.wave_write & WAVE_WRITE != 0 {
+            self.held
+        } else {
+            self.wave[self.wave_position]
+        };
+        let gain = self.volume.gain.min(MAX_GAIN);
+        let master = MASTER_VOLUMES[(self.wave_write & MASTER_VOLUME) as usize];
+        (sample as f32 / WAVE_MASK as f32) * (gain as f32 / MAX_GAIN as f32) * master * FULL_VOLUME
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn square() -> FdsAudio {
+        let mut audio = FdsAudio::default();
+        audio.write(0x4089, WAVE_WRITE);
+        (0..32).for_each(|i| audio.write(0x4040 + i, 0x3f));
+        audio.write(0x4089, 0);
+        audio.write(0x4080, ENVELOPE_DISABLE | MAX_GAIN);
+        audio
+    }
+
+    #[test]
+    fn wave() {
+        let mut audio = square();
+        assert_eq!(audio.read(0x4040), Some(0x7f));
+        assert_eq!(audio.read(0x4090), Some(0x60));
+        assert_eq!(audio.output(), FULL_VOLUME);
+
+        // a step each 0x10000 / 0x800 cycles
+        audio.write(0x4082, 0x00);
+        audio.write(0x4083, 0x08);
+        (0..32).for_each(|_| audio.tick(32));
+        assert_eq!(audio.wave_position, 32);
+        assert_eq!(audio.output(), 0.0);
+
+        audio.write(0x4083, WAVE_HALT);
+        assert_eq!(audio.wave_position, 0);
+        audio.write(0x4089, 3);
+        assert_eq!(audio.output(), FULL_VOLUME * 0.4);
+    }
+
+    #[test]
+    fn envelope() {
+        let mut audio = square();
+        audio.write(0x408a, 1);
+        audio.write(0x4083, 0);
+        audio.write(0x4080, ENVELOPE_INCREASE);
+        audio.tick(8);
+        assert_eq!(audio.volume.gain, MAX_GAIN);
+        audio.write(0x4080, 0);
+        audio.tick(16);
+        assert_eq!(audio.volume.gain, MAX_GAIN - 2);
+    }
+
+    #[test]
+    fn modulation() {
+        let mut audio = FdsAudio::default();
+        audio.write(0x4087, MOD_HALT);
+        for entry in [1, 1, 7, MOD_RESET] {
+            audio.write(0x4088, entry);
+        }
+        assert_eq!(audio.mod_table[..8], [1, 1, 1, 1, 7, 7, 4, 4]);
+
+        audio.mod_position = 0;
+        audio.write(0x4085, 0x3f);
+        assert_eq!(audio.mod_counter, 63);
+        audio.step_modulator();
+        assert_eq!(audio.mod_counter, -64);
+        audio.write(0x4085, 0x40);
+        assert_eq!(audio.mod_counter, -64);
+
+        audio.write(0x4086, 0xff);
+        audio.write(0x4087, 0x0f);
+        audio.write(0x4082, 0x00);
+        audio.write(0x4083, 0x01);
+        audio.write(0x4084, ENVELOPE_DISABLE | 0x20);
+        audio.write(0x4085, 0x70);
+        assert!(audio.pitch() < 0x100);
+        audio.write(0x4085, 0x10);
+        assert!(audio.pitch() > 0x100);
+    }
+}
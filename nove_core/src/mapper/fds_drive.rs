@@ -0,0 +1,379 @@
+use crate::cartridge::Mirroring;
+use crate::patch;
+use crate::Program;
+use log::warn;
+
+/// Gap at the start of a side, before the first block
+const LEADING_GAP: usize = 28300 / 8;
+/// Gap after each block
+const BLOCK_GAP: usize = 976 / 8;
+/// Mark written by the drive before the data of each block
+const BLOCK_START: u8 = 0x80;
+/// The images drop the CRCs, so the drive checks none and any value works
+const FAKE_CRC: [u8; 2] = [0x4d, 0x62];
+const DISK_INFO_SIZE: usize = 56;
+const FILE_AMOUNT_SIZE: usize = 2;
+const FILE_HEADER_SIZE: usize = 16;
+/// Offset of the file size inside the file header
+const FILE_SIZE_OFFSET: usize = 13;
+/// CPU cycles to transfer a byte, at about 96.4 kbit/s
+const BYTE_CYCLES: u32 = 150;
+/// CPU cycles for the motor to reach the start of the disk
+const MOTOR_CYCLES: u32 = 50000;
+/// CPU cycles the disk stays out of the drive when changing sides, long enough for the BIOS
+/// to notice it
+const EJECT_CYCLES: u32 = 1_000_000;
+/// Polynomial of the CRC-16 of the blocks, reversed
+const CRC_POLYNOMIAL: u16 = 0x8408;
+
+/*
+   Drive control ($4025)
+
+   7  bit  0
+   ---- ----
+   IS.C MRTD
+   | | |||||
+   | | ||||+- Drive motor on
+   | | |||+-- Transfer reset, holding the transfer at the start of the disk
+   | | ||+--- Read mode, write mode when clear
+   | | |+---- Mirroring, horizontal when set and vertical when clear
+   | | +----- Transfer the CRC while writing
+   | +------- Start transferring, the gaps are skipped until it's set
+   +--------- IRQ on each byte transferred
+*/
+const MOTOR_ON: u8 = 0b0000_0001;
+const TRANSFER_RESET: u8 = 0b0000_0010;
+const READ_MODE: u8 = 0b0000_0100;
+const HORIZONTAL_MIRRORING: u8 = 0b0000_1000;
+const CRC_CONTROL: u8 = 0b0001_0000;
+const TRANSFER_START: u8 = 0b0100_0000;
+const TRANSFER_IRQ: u8 = 0b1000_0000;
+
+/// Disk drive of the Famicom Disk System. The disk spins under the head from the start to the
+/// end of the side, transferring a byte every few cycles through the data registers.
+pub struct Drive {
+    /// Sides as the drive sees them, with the gaps and marks the images drop
+    sides: Vec<Program>,
+    original: Vec<Program>,
+    side: Option<usize>,
+    /// Side to insert once the last one is out of the drive for long enough
+    next_side: Option<usize>,
+    eject_delay: u32,
+    control: u8,
+    position: usize,
+    delay: u32,
+    end_of_head: bool,
+    scanning: bool,
+    gap_ended: bool,
+    previous_crc_control: bool,
+    crc: u16,
+    read_data: u8,
+    write_data: u8,
+    transfer_complete: bool,
+    irq: bool,
+}
+
+impl Drive {
+    pub fn new(sides: &[Program]) -> Self {
+        let sides: Vec<Program> = sides.iter().map(|side| add_gaps(side)).collect();
+        Self {
+            original: sides.clone(),
+            side: (!sides.is_empty()).then_some(0),
+            sides,
+            next_side: None,
+            eject_delay: 0,
+            control: 0,
+            position: 0,
+            delay: 0,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            previous_crc_control: false,
+            crc: 0,
+            read_data: 0,
+            write_data: 0,
+            transfer_complete: false,
+            irq: false,
+        }
+    }
+
+    pub fn sides(&self) -> usize {
+        self.sides.len()
+    }
+
+    /// Ejects the disk, inserting the side after a delay if there is one
+    pub fn insert(&mut self, side: Option<usize>) {
+        self.side = None;
+        self.next_side = side.filter(|&side| side < self.sides.len());
+        self.eject_delay = EJECT_CYCLES;
+    }
+
+    pub fn write_control(&mut self, value: u8) {
+        self.control = value;
+        self.irq = false;
+    }
+
+    pub fn write_data(&mut self, value: u8) {
+        self.write_data = value;
+        self.transfer_complete = false;
+        self.irq = false;
+    }
+
+    pub fn read_data(&mut self) -> u8 {
+        self.transfer_complete = false;
+        self.irq = false;
+        self.read_data
+    }
+
+    /// Status bits of $4030, acknowledging the transfer
+    pub fn read_status(&mut self) -> u8 {
+        let status = (self.transfer_complete as u8) << 1 | (self.end_of_head as u8) << 6;
+        self.transfer_complete = false;
+        self.irq = false;
+        status
+    }
+
+    /// Drive status of $4032: no disk, not ready and write protected
+    pub fn read_drive_status(&self) -> u8 {
+        let inserted = self.side.is_some();
+        !inserted as u8 | ((!inserted || !self.scanning) as u8) << 1 | (!inserted as u8) << 2
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        if self.control & HORIZONTAL_MIRRORING != 0 {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        }
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq
+    }
+
+    pub fn clock(&mut self) {
+        if self.eject_delay > 0 {
+            self.eject_delay -= 1;
+            if self.eject_delay == 0 {
+                self.side = self.next_side.take();
+            }
+        }
+        let Some(side) = self.side.filter(|_| self.control & MOTOR_ON != 0) else {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        };
+        if self.control & TRANSFER_RESET != 0 && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.delay = MOTOR_CYCLES;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let ready = self.control & TRANSFER_START != 0;
+        let crc_control = self.control & CRC_CONTROL != 0;
+        let mut irq = self.control & TRANSFER_IRQ != 0;
+        if self.control & READ_MODE != 0 {
+            let data = self.sides[side][self.position];
+            if !ready {
+                self.gap_ended = false;
+            } else if data != 0 && !self.gap_ended {
+                // the start mark of the block is read without an IRQ
+                self.gap_ended = true;
+                irq = false;
+            }
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = data;
+                self.irq |= irq;
+            }
+        } else {
+            let mut data = 0;
+            if !crc_control {
+                self.transfer_complete = true;
+                data = self.write_data;
+                self.irq |= irq;
+            }
+            if !ready {
+                data = 0;
+                self.crc = 0;
+            }
+            if !crc_control {
+                self.update_crc(data);
+            } else {
+                if !self.previous_crc_control {
+                    self.update_crc(0);
+                    self.update_crc(0);
+                }
+                data = self.crc as u8;
+                self.crc >>= 8;
+            }
+            self.sides[side][self.position] = data;
+            self.gap_ended = false;
+        }
+        self.previous_crc_control = crc_control;
+
+        self.position += 1;
+        if self.position >= self.sides[side].len() {
+            self.control &= !MOTOR_ON;
+        } else {
+            self.delay = BYTE_CYCLES;
+        }
+    }
+
+    fn update_crc(&mut self, value: u8) {
+        for bit in 0..8 {
+            let carry = self.crc & 1 != 0;
+            self.crc >>= 1;
+            if carry {
+                self.crc ^= CRC_POLYNOMIAL;
+            }
+            if value & (1 << bit) != 0 {
+                self.crc ^= 0x8000;
+            }
+        }
+    }
+
+    /// Changes made to the disks, as an IPS patch of their sides. `None` while untouched.
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        if self.sides == self.original {
+            return None;
+        }
+        patch::create_ips(&self.original.concat(), &self.sides.concat())
+            .inspect_err(|err| warn!("failed to save the disk: {err}"))
+            .ok()
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        let disk = match patch::apply(&self.original.concat(), data) {
+            Ok(disk) => disk,
+            Err(err) => return warn!("failed to load the disk save: {err}"),
+        };
+        let mut start = 0;
+        for side in self.sides.iter_mut() {
+            let end = (start + side.len()).min(disk.len());
+            side[..end - start].copy_from_slice(&disk[start..end]);
+            start = end;
+        }
+    }
+}
+
+/// Rebuilds the side of a disk from an image, with the gaps between the blocks and the marks
+/// and CRCs around their data
+fn add_gaps(side: &[u8]) -> Program {
+    let mut disk = vec![0; LEADING_GAP];
+    let mut pos = 0;
+    while pos < side.len() {
+        let len = match side[pos] {
+            1 => DISK_INFO_SIZE,
+            2 => FILE_AMOUNT_SIZE,
+            3 => FILE_HEADER_SIZE,
+            4 if pos >= FILE_HEADER_SIZE => {
+                let size = &side[pos - FILE_HEADER_SIZE + FILE_SIZE_OFFSET..];
+                1 + u16::from_le_bytes([size[0], size[1]]) as usize
+            }
+            _ => break,
+        };
+        let end = (pos + len).min(side.len());
+        disk.push(BLOCK_START);
+        disk.extend(&side[pos..end]);
+        disk.extend(FAKE_CRC);
+        disk.extend([0; BLOCK_GAP]);
+        pos = end;
+    }
+    // the unused space of the side is left as a gap for the new files
+    disk.resize(disk.len() + side.len() - pos, 0);
+    disk
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::is_fds_image;
+
+    fn side() -> Program {
+        let mut side = b"\x01*NINTENDO-HVC*".to_vec();
+        side.resize(DISK_INFO_SIZE, 0);
+        side.extend([2, 1]);
+        let mut header = vec![3; FILE_HEADER_SIZE];
+        header[FILE_SIZE_OFFSET..FILE_SIZE_OFFSET + 2].copy_from_slice(&[3, 0]);
+        side.extend(header);
+        side.extend([4, 0xaa, 0xbb, 0xcc]);
+        side.resize(0x100, 0);
+        side
+    }
+
+    /// Runs the drive until it transfers a byte, returning it and if it raised the IRQ
+    fn next_byte(drive: &mut Drive) -> (u8, bool) {
+        while !drive.transfer_complete {
+            drive.clock();
+        }
+        let irq = drive.irq();
+        (drive.read_data(), irq)
+    }
+
+    #[test]
+    fn gaps() {
+        let side = side();
+        assert!(is_fds_image(&side));
+        let disk = add_gaps(&side);
+        assert_eq!(disk[..LEADING_GAP], [0; LEADING_GAP]);
+        assert_eq!(disk[LEADING_GAP], BLOCK_START);
+        assert_eq!(disk[LEADING_GAP + 1..LEADING_GAP + 16], side[..15]);
+        let file = disk.len() - (0x100 - 78) - BLOCK_GAP - 7;
+        assert_eq!(
+            disk[file..file + 6],
+            [BLOCK_START, 4, 0xaa, 0xbb, 0xcc, FAKE_CRC[0]]
+        );
+        assert_eq!(disk.len(), LEADING_GAP + 4 * (BLOCK_GAP + 3) + 0x100);
+    }
+
+    #[test]
+    fn read_blocks() {
+        let mut drive = Drive::new(&[side()]);
+        assert_eq!(drive.read_drive_status() & 0b101, 0);
+        drive.write_control(MOTOR_ON | READ_MODE | TRANSFER_START | TRANSFER_IRQ);
+        assert_eq!(next_byte(&mut drive), (BLOCK_START, false));
+        assert_eq!(next_byte(&mut drive), (0x01, true));
+        assert_eq!(next_byte(&mut drive), (b'*', true));
+        assert_eq!(drive.read_drive_status(), 0);
+    }
+
+    #[test]
+    fn write_and_save() {
+        let mut drive = Drive::new(&[side()]);
+        drive.write_control(MOTOR_ON | READ_MODE | TRANSFER_START);
+        next_byte(&mut drive);
+        next_byte(&mut drive);
+        drive.write_control(MOTOR_ON | TRANSFER_START);
+        drive.write_data(0x55);
+        next_byte(&mut drive);
+        assert_eq!(drive.sides[0][LEADING_GAP + 2], 0x55);
+
+        let save = drive.save_data().unwrap();
+        let mut loaded = Drive::new(&[side()]);
+        assert_eq!(loaded.save_data(), None);
+        loaded.load_save_data(&save);
+        assert_eq!(loaded.sides, drive.sides);
+    }
+
+    #[test]
+    fn swap_sides() {
+        let mut drive = Drive::new(&[side(), side()]);
+        drive.insert(Some(1));
+        drive.clock();
+        assert_eq!(drive.read_drive_status(), 0b111);
+        (0..EJECT_CYCLES).for_each(|_| drive.clock());
+        assert_eq!(drive.side, Some(1));
+        assert_eq!(drive.read_drive_status() & 0b101, 0);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::chr::Chr;
+use crate::mapper::sunsoft5b::Sunsoft5b;
+use crate::mapper::{bank_from_end, load_memory, new_prg_ram, read_bank, Mapper, PRG_BANK_8K};
+use crate::Program;
+
+const PRG_RAM_START: u16 = 0x6000;
+const CHR_BANK_1K: usize = 0x400;
+
+const COMMAND_MASK: u8 = 0b0000_1111;
+const PRG_BANK_MASK: u8 = 0b0011_1111;
+
+/*
+   PRG bank at $6000 (command $8)
+
+   7  bit  0
+   ---- ----
+   ERbB BBBB
+   |||| ||||
+   ||++-++++- 8 KiB bank
+   |+-------- RAM instead of ROM
+   +--------- RAM enable
+*/
+const PRG_RAM_ENABLE: u8 = 0b1000_0000;
+const PRG_RAM_SELECT: u8 = 0b0100_0000;
+
+const IRQ_ENABLE: u8 = 0b0000_0001;
+const IRQ_COUNTER_ENABLE: u8 = 0b1000_0000;
+
+/// Mapper 69, the Sunsoft FME-7 and its 5B variant with expansion audio. Its registers are set
+/// writing the command number to $8000 and its parameter to $A000.
+pub struct Fme7 {
+    prg_rom: Program,
+    chr: Chr,
+    prg_ram: Vec<u8>,
+    battery: bool,
+    command: u8,
+    chr_banks: [u8; 8],
+    /// Banks for $6000, $8000, $A000 and $C000
+    prg_banks: [u8; 4],
+    mirroring: Mirroring,
+    irq_control: u8,
+    irq_counter: u16,
+    irq_pending: bool,
+    audio: Sunsoft5b,
+}
+
+impl Fme7 {
+    pub fn new(rom: Rom) -> Self {
+        Self {
+            prg_ram: new_prg_ram(&rom, rom.prg_ram_total().max(PRG_BANK_8K)),
+            battery: rom.battery,
+            chr: Chr::new(&rom),
+            prg_rom: rom.prg_rom,
+            command: 0,
+            chr_banks: [0; 8],
+            prg_banks: [0; 4],
+            mirroring: rom.screen_mirroring,
+            irq_control: 0,
+            irq_counter: 0,
+            irq_pending: false,
+            audio: Default::default(),
+        }
+    }
+
+    fn write_parameter(&mut self, value: u8) {
+        match self.command {
+            0..=7 => self.chr_banks[self.command as usize] = value,
+            8..=0xb => self.prg_banks[self.command as usize - 8] = value,
+            0xc => {
+                self.mirroring = match value & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                }
+            }
+            0xd => {
+                self.irq_control = value;
+                self.irq_pending = false;
+            }
+            0xe => self.irq_counter = (self.irq_counter & 0xff00) | value as u16,
+            _ => self.irq_counter = (self.irq_counter & 0x00ff) | (value as u16) << 8,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        let bank = self.prg_banks[0];
+        bank & PRG_RAM_SELECT != 0 && bank & PRG_RAM_ENABLE != 0
+    }
+
+    fn prg_ram_addr(&self, addr: u16) -> usize {
+        let bank = (self.prg_banks[0] & PRG_BANK_MASK) as usize;
+        (bank * PRG_BANK_8K + (addr - PRG_RAM_START) as usize % PRG_BANK_8K) % self.prg_ram.len()
+    }
+}
+
+impl Mapper for Fme7 {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        let bank = match addr {
+            0xe000.. => bank_from_end(&self.prg_rom, PRG_BANK_8K, 1),
+            PRG_ROM_START.. => {
+                let slot = (addr - PRG_ROM_START) as usize / PRG_BANK_8K;
+                (self.prg_banks[slot + 1] & PRG_BANK_MASK) as usize
+            }
+            PRG_RAM_START.. if self.prg_banks[0] & PRG_RAM_SELECT == 0 => {
+                (self.prg_banks[0] & PRG_BANK_MASK) as usize
+            }
+            PRG_RAM_START.. if self.prg_ram_enabled() => {
+                return Some(self.prg_ram[self.prg_ram_addr(addr)]);
+            }
+            _ => return None,
+        };
+        Some(read_bank(&self.prg_rom, bank, PRG_BANK_8K, addr))
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x8000..=0x9fff => self.command = value & COMMAND_MASK,
+            0xa000..=0xbfff => self.write_parameter(value),
+            0xc000..=0xdfff => self.audio.select(value),
+            0xe000..=0xffff => self.audio.write(value),
+            PRG_RAM_START.. if self.prg_ram_enabled() => {
+                let i = self.prg_ram_addr(addr);
+                self.prg_ram[i] = value;
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        let bank = self.chr_banks[addr as usize / CHR_BANK_1K] as usize;
+        self.chr.read(bank, CHR_BANK_1K, addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        let bank = self.chr_banks[addr as usize / CHR_BANK_1K] as usize;
+        self.chr.write(bank, CHR_BANK_1K, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        self.audio.tick(cycles);
+        if self.irq_control & IRQ_COUNTER_ENABLE == 0 {
+            return;
+        }
+        let (counter, wrapped) = self.irq_counter.overflowing_sub(cycles as u16);
+        self.irq_counter = counter;
+        if wrapped && self.irq_control & IRQ_ENABLE != 0 {
+            self.irq_pending = true;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.prg_ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_memory(&mut self.prg_ram, data)
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::CHR_BANK_8K;
+
+    fn fme7() -> Fme7 {
+        Fme7::new(Rom {
+            prg_rom: (0..32).flat_map(|i| vec![i; PRG_BANK_8K]).collect(),
+            chr_rom: (0..=255).flat_map(|i| vec![i; CHR_BANK_1K]).collect(),
+            prg_ram_size: 2 * PRG_BANK_8K,
+            ..Default::default()
+        })
+    }
+
+    fn command(fme7: &mut Fme7, command: u8, parameter: u8) {
+        fme7.write_prg(0x8000, command);
+        fme7.write_prg(0xa000, parameter);
+    }
+
+    #[test]
+    fn banks() {
+        let mut fme7 = fme7();
+        command(&mut fme7, 0x9, 3);
+        command(&mut fme7, 0xa, 4);
+        command(&mut fme7, 0xb, 5);
+        command(&mut fme7, 0x7, 200);
+        command(&mut fme7, 0xc, 3);
+        assert_eq!(fme7.read_prg(0x8000), Some(3));
+        assert_eq!(fme7.read_prg(0xa000), Some(4));
+        assert_eq!(fme7.read_prg(0xc000), Some(5));
+        assert_eq!(fme7.read_prg(0xe000), Some(31));
+        assert_eq!(fme7.read_chr(0x1c00), 200);
+        assert_eq!(fme7.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn prg_ram() {
+        let mut fme7 = fme7();
+        command(&mut fme7, 0x8, 6);
+        assert_eq!(fme7.read_prg(0x6000), Some(6));
+
+        command(&mut fme7, 0x8, PRG_RAM_SELECT);
+        assert_eq!(fme7.read_prg(0x6000), None);
+        fme7.write_prg(0x6000, 1);
+
+        command(&mut fme7, 0x8, PRG_RAM_ENABLE | PRG_RAM_SELECT | 1);
+        fme7.write_prg(0x6000, 2);
+        assert_eq!(fme7.prg_ram, {
+            let mut ram = vec![0; 2 * PRG_BANK_8K];
+            ram[PRG_BANK_8K] = 2;
+            ram
+        });
+    }
+
+    #[test]
+    fn irq() {
+        let mut fme7 = fme7();
+        command(&mut fme7, 0xe, 5);
+        command(&mut fme7, 0xf, 0);
+        command(&mut fme7, 0xd, IRQ_ENABLE | IRQ_COUNTER_ENABLE);
+        fme7.tick(5);
+        assert!(!fme7.irq());
+        fme7.tick(1);
+        assert!(fme7.irq());
+        assert_eq!(fme7.irq_counter, 0xffff);
+
+        command(&mut fme7, 0xd, IRQ_COUNTER_ENABLE);
+        assert!(!fme7.irq());
+        fme7.tick(255);
+        fme7.tick(255);
+        assert_eq!(fme7.irq_counter, 0xffff - 510);
+    }
+
+    #[test]
+    fn audio() {
+        let mut fme7 = fme7();
+        fme7.write_prg(0xc000, 7);
+        fme7.write_prg(0xe000, 0b11_1110);
+        fme7.write_prg(0xc000, 8);
+        fme7.write_prg(0xe000, 15);
+        fme7.tick(16);
+        assert!(fme7.audio_output() > 0.0);
+    }
+
+    #[test]
+    fn chr_ram_banks() {
+        let mut fme7 = Fme7::new(Rom {
+            chr_ram_size: CHR_BANK_8K,
+            ..Default::default()
+        });
+        for command in [0, 1] {
+            fme7.write_prg(0x8000, command);
+            fme7.write_prg(0xa000, 3);
+        }
+        fme7.write_chr(0x0012, 5);
+        assert_eq!(fme7.read_chr(0x0412), 5);
+        fme7.write_prg(0x8000, 0);
+        fme7.write_prg(0xa000, 4);
+        assert_eq!(fme7.read_chr(0x0012), 0);
+    }
+}
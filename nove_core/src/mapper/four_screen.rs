@@ -0,0 +1,120 @@
+use crate::cartridge::Mirroring;
+use crate::mapper::Mapper;
+
+/// Four nametables of 1 KiB
+const FOUR_SCREEN_VRAM_SIZE: usize = 0x1000;
+
+/// Boards wired for four-screen mirroring, carrying their own 4 KiB of VRAM to hold the four
+/// nametables instead of the 2 KiB of the console. Wraps the mapper of the board, that keeps
+/// deciding everything else.
+pub struct FourScreen<M: Mapper> {
+    mapper: M,
+    vram: [u8; FOUR_SCREEN_VRAM_SIZE],
+}
+
+impl<M: Mapper> FourScreen<M> {
+    pub fn new(mapper: M) -> Self {
+        Self {
+            mapper,
+            vram: [0; FOUR_SCREEN_VRAM_SIZE],
+        }
+    }
+}
+
+impl<M: Mapper> Mapper for FourScreen<M> {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        self.mapper.read_prg(addr)
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        self.mapper.write_prg(addr, value)
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.mapper.read_chr(addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        self.mapper.write_chr(addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mapper.mirroring()
+    }
+
+    fn read_nametable(&self, addr: u16, vram: &[u8]) -> u8 {
+        match self.mirroring() {
+            Mirroring::FourScreen => self.vram[Mirroring::FourScreen.vram_addr(addr)],
+            _ => self.mapper.read_nametable(addr, vram),
+        }
+    }
+
+    fn write_nametable(&mut self, addr: u16, value: u8, vram: &mut [u8]) {
+        match self.mirroring() {
+            Mirroring::FourScreen => self.vram[Mirroring::FourScreen.vram_addr(addr)] = value,
+            _ => self.mapper.write_nametable(addr, value, vram),
+        }
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        self.mapper.tick(cycles)
+    }
+
+    fn notify_ppu_addr(&mut self, addr: u16) {
+        self.mapper.notify_ppu_addr(addr)
+    }
+
+    fn notify_cpu_write(&mut self, addr: u16, value: u8) {
+        self.mapper.notify_cpu_write(addr, value)
+    }
+
+    fn irq(&self) -> bool {
+        self.mapper.irq()
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.mapper.save_data()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.mapper.load_save_data(data)
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.mapper.audio_output()
+    }
+
+    fn disk_sides(&self) -> usize {
+        self.mapper.disk_sides()
+    }
+
+    fn insert_disk(&mut self, side: Option<usize>) {
+        self.mapper.insert_disk(side)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::Rom;
+    use crate::mapper::nrom::Nrom;
+
+    #[test]
+    fn four_nametables() {
+        let mut mapper = FourScreen::new(Nrom::new(Rom {
+            prg_rom: vec![0; 0x4000],
+            chr_rom: vec![0; 0x2000],
+            screen_mirroring: Mirroring::FourScreen,
+            ..Default::default()
+        }));
+        let mut vram = [0; 0x800];
+        for (i, addr) in [0x2000, 0x2400, 0x2800, 0x2c00].into_iter().enumerate() {
+            mapper.write_nametable(addr + 5, i as u8 + 1, &mut vram);
+        }
+        assert_eq!(vram, [0; 0x800]);
+        assert_eq!(mapper.read_nametable(0x2005, &vram), 1);
+        assert_eq!(mapper.read_nametable(0x2405, &vram), 2);
+        assert_eq!(mapper.read_nametable(0x2805, &vram), 3);
+        assert_eq!(mapper.read_nametable(0x3c05, &vram), 4);
+    }
+}
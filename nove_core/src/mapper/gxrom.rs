@@ -0,0 +1,89 @@
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::chr::Chr;
+use crate::mapper::prg_ram::PrgRam;
+use crate::mapper::{read_bank, Mapper, CHR_BANK_8K, PRG_BANK_32K};
+use crate::Program;
+
+const PRG_BANK_MASK: u8 = 0b0011_0000;
+const CHR_BANK_MASK: u8 = 0b0000_0011;
+
+/// Mapper 66, a single register selecting both a 32 KiB PRG bank and an 8 KiB CHR bank
+pub struct Gxrom {
+    prg_rom: Program,
+    chr: Chr,
+    prg_ram: PrgRam,
+    mirroring: Mirroring,
+    prg_bank: usize,
+    chr_bank: usize,
+}
+
+impl Gxrom {
+    pub fn new(rom: Rom) -> Self {
+        Self {
+            chr: Chr::new(&rom),
+            prg_ram: PrgRam::new(&rom),
+            prg_rom: rom.prg_rom,
+            mirroring: rom.screen_mirroring,
+            prg_bank: 0,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Gxrom {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START.. => Some(read_bank(&self.prg_rom, self.prg_bank, PRG_BANK_32K, addr)),
+            _ => self.prg_ram.read(addr),
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        if addr >= PRG_ROM_START {
+            self.prg_bank = ((value & PRG_BANK_MASK) >> 4) as usize;
+            self.chr_bank = (value & CHR_BANK_MASK) as usize;
+        } else {
+            self.prg_ram.write(addr, value);
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr.read(self.chr_bank, CHR_BANK_8K, addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        self.chr.write(self.chr_bank, CHR_BANK_8K, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.prg_ram.save_data()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.prg_ram.load_save_data(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bank_switching() {
+        let prg_rom: Program = (0..4).flat_map(|i| vec![i; PRG_BANK_32K]).collect();
+        let chr_rom: Program = (0..4).flat_map(|i| vec![i; CHR_BANK_8K]).collect();
+        let mut gxrom = Gxrom::new(Rom {
+            prg_rom,
+            chr_rom,
+            ..Default::default()
+        });
+        gxrom.write_prg(0x8000, 0b0010_0011);
+        assert_eq!(gxrom.read_prg(0x8000), Some(2));
+        assert_eq!(gxrom.read_chr(0x0000), 3);
+    }
+}
@@ -0,0 +1,351 @@
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::chr::Chr;
+use crate::mapper::{
+    bank_addr, load_memory, new_prg_ram, read_bank, Mapper, CHR_BANK_4K, CHR_BANK_8K, PRG_BANK_16K,
+    PRG_BANK_8K,
+};
+use crate::Program;
+
+const PRG_RAM_START: u16 = 0x6000;
+const OUTER_BANK_ROM_SIZE: usize = 0x40000; // 256 KiB
+
+const SHIFT_RESET: u8 = 0b1000_0000;
+const SHIFT_INIT: u8 = 0b0001_0000;
+const CONTROL_INIT: u8 = 0b0000_1100;
+
+const MIRRORING_MASK: u8 = 0b0000_0011;
+const PRG_MODE_MASK: u8 = 0b0000_1100;
+const CHR_MODE_4K: u8 = 0b0001_0000;
+const PRG_BANK_MASK: u8 = 0b0000_1111;
+const PRG_RAM_DISABLE: u8 = 0b0001_0000;
+
+/*
+   Some SxROM boards reuse the CHR bank 0 bits that CHR RAM doesn't need
+
+   4bit0
+   -----
+   ERR..
+   |||
+   ||+--- SXROM: PRG RAM bank, low bit
+   |+---- SOROM: PRG RAM bank; SXROM: PRG RAM bank, high bit
+   +----- SNROM: PRG RAM disable; SUROM/SXROM: 256 KiB PRG ROM outer bank
+*/
+const SNROM_RAM_DISABLE: u8 = 0b0001_0000;
+const SUROM_OUTER_BANK: u8 = 0b0001_0000;
+
+#[derive(Debug, PartialEq)]
+enum Board {
+    Standard,
+    Snrom,
+    Sorom,
+    Surom,
+    Sxrom,
+}
+
+impl Board {
+    fn detect(rom: &Rom) -> Self {
+        let large_prg = rom.prg_rom.len() > OUTER_BANK_ROM_SIZE;
+        match (large_prg, rom.prg_ram_total() / PRG_BANK_8K) {
+            (true, 4) => Board::Sxrom,
+            (true, _) => Board::Surom,
+            (false, 2) => Board::Sorom,
+            (false, _) if rom.chr_rom.is_empty() => Board::Snrom,
+            _ => Board::Standard,
+        }
+    }
+}
+
+/// Mapper 1, the Nintendo MMC1. Its registers are loaded one bit at a time through a serial port
+/// on $8000-$FFFF, the address of the fifth write selecting the register to set.
+pub struct Mmc1 {
+    prg_rom: Program,
+    chr: Chr,
+    prg_ram: Vec<u8>,
+    battery: bool,
+    board: Board,
+    shift: u8,
+    control: u8,
+    chr_banks: [u8; 2],
+    prg_bank: u8,
+    written: bool,
+}
+
+impl Mmc1 {
+    pub fn new(rom: Rom) -> Self {
+        Self {
+            board: Board::detect(&rom),
+            chr: Chr::new(&rom),
+            prg_ram: new_prg_ram(&rom, rom.prg_ram_total().max(PRG_BANK_8K)),
+            battery: rom.battery,
+            prg_rom: rom.prg_rom,
+            shift: SHIFT_INIT,
+            control: CONTROL_INIT,
+            chr_banks: [0; 2],
+            prg_bank: 0,
+            written: false,
+        }
+    }
+
+    fn write_serial(&mut self, addr: u16, value: u8) {
+        // the MMC1 ignores writes on consecutive cycles, like the dummy write of RMW instructions
+        if std::mem::replace(&mut self.written, true) {
+            return;
+        }
+
+        if value & SHIFT_RESET != 0 {
+            self.shift = SHIFT_INIT;
+            self.control |= CONTROL_INIT;
+            return;
+        }
+
+        let full = self.shift & 1 != 0;
+        self.shift = (self.shift >> 1) | ((value & 1) << 4);
+        if full {
+            match addr {
+                0x8000..=0x9fff => self.control = self.shift,
+                0xa000..=0xbfff => self.chr_banks[0] = self.shift,
+                0xc000..=0xdfff => self.chr_banks[1] = self.shift,
+                _ => self.prg_bank = self.shift,
+            }
+            self.shift = SHIFT_INIT;
+        }
+    }
+
+    fn prg_bank(&self, addr: u16) -> usize {
+        let bank = (self.prg_bank & PRG_BANK_MASK) as usize;
+        let high = addr >= 0xc000;
+        let bank = match ((self.control & PRG_MODE_MASK) >> 2, high) {
+            (0 | 1, _) => (bank & !1) | high as usize,
+            (2, false) => 0,
+            (3, true) => PRG_BANK_MASK as usize,
+            _ => bank,
+        };
+        let outer = match self.board {
+            Board::Surom | Board::Sxrom if self.chr_banks[0] & SUROM_OUTER_BANK != 0 => {
+                OUTER_BANK_ROM_SIZE / PRG_BANK_16K
+            }
+            _ => 0,
+        };
+        outer + bank
+    }
+
+    /// Bank and bank size of the CHR mapped on `addr`
+    fn chr_bank(&self, addr: u16) -> (usize, usize) {
+        if self.control & CHR_MODE_4K == 0 {
+            ((self.chr_banks[0] >> 1) as usize, CHR_BANK_8K)
+        } else {
+            (
+                self.chr_banks[addr as usize / CHR_BANK_4K] as usize,
+                CHR_BANK_4K,
+            )
+        }
+    }
+
+    fn prg_ram_addr(&self, addr: u16) -> Option<usize> {
+        let disabled = self.prg_bank & PRG_RAM_DISABLE != 0
+            || (self.board == Board::Snrom && self.chr_banks[0] & SNROM_RAM_DISABLE != 0);
+        if disabled {
+            return None;
+        }
+        let bank = match self.board {
+            Board::Sorom => (self.chr_banks[0] >> 3) & 1,
+            Board::Sxrom => (self.chr_banks[0] >> 2) & 0b11,
+            _ => 0,
+        };
+        Some(bank_addr(&self.prg_ram, bank as usize, PRG_BANK_8K, addr))
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START.. => Some(read_bank(
+                &self.prg_rom,
+                self.prg_bank(addr),
+                PRG_BANK_16K,
+                addr,
+            )),
+            PRG_RAM_START.. => self.prg_ram_addr(addr).map(|i| self.prg_ram[i]),
+            _ => None,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            PRG_ROM_START.. => self.write_serial(addr, value),
+            PRG_RAM_START.. => {
+                if let Some(i) = self.prg_ram_addr(addr) {
+                    self.prg_ram[i] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        let (bank, bank_size) = self.chr_bank(addr);
+        self.chr.read(bank, bank_size, addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        let (bank, bank_size) = self.chr_bank(addr);
+        self.chr.write(bank, bank_size, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & MIRRORING_MASK {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn tick(&mut self, _cycles: u8) {
+        self.written = false;
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.prg_ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_memory(&mut self.prg_ram, data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mmc1(prg_banks: u8, chr_rom: Program, prg_ram_size: usize) -> Mmc1 {
+        Mmc1::new(Rom {
+            prg_rom: (0..prg_banks).flat_map(|i| vec![i; PRG_BANK_16K]).collect(),
+            chr_rom,
+            prg_ram_size,
+            ..Default::default()
+        })
+    }
+
+    fn write_register(mmc1: &mut Mmc1, addr: u16, value: u8) {
+        for i in 0..5 {
+            mmc1.write_prg(addr, value >> i);
+            mmc1.tick(1);
+        }
+    }
+
+    #[test]
+    fn power_on_fixes_last_bank() {
+        let mut mmc1 = mmc1(8, vec![], PRG_BANK_8K);
+        assert_eq!(mmc1.read_prg(0x8000), Some(0));
+        assert_eq!(mmc1.read_prg(0xc000), Some(7));
+    }
+
+    #[test]
+    fn prg_modes() {
+        let mut mmc1 = mmc1(8, vec![], PRG_BANK_8K);
+        write_register(&mut mmc1, 0xe000, 5);
+        assert_eq!(mmc1.read_prg(0x8000), Some(5));
+        assert_eq!(mmc1.read_prg(0xc000), Some(7));
+
+        write_register(&mut mmc1, 0x8000, 0b0_1000);
+        assert_eq!(mmc1.read_prg(0x8000), Some(0));
+        assert_eq!(mmc1.read_prg(0xc000), Some(5));
+
+        write_register(&mut mmc1, 0x8000, 0b0_0000);
+        assert_eq!(mmc1.read_prg(0x8000), Some(4));
+        assert_eq!(mmc1.read_prg(0xc000), Some(5));
+    }
+
+    #[test]
+    fn reset_and_consecutive_writes() {
+        let mut mmc1 = mmc1(8, vec![], PRG_BANK_8K);
+        write_register(&mut mmc1, 0x8000, 0b0_0000);
+        mmc1.write_prg(0xe000, 1);
+        mmc1.tick(1);
+        mmc1.write_prg(0xe000, SHIFT_RESET);
+        // ignored, like the second write of an INC
+        mmc1.write_prg(0xe000, 1);
+        mmc1.tick(1);
+        assert_eq!(mmc1.shift, SHIFT_INIT);
+        assert_eq!(mmc1.control, CONTROL_INIT);
+    }
+
+    #[test]
+    fn chr_modes() {
+        let chr_rom: Program = (0..8).flat_map(|i| vec![i; CHR_BANK_4K]).collect();
+        let mut mmc1 = mmc1(2, chr_rom, PRG_BANK_8K);
+        write_register(&mut mmc1, 0xa000, 3);
+        assert_eq!(mmc1.read_chr(0x0000), 2);
+        assert_eq!(mmc1.read_chr(0x1000), 3);
+
+        write_register(&mut mmc1, 0x8000, CHR_MODE_4K | 0b10);
+        write_register(&mut mmc1, 0xc000, 6);
+        assert_eq!(mmc1.read_chr(0x0000), 3);
+        assert_eq!(mmc1.read_chr(0x1000), 6);
+        assert_eq!(mmc1.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn chr_ram() {
+        let mut mmc1 = Mmc1::new(Rom {
+            prg_rom: vec![0; PRG_BANK_16K],
+            chr_ram_size: CHR_BANK_8K,
+            ..Default::default()
+        });
+        write_register(&mut mmc1, 0x8000, CHR_MODE_4K);
+        write_register(&mut mmc1, 0xc000, 0);
+        mmc1.write_chr(0x1010, 0x34);
+        assert_eq!(mmc1.read_chr(0x0010), 0x34);
+        assert_eq!(mmc1.read_chr(0x1010), 0x34);
+    }
+
+    #[test]
+    fn prg_ram() {
+        let mut mmc1 = mmc1(2, vec![0; CHR_BANK_8K], PRG_BANK_8K);
+        mmc1.write_prg(0x6001, 0x12);
+        assert_eq!(mmc1.read_prg(0x6001), Some(0x12));
+        write_register(&mut mmc1, 0xe000, PRG_RAM_DISABLE);
+        assert_eq!(mmc1.read_prg(0x6001), None);
+    }
+
+    #[test]
+    fn snrom_ram_disable() {
+        let mut mmc1 = mmc1(2, vec![], PRG_BANK_8K);
+        assert_eq!(mmc1.board, Board::Snrom);
+        write_register(&mut mmc1, 0xa000, SNROM_RAM_DISABLE);
+        assert_eq!(mmc1.read_prg(0x6000), None);
+    }
+
+    #[test]
+    fn sorom_ram_banks() {
+        let mut mmc1 = mmc1(2, vec![], 2 * PRG_BANK_8K);
+        assert_eq!(mmc1.board, Board::Sorom);
+        mmc1.write_prg(0x6000, 1);
+        write_register(&mut mmc1, 0xa000, 0b0_1000);
+        mmc1.write_prg(0x6000, 2);
+        assert_eq!(mmc1.read_prg(0x6000), Some(2));
+        write_register(&mut mmc1, 0xa000, 0b0_0000);
+        assert_eq!(mmc1.read_prg(0x6000), Some(1));
+    }
+
+    #[test]
+    fn surom_outer_bank() {
+        let mut mmc1 = mmc1(32, vec![], PRG_BANK_8K);
+        assert_eq!(mmc1.board, Board::Surom);
+        assert_eq!(mmc1.read_prg(0xc000), Some(15));
+        write_register(&mut mmc1, 0xa000, SUROM_OUTER_BANK);
+        assert_eq!(mmc1.read_prg(0x8000), Some(16));
+        assert_eq!(mmc1.read_prg(0xc000), Some(31));
+    }
+
+    #[test]
+    fn sxrom_ram_banks() {
+        let mut mmc1 = mmc1(32, vec![], 4 * PRG_BANK_8K);
+        assert_eq!(mmc1.board, Board::Sxrom);
+        write_register(&mut mmc1, 0xa000, 0b0_1100);
+        mmc1.write_prg(0x7fff, 3);
+        assert_eq!(mmc1.prg_ram[4 * PRG_BANK_8K - 1], 3);
+    }
+}
@@ -0,0 +1,255 @@
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::chr::Chr;
+use crate::mapper::{
+    bank_from_end, load_memory, new_prg_ram, read_bank, Mapper, CHR_BANK_4K, PRG_BANK_16K,
+    PRG_BANK_8K,
+};
+use crate::Program;
+
+const PRG_RAM_START: u16 = 0x6000;
+const PRG_BANK_MASK: u8 = 0b0000_1111;
+const CHR_BANK_MASK: u8 = 0b0001_1111;
+
+const LATCH_FD: u8 = 0xfd;
+const LATCH_FE: u8 = 0xfe;
+
+/// Both chips share the CHR latches, differing in the PRG banking and the latch 0 triggers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Chip {
+    /// Mapper 9, 8 KiB switchable PRG bank and latch 0 only triggered by $0FD8 and $0FE8
+    Mmc2,
+    /// Mapper 10, 16 KiB switchable PRG bank and PRG RAM
+    Mmc4,
+}
+
+/// Nintendo MMC2 and MMC4. Each pattern table has two CHR banks selected by a latch that flips
+/// when the PPU fetches the high plane of the tiles $FD or $FE, background or sprites, letting
+/// a frame use more tiles than fit in a pattern table.
+pub struct Mmc2 {
+    prg_rom: Program,
+    chr: Chr,
+    prg_ram: Vec<u8>,
+    battery: bool,
+    chip: Chip,
+    prg_bank: u8,
+    /// CHR banks for the latch values $FD and $FE of each pattern table
+    chr_banks: [[u8; 2]; 2],
+    latches: [u8; 2],
+    /// Last address on the PPU bus, which flips the latches once its fetch is done
+    ppu_addr: u16,
+    mirroring: Mirroring,
+}
+
+impl Mmc2 {
+    pub fn new(rom: Rom, chip: Chip) -> Self {
+        let prg_ram = match chip {
+            Chip::Mmc2 => vec![],
+            Chip::Mmc4 => new_prg_ram(&rom, rom.prg_ram_total().max(PRG_BANK_8K)),
+        };
+        Self {
+            chr: Chr::new(&rom),
+            prg_rom: rom.prg_rom,
+            prg_ram,
+            battery: rom.battery,
+            chip,
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [LATCH_FE; 2],
+            ppu_addr: 0,
+            mirroring: rom.screen_mirroring,
+        }
+    }
+
+    fn read_rom(&self, addr: u16) -> u8 {
+        let (bank_size, switchable) = match self.chip {
+            Chip::Mmc2 => (PRG_BANK_8K, 0x8000..0xa000),
+            Chip::Mmc4 => (PRG_BANK_16K, 0x8000..0xc000),
+        };
+        let bank = if switchable.contains(&addr) {
+            self.prg_bank as usize
+        } else {
+            // the rest of the space is fixed to the last banks
+            let fixed = (0x10000 - switchable.end as usize) / bank_size;
+            let slot = (addr - switchable.end) as usize / bank_size;
+            bank_from_end(&self.prg_rom, bank_size, fixed - slot)
+        };
+        read_bank(&self.prg_rom, bank, bank_size, addr)
+    }
+
+    /// CHR bank selected by the latch of the pattern table of `addr`
+    fn chr_bank(&self, addr: u16) -> usize {
+        let table = addr as usize / CHR_BANK_4K;
+        let latch = (self.latches[table] - LATCH_FD) as usize;
+        self.chr_banks[table][latch] as usize
+    }
+
+    fn update_latch(&mut self, addr: u16) {
+        let latch_0_range = match self.chip {
+            Chip::Mmc2 => 0,
+            Chip::Mmc4 => 7,
+        };
+        match addr {
+            0x0fd8 => self.latches[0] = LATCH_FD,
+            0x0fe8 => self.latches[0] = LATCH_FE,
+            0x0fd9..=0x0fdf if addr - 0x0fd8 <= latch_0_range => self.latches[0] = LATCH_FD,
+            0x0fe9..=0x0fef if addr - 0x0fe8 <= latch_0_range => self.latches[0] = LATCH_FE,
+            0x1fd8..=0x1fdf => self.latches[1] = LATCH_FD,
+            0x1fe8..=0x1fef => self.latches[1] = LATCH_FE,
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Mmc2 {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START.. => Some(self.read_rom(addr)),
+            PRG_RAM_START.. if !self.prg_ram.is_empty() => {
+                Some(self.prg_ram[(addr - PRG_RAM_START) as usize % self.prg_ram.len()])
+            }
+            _ => None,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            0xa000..=0xafff => self.prg_bank = value & PRG_BANK_MASK,
+            0xb000..=0xefff => {
+                let register = (addr - 0xb000) as usize / 0x1000;
+                self.chr_banks[register / 2][register % 2] = value & CHR_BANK_MASK;
+            }
+            0xf000..=0xffff => {
+                self.mirroring = if value & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                }
+            }
+            PRG_RAM_START..PRG_ROM_START if !self.prg_ram.is_empty() => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - PRG_RAM_START) as usize % len] = value;
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr.read(self.chr_bank(addr), CHR_BANK_4K, addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        self.chr
+            .write(self.chr_bank(addr), CHR_BANK_4K, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    /// The new bank is used from the fetch after the one of the trigger tile on
+    fn notify_ppu_addr(&mut self, addr: u16) {
+        self.update_latch(self.ppu_addr);
+        self.ppu_addr = addr;
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        (self.battery && !self.prg_ram.is_empty()).then(|| self.prg_ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_memory(&mut self.prg_ram, data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::CHR_BANK_8K;
+
+    fn mmc(chip: Chip) -> Mmc2 {
+        Mmc2::new(
+            Rom {
+                prg_rom: (0..16).flat_map(|i| vec![i; PRG_BANK_8K]).collect(),
+                chr_rom: (0..32).flat_map(|i| vec![i; CHR_BANK_4K]).collect(),
+                ..Default::default()
+            },
+            chip,
+        )
+    }
+
+    #[test]
+    fn mmc2_prg_banks() {
+        let mut mmc2 = mmc(Chip::Mmc2);
+        mmc2.write_prg(0xa000, 5);
+        assert_eq!(mmc2.read_prg(0x8000), Some(5));
+        assert_eq!(mmc2.read_prg(0xa000), Some(13));
+        assert_eq!(mmc2.read_prg(0xc000), Some(14));
+        assert_eq!(mmc2.read_prg(0xe000), Some(15));
+        assert_eq!(mmc2.read_prg(0x6000), None);
+    }
+
+    #[test]
+    fn mmc4_prg_banks() {
+        let mut mmc4 = mmc(Chip::Mmc4);
+        mmc4.write_prg(0xa000, 2);
+        assert_eq!(mmc4.read_prg(0x8000), Some(4));
+        assert_eq!(mmc4.read_prg(0xa000), Some(5));
+        assert_eq!(mmc4.read_prg(0xc000), Some(14));
+        mmc4.write_prg(0x6000, 7);
+        assert_eq!(mmc4.read_prg(0x6000), Some(7));
+    }
+
+    fn fetch(mmc2: &mut Mmc2, addr: u16) -> u8 {
+        mmc2.notify_ppu_addr(addr);
+        mmc2.read_chr(addr)
+    }
+
+    #[test]
+    fn chr_latches() {
+        let mut mmc2 = mmc(Chip::Mmc2);
+        for (addr, bank) in [(0xb000, 1), (0xc000, 2), (0xd000, 3), (0xe000, 4)] {
+            mmc2.write_prg(addr, bank);
+        }
+        assert_eq!(fetch(&mut mmc2, 0x0000), 2);
+        assert_eq!(fetch(&mut mmc2, 0x1000), 4);
+
+        assert_eq!(fetch(&mut mmc2, 0x0fd8), 2);
+        assert_eq!(fetch(&mut mmc2, 0x0000), 1);
+        assert_eq!(fetch(&mut mmc2, 0x1fdd), 4);
+        assert_eq!(fetch(&mut mmc2, 0x1000), 3);
+
+        assert_eq!(fetch(&mut mmc2, 0x0fe8), 1);
+        assert_eq!(fetch(&mut mmc2, 0x0000), 2);
+    }
+
+    #[test]
+    fn latch_0_triggers() {
+        let mut mmc2 = mmc(Chip::Mmc2);
+        let mut mmc4 = mmc(Chip::Mmc4);
+        for addr in [0x0fdb, 0x0000] {
+            mmc2.notify_ppu_addr(addr);
+            mmc4.notify_ppu_addr(addr);
+        }
+        assert_eq!(mmc2.latches[0], LATCH_FE);
+        assert_eq!(mmc4.latches[0], LATCH_FD);
+    }
+
+    #[test]
+    fn chr_ram_banks() {
+        let mut mmc2 = Mmc2::new(
+            Rom {
+                chr_ram_size: CHR_BANK_8K,
+                ..Default::default()
+            },
+            Chip::Mmc2,
+        );
+        // both pattern tables on the second bank through their $FE latches
+        mmc2.write_prg(0xc000, 1);
+        mmc2.write_prg(0xe000, 1);
+        mmc2.write_chr(0x0012, 5);
+        assert_eq!(mmc2.read_chr(0x1012), 5);
+        mmc2.write_prg(0xc000, 0);
+        assert_eq!(mmc2.read_chr(0x0012), 0);
+    }
+}
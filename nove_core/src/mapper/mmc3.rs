@@ -0,0 +1,418 @@
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::chr::Chr;
+use crate::mapper::{load_memory, new_prg_ram, read_bank, Mapper, PRG_BANK_8K};
+use crate::Program;
+
+const PRG_RAM_START: u16 = 0x6000;
+const MMC6_RAM_START: u16 = 0x7000;
+const MMC6_RAM_SIZE: usize = 0x400; // 1 KiB
+const MMC6_RAM_HALF: usize = MMC6_RAM_SIZE / 2;
+
+const CHR_BANK_1K: usize = 0x400;
+const PPU_A12: u16 = 0x1000;
+/// Fetches with A12 low needed to clock the counter again, filtering the quick toggles of
+/// the background or 8x16 sprite fetches
+const MIN_A12_LOW_FETCHES: u8 = 4;
+
+/*
+   7  bit  0
+   ---- ----
+   CPMx xRRR
+   |||   |||
+   |||   +++- Specify which bank register to update on next write to Bank Data register
+   |||        0: 2 KB CHR bank at PPU $0000-$07FF (or $1000-$17FF)
+   |||        1: 2 KB CHR bank at PPU $0800-$0FFF (or $1800-$1FFF)
+   |||        2: 1 KB CHR bank at PPU $1000-$13FF (or $0000-$03FF)
+   |||        3: 1 KB CHR bank at PPU $1400-$17FF (or $0400-$07FF)
+   |||        4: 1 KB CHR bank at PPU $1800-$1BFF (or $0800-$0BFF)
+   |||        5: 1 KB CHR bank at PPU $1C00-$1FFF (or $0C00-$0FFF)
+   |||        6: 8 KB PRG ROM bank at $8000-$9FFF (or $C000-$DFFF)
+   |||        7: 8 KB PRG ROM bank at $A000-$BFFF
+   ||+------- MMC6 only: PRG RAM enable
+   |+-------- PRG ROM bank mode (0: $8000-$9FFF swappable,
+   |                                $C000-$DFFF fixed to second-last bank;
+   |                             1: $C000-$DFFF swappable,
+   |                                $8000-$9FFF fixed to second-last bank)
+   +--------- CHR A12 inversion (0: two 2 KB banks at $0000-$0FFF,
+                                    four 1 KB banks at $1000-$1FFF;
+                                 1: two 2 KB banks at $1000-$1FFF,
+                                    four 1 KB banks at $0000-$0FFF)
+*/
+const BANK_REGISTER_MASK: u8 = 0b0000_0111;
+const MMC6_RAM_ENABLE: u8 = 0b0010_0000;
+const PRG_MODE: u8 = 0b0100_0000;
+const CHR_INVERSION: u8 = 0b1000_0000;
+
+const PRG_RAM_ENABLE: u8 = 0b1000_0000;
+const PRG_RAM_WRITE_PROTECT: u8 = 0b0100_0000;
+const MMC6_HI_READ: u8 = 0b1000_0000;
+const MMC6_HI_WRITE: u8 = 0b0100_0000;
+const MMC6_LO_READ: u8 = 0b0010_0000;
+const MMC6_LO_WRITE: u8 = 0b0001_0000;
+
+/// Chip revisions sharing mapper 4 with differences in the IRQ counter and the PRG RAM
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Revision {
+    /// Older NEC chips, only raise the IRQ when the counter decrements to zero
+    Mmc3A,
+    /// Sharp chips, raise the IRQ every clock ending with the counter at zero
+    Mmc3B,
+    /// MMC3B counter with 1 KiB of internal RAM at $7000-$7FFF and its own protection
+    Mmc6,
+}
+
+/// Mapper 4, the Nintendo MMC3. Switches 8 KiB PRG banks and 1/2 KiB CHR banks and clocks a
+/// scanline counter with the rising edges of the PPU A12 address line.
+pub struct Mmc3 {
+    prg_rom: Program,
+    chr: Chr,
+    prg_ram: Vec<u8>,
+    battery: bool,
+    revision: Revision,
+    four_screen: bool,
+    bank_select: u8,
+    banks: [u8; 8],
+    mirroring: Mirroring,
+    ram_protect: u8,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq: bool,
+    a12_low_fetches: u8,
+}
+
+impl Mmc3 {
+    pub fn new(rom: Rom, revision: Revision) -> Self {
+        let ram_size = match revision {
+            Revision::Mmc6 => MMC6_RAM_SIZE,
+            _ => rom.prg_ram_total().max(PRG_BANK_8K),
+        };
+        Self {
+            chr: Chr::new(&rom),
+            prg_ram: new_prg_ram(&rom, ram_size),
+            prg_rom: rom.prg_rom,
+            battery: rom.battery,
+            revision,
+            four_screen: rom.screen_mirroring == Mirroring::FourScreen,
+            bank_select: 0,
+            banks: [0, 2, 4, 5, 6, 7, 0, 1],
+            mirroring: rom.screen_mirroring,
+            ram_protect: 0,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq: false,
+            a12_low_fetches: 0,
+        }
+    }
+
+    fn prg_bank(&self, addr: u16) -> usize {
+        let second_last = (self.prg_rom.len() / PRG_BANK_8K).saturating_sub(2);
+        let swapped = self.bank_select & PRG_MODE != 0;
+        match ((addr - PRG_ROM_START) as usize / PRG_BANK_8K, swapped) {
+            (0, false) | (2, true) => self.banks[6] as usize,
+            (0, true) | (2, false) => second_last,
+            (1, _) => self.banks[7] as usize,
+            _ => second_last + 1,
+        }
+    }
+
+    fn chr_bank(&self, addr: u16) -> usize {
+        let addr = if self.bank_select & CHR_INVERSION != 0 {
+            addr ^ PPU_A12
+        } else {
+            addr
+        };
+        match addr as usize / CHR_BANK_1K {
+            slot @ 0..=3 => (self.banks[slot / 2] & !1) as usize + slot % 2,
+            slot => self.banks[slot - 2] as usize,
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, value: u8) {
+        let even = addr & 1 == 0;
+        match (addr, even) {
+            (0x8000..=0x9fff, true) => self.bank_select = value,
+            (0x8000..=0x9fff, false) => {
+                self.banks[(self.bank_select & BANK_REGISTER_MASK) as usize] = value
+            }
+            (0xa000..=0xbfff, true) if !self.four_screen => {
+                self.mirroring = if value & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                }
+            }
+            (0xa000..=0xbfff, false) => self.ram_protect = value,
+            (0xc000..=0xdfff, true) => self.irq_latch = value,
+            (0xc000..=0xdfff, false) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (0xe000..=0xffff, true) => {
+                self.irq_enabled = false;
+                self.irq = false;
+            }
+            (0xe000..=0xffff, false) => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn clock_counter(&mut self) {
+        let prev = self.irq_counter;
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+        } else {
+            self.irq_counter -= 1;
+        }
+        let trigger = match self.revision {
+            Revision::Mmc3A => prev != 0 || self.irq_reload,
+            Revision::Mmc3B | Revision::Mmc6 => true,
+        };
+        if trigger && self.irq_counter == 0 && self.irq_enabled {
+            self.irq = true;
+        }
+        self.irq_reload = false;
+    }
+
+    /// Index of the PRG RAM for `addr` if it's enabled for the access
+    fn prg_ram_addr(&self, addr: u16, write: bool) -> Option<usize> {
+        if self.revision != Revision::Mmc6 {
+            let enabled = self.ram_protect & PRG_RAM_ENABLE != 0;
+            let protected = write && self.ram_protect & PRG_RAM_WRITE_PROTECT != 0;
+            return (enabled && !protected).then_some((addr - PRG_RAM_START) as usize);
+        }
+
+        if addr < MMC6_RAM_START || self.bank_select & MMC6_RAM_ENABLE == 0 {
+            return None;
+        }
+        let i = (addr - MMC6_RAM_START) as usize % MMC6_RAM_SIZE;
+        let flag = match (i < MMC6_RAM_HALF, write) {
+            (true, false) => MMC6_LO_READ,
+            (true, true) => MMC6_LO_WRITE,
+            (false, false) => MMC6_HI_READ,
+            (false, true) => MMC6_HI_WRITE,
+        };
+        (self.ram_protect & flag != 0).then_some(i)
+    }
+
+    fn read_mmc6_ram(&self, addr: u16) -> Option<u8> {
+        if let Some(i) = self.prg_ram_addr(addr, false) {
+            return Some(self.prg_ram[i]);
+        }
+        // a disabled half reads as zero while the other one is readable
+        let enabled = self.bank_select & MMC6_RAM_ENABLE != 0 && addr >= MMC6_RAM_START;
+        let any_readable = self.ram_protect & (MMC6_HI_READ | MMC6_LO_READ) != 0;
+        (enabled && any_readable).then_some(0)
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START.. => Some(read_bank(
+                &self.prg_rom,
+                self.prg_bank(addr),
+                PRG_BANK_8K,
+                addr,
+            )),
+            PRG_RAM_START.. if self.revision == Revision::Mmc6 => self.read_mmc6_ram(addr),
+            PRG_RAM_START.. => self
+                .prg_ram_addr(addr, false)
+                .map(|i| self.prg_ram[i % self.prg_ram.len()]),
+            _ => None,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            PRG_ROM_START.. => self.write_register(addr, value),
+            PRG_RAM_START.. => {
+                if let Some(i) = self.prg_ram_addr(addr, true) {
+                    let len = self.prg_ram.len();
+                    self.prg_ram[i % len] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr.read(self.chr_bank(addr), CHR_BANK_1K, addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        self.chr
+            .write(self.chr_bank(addr), CHR_BANK_1K, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn notify_ppu_addr(&mut self, addr: u16) {
+        if addr & PPU_A12 == 0 {
+            self.a12_low_fetches = self.a12_low_fetches.saturating_add(1);
+            return;
+        }
+        if self.a12_low_fetches >= MIN_A12_LOW_FETCHES {
+            self.clock_counter();
+        }
+        self.a12_low_fetches = 0;
+    }
+
+    fn irq(&self) -> bool {
+        self.irq
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.prg_ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_memory(&mut self.prg_ram, data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mmc3(revision: Revision) -> Mmc3 {
+        Mmc3::new(
+            Rom {
+                prg_rom: (0..16).flat_map(|i| vec![i; PRG_BANK_8K]).collect(),
+                chr_rom: (0..64).flat_map(|i| vec![i; CHR_BANK_1K]).collect(),
+                ..Default::default()
+            },
+            revision,
+        )
+    }
+
+    fn scanline(mmc3: &mut Mmc3) {
+        for _ in 0..MIN_A12_LOW_FETCHES {
+            mmc3.notify_ppu_addr(0x0000);
+        }
+        mmc3.notify_ppu_addr(0x1000);
+        mmc3.notify_ppu_addr(0x1008);
+    }
+
+    #[test]
+    fn prg_banks() {
+        let mut mmc3 = mmc3(Revision::Mmc3B);
+        mmc3.write_prg(0x8000, 6);
+        mmc3.write_prg(0x8001, 3);
+        mmc3.write_prg(0x8000, 7);
+        mmc3.write_prg(0x8001, 4);
+        assert_eq!(mmc3.read_prg(0x8000), Some(3));
+        assert_eq!(mmc3.read_prg(0xa000), Some(4));
+        assert_eq!(mmc3.read_prg(0xc000), Some(14));
+        assert_eq!(mmc3.read_prg(0xe000), Some(15));
+
+        mmc3.write_prg(0x8000, PRG_MODE);
+        assert_eq!(mmc3.read_prg(0x8000), Some(14));
+        assert_eq!(mmc3.read_prg(0xc000), Some(3));
+    }
+
+    #[test]
+    fn chr_banks() {
+        let mut mmc3 = mmc3(Revision::Mmc3B);
+        mmc3.write_prg(0x8000, 0);
+        mmc3.write_prg(0x8001, 9);
+        mmc3.write_prg(0x8000, 5);
+        mmc3.write_prg(0x8001, 20);
+        assert_eq!(mmc3.read_chr(0x0000), 8);
+        assert_eq!(mmc3.read_chr(0x0400), 9);
+        assert_eq!(mmc3.read_chr(0x1c00), 20);
+
+        mmc3.write_prg(0x8000, CHR_INVERSION);
+        assert_eq!(mmc3.read_chr(0x1400), 9);
+        assert_eq!(mmc3.read_chr(0x0c00), 20);
+    }
+
+    #[test]
+    fn mirroring_and_ram_protection() {
+        let mut mmc3 = mmc3(Revision::Mmc3B);
+        mmc3.write_prg(0xa000, 0);
+        assert_eq!(mmc3.mirroring(), Mirroring::Vertical);
+        mmc3.write_prg(0xa000, 1);
+        assert_eq!(mmc3.mirroring(), Mirroring::Horizontal);
+
+        mmc3.write_prg(0x6000, 1);
+        assert_eq!(mmc3.read_prg(0x6000), None);
+        mmc3.write_prg(0xa001, PRG_RAM_ENABLE);
+        mmc3.write_prg(0x6000, 1);
+        mmc3.write_prg(0xa001, PRG_RAM_ENABLE | PRG_RAM_WRITE_PROTECT);
+        mmc3.write_prg(0x6000, 2);
+        assert_eq!(mmc3.read_prg(0x6000), Some(1));
+    }
+
+    #[test]
+    fn scanline_irq() {
+        let mut mmc3 = mmc3(Revision::Mmc3B);
+        mmc3.write_prg(0xc000, 2);
+        mmc3.write_prg(0xc001, 0);
+        mmc3.write_prg(0xe001, 0);
+
+        scanline(&mut mmc3);
+        scanline(&mut mmc3);
+        assert!(!mmc3.irq());
+        scanline(&mut mmc3);
+        assert!(mmc3.irq());
+
+        mmc3.write_prg(0xe000, 0);
+        assert!(!mmc3.irq());
+    }
+
+    #[test]
+    fn a12_filter() {
+        let mut mmc3 = mmc3(Revision::Mmc3B);
+        mmc3.write_prg(0xc000, 1);
+        mmc3.write_prg(0xc001, 0);
+        mmc3.write_prg(0xe001, 0);
+        scanline(&mut mmc3);
+        // background fetches from $1000 only leave A12 low for the nametable and attributes
+        for _ in 0..32 {
+            mmc3.notify_ppu_addr(0x2000);
+            mmc3.notify_ppu_addr(0x23c0);
+            mmc3.notify_ppu_addr(0x1000);
+            mmc3.notify_ppu_addr(0x1008);
+        }
+        assert!(!mmc3.irq());
+    }
+
+    #[test]
+    fn zero_latch_revisions() {
+        let mut mmc3a = mmc3(Revision::Mmc3A);
+        let mut mmc3b = mmc3(Revision::Mmc3B);
+        for mmc3 in [&mut mmc3a, &mut mmc3b] {
+            mmc3.write_prg(0xc000, 0);
+            mmc3.write_prg(0xc001, 0);
+            mmc3.write_prg(0xe001, 0);
+            scanline(mmc3);
+            mmc3.write_prg(0xe000, 0);
+            mmc3.write_prg(0xe001, 0);
+            scanline(mmc3);
+        }
+        assert!(!mmc3a.irq());
+        assert!(mmc3b.irq());
+    }
+
+    #[test]
+    fn mmc6_ram() {
+        let mut mmc6 = mmc3(Revision::Mmc6);
+        assert_eq!(mmc6.read_prg(0x7000), None);
+        mmc6.write_prg(0x8000, MMC6_RAM_ENABLE);
+        mmc6.write_prg(0xa001, MMC6_LO_READ | MMC6_LO_WRITE | MMC6_HI_READ);
+        mmc6.write_prg(0x7001, 0x12);
+        mmc6.write_prg(0x7201, 0x34);
+        assert_eq!(mmc6.read_prg(0x7401), Some(0x12));
+        assert_eq!(mmc6.read_prg(0x7201), Some(0));
+        mmc6.write_prg(0xa001, MMC6_HI_READ);
+        assert_eq!(mmc6.read_prg(0x7001), Some(0));
+        assert_eq!(mmc6.read_prg(0x6001), None);
+    }
+}
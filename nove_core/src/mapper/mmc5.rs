@@ -0,0 +1,697 @@
+use crate::addresses::ppu::{CTRL, VRAM_END, VRAM_START};
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::chr::Chr;
+use crate::mapper::{
+    load_memory, new_prg_ram, read_bank, Mapper, CHR_BANK_4K, CHR_BANK_8K, PRG_BANK_8K,
+};
+use crate::Program;
+
+const PRG_RAM_START: u16 = 0x6000;
+const EXRAM_START: u16 = 0x5c00;
+const EXRAM_SIZE: usize = 0x400; // 1 KiB
+const MAX_PRG_RAM_SIZE: usize = 0x10000; // 64 KiB
+const CHR_BANK_1K: usize = 0x400;
+const CHR_BANK_2K: usize = 0x800;
+
+const NAMETABLE_SIZE: u16 = 0x400;
+const ATTRIBUTE_TABLE_OFFSET: u16 = 0x3c0;
+const TILES_PER_ROW: u16 = 32;
+const SCREEN_HEIGHT: u16 = 240;
+
+const PRG_ROM_SELECT: u8 = 0b1000_0000;
+const PRG_BANK_MASK: u8 = 0b0111_1111;
+const PRG_RAM_WRITE_KEYS: (u8, u8) = (0b10, 0b01);
+const SPRITE_SIZE_16: u8 = 0b0010_0000;
+
+const IRQ_ENABLE: u8 = 0b1000_0000;
+const IRQ_PENDING: u8 = 0b1000_0000;
+const IN_FRAME: u8 = 0b0100_0000;
+
+/*
+   Vertical split control ($5200)
+
+   7  bit  0
+   ---- ----
+   ERxT TTTT
+   || | ||||
+   || +-++++- Tile column where the split starts (or ends on the left side)
+   |+-------- Side of the split, 0 left and 1 right
+   +--------- Enable
+*/
+const SPLIT_ENABLE: u8 = 0b1000_0000;
+const SPLIT_RIGHT: u8 = 0b0100_0000;
+const SPLIT_TILE_MASK: u8 = 0b0001_1111;
+
+/// Fetches of the PPU in each scanline, counted from the one that completes the scanline
+/// detection: 32 background tiles, 8 sprites and the two first tiles of the next line
+const SPRITE_FETCHES_START: u16 = 128;
+const SPRITE_FETCHES_END: u16 = 160;
+const FETCHES_PER_TILE: u16 = 4;
+const PREFETCHED_TILES: u16 = 2;
+/// CPU cycles without PPU reads after which the MMC5 considers the rendering stopped
+const IDLE_CYCLES: u8 = 3;
+
+/// Usage of the 1 KiB of internal memory of the MMC5, selected with $5104
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExramMode {
+    Nametable,
+    ExtendedAttributes,
+    Ram,
+    Rom,
+}
+
+impl From<u8> for ExramMode {
+    fn from(value: u8) -> Self {
+        match value & 0b11 {
+            0 => ExramMode::Nametable,
+            1 => ExramMode::ExtendedAttributes,
+            2 => ExramMode::Ram,
+            _ => ExramMode::Rom,
+        }
+    }
+}
+
+/// Mapper 5, the Nintendo MMC5. Besides the PRG and CHR banking, it has an internal ExRAM usable
+/// as nametable, as per tile attributes or as CPU memory, a fill mode nametable, a vertical split
+/// of the screen, a scanline IRQ and an 8x8 multiplier. The scanlines are detected watching the
+/// PPU bus for the three consecutive reads of the same nametable address that end each line.
+pub struct Mmc5 {
+    prg_rom: Program,
+    chr: Chr,
+    prg_ram: Vec<u8>,
+    battery: bool,
+    exram: [u8; EXRAM_SIZE],
+    prg_mode: u8,
+    chr_mode: u8,
+    prg_ram_protect: (u8, u8),
+    exram_mode: ExramMode,
+    /// Source of each nametable: CIRAM page 0 or 1, ExRAM or fill mode
+    nametables: u8,
+    fill_tile: u8,
+    fill_attribute: u8,
+    /// Banks of $5113-$5117, PRG RAM for $6000 and the four 8 KiB slots of $8000
+    prg_banks: [u8; 5],
+    /// Set A ($5120-$5127), used by sprites
+    chr_banks_a: [u16; 8],
+    /// Set B ($5128-$512B), used by background
+    chr_banks_b: [u16; 4],
+    chr_upper_bits: u8,
+    last_chr_set_b: bool,
+    sprites_16: bool,
+    split_control: u8,
+    split_scroll: u8,
+    split_bank: u8,
+    irq_scanline: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+    multiplicand: u8,
+    multiplier: u8,
+    in_frame: bool,
+    scanline: u16,
+    fetches: u16,
+    last_addr: u16,
+    repeated_reads: u8,
+    /// Nametable address of the background tile being fetched, for the extended attributes
+    tile_addr: u16,
+    fetched: bool,
+    idle_cycles: u8,
+}
+
+impl Mmc5 {
+    pub fn new(rom: Rom) -> Self {
+        Self {
+            prg_ram: new_prg_ram(
+                &rom,
+                rom.prg_ram_total().clamp(PRG_BANK_8K, MAX_PRG_RAM_SIZE),
+            ),
+            battery: rom.battery,
+            chr: Chr::new(&rom),
+            prg_rom: rom.prg_rom,
+            exram: [0; EXRAM_SIZE],
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: (0, 0),
+            exram_mode: ExramMode::Nametable,
+            nametables: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_banks: [0, 0, 0, 0, 0xff],
+            chr_banks_a: [0; 8],
+            chr_banks_b: [0; 4],
+            chr_upper_bits: 0,
+            last_chr_set_b: false,
+            sprites_16: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            irq_scanline: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            multiplicand: 0xff,
+            multiplier: 0xff,
+            in_frame: false,
+            scanline: 0,
+            fetches: 0,
+            last_addr: 0,
+            repeated_reads: 0,
+            tile_addr: 0,
+            fetched: false,
+            idle_cycles: 0,
+        }
+    }
+
+    /// Bank register of $5114-$5117 mapped on the 8 KiB slot of `addr`, with the bits selecting
+    /// the slot inside of the larger banks of the current mode already replaced
+    fn prg_bank(&self, addr: u16) -> u8 {
+        let slot = ((addr - PRG_ROM_START) as usize / PRG_BANK_8K) as u8;
+        let (register, mask) = match (self.prg_mode, slot) {
+            (0, _) => (4, 0b11),
+            (1 | 2, 0 | 1) => (2, 0b01),
+            (1, _) => (4, 0b01),
+            (_, slot) => (slot as usize + 1, 0),
+        };
+        let bank = self.prg_banks[register];
+        // $5117 always maps ROM
+        let bank = if register == 4 {
+            bank | PRG_ROM_SELECT
+        } else {
+            bank
+        };
+        (bank & !mask) | (slot & mask)
+    }
+
+    fn prg_ram_addr(&self, bank: u8, addr: u16) -> usize {
+        let bank = (bank & PRG_BANK_MASK) as usize;
+        (bank * PRG_BANK_8K + addr as usize % PRG_BANK_8K) % self.prg_ram.len()
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == PRG_RAM_WRITE_KEYS
+    }
+
+    /// Whether the PPU can use the ExRAM as nametable
+    fn exram_nametable(&self) -> bool {
+        matches!(
+            self.exram_mode,
+            ExramMode::Nametable | ExramMode::ExtendedAttributes
+        )
+    }
+
+    fn read_status(&mut self) -> u8 {
+        let mut status = 0;
+        if std::mem::take(&mut self.irq_pending) {
+            status |= IRQ_PENDING;
+        }
+        if self.in_frame {
+            status |= IN_FRAME;
+        }
+        status
+    }
+
+    fn write_chr_bank(&mut self, register: usize, value: u8) {
+        let bank = (self.chr_upper_bits as u16) << 8 | value as u16;
+        match register {
+            0..8 => self.chr_banks_a[register] = bank,
+            _ => self.chr_banks_b[register - 8] = bank,
+        }
+        self.last_chr_set_b = register >= 8;
+    }
+
+    fn detect_scanline(&mut self) {
+        if self.in_frame {
+            self.scanline += 1;
+            if self.scanline == self.irq_scanline as u16 {
+                self.irq_pending = true;
+            }
+        } else {
+            self.in_frame = true;
+            self.scanline = 0;
+            self.irq_pending = false;
+        }
+        self.fetches = 0;
+    }
+
+    fn sprite_fetch(&self) -> bool {
+        self.in_frame && (SPRITE_FETCHES_START..SPRITE_FETCHES_END).contains(&self.fetches)
+    }
+
+    /// Column and scanline of the background tile being fetched
+    fn bg_tile(&self) -> Option<(u16, u16)> {
+        if !self.in_frame || self.sprite_fetch() {
+            return None;
+        }
+        let tile = self.fetches / FETCHES_PER_TILE;
+        if self.fetches < SPRITE_FETCHES_START {
+            Some((tile + PREFETCHED_TILES, self.scanline))
+        } else {
+            let tile = tile - SPRITE_FETCHES_END / FETCHES_PER_TILE;
+            Some((tile, self.scanline + 1))
+        }
+    }
+
+    /// Column and line of the split region being fetched, when the background tile falls on it
+    fn split_tile(&self) -> Option<(u16, u16)> {
+        if self.split_control & SPLIT_ENABLE == 0 || !self.exram_nametable() {
+            return None;
+        }
+        let (column, line) = self.bg_tile()?;
+        let threshold = (self.split_control & SPLIT_TILE_MASK) as u16;
+        let in_split = if self.split_control & SPLIT_RIGHT != 0 {
+            column >= threshold
+        } else {
+            column < threshold
+        };
+        let y = (self.split_scroll as u16 + line) % SCREEN_HEIGHT;
+        in_split.then_some((column % TILES_PER_ROW, y))
+    }
+
+    fn ext_attributes(&self) -> Option<u8> {
+        match self.exram_mode {
+            ExramMode::ExtendedAttributes if self.bg_tile().is_some() => {
+                Some(self.exram[(self.tile_addr % NAMETABLE_SIZE) as usize])
+            }
+            _ => None,
+        }
+    }
+
+    fn chr_bank(&self, set_b: bool, addr: u16) -> (usize, usize) {
+        let addr = addr as usize;
+        let (size, register) = match self.chr_mode {
+            0 => (CHR_BANK_8K, 7),
+            1 => (CHR_BANK_4K, 3 + 4 * (addr / CHR_BANK_4K)),
+            2 => (CHR_BANK_2K, 1 + 2 * (addr / CHR_BANK_2K)),
+            _ => (CHR_BANK_1K, addr / CHR_BANK_1K),
+        };
+        let bank = if set_b {
+            self.chr_banks_b[register % self.chr_banks_b.len()]
+        } else {
+            self.chr_banks_a[register]
+        };
+        (bank as usize, size)
+    }
+}
+
+/// Replicates a palette on the four quadrants of an attribute byte
+fn attribute_byte(palette: u8) -> u8 {
+    (palette & 0b11) * 0b0101_0101
+}
+
+impl Mapper for Mmc5 {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        // the MMC5 leaves the frame when the CPU fetches the NMI vector
+        if addr == 0xfffa || addr == 0xfffb {
+            self.in_frame = false;
+        }
+        match addr {
+            PRG_ROM_START.. => {
+                let bank = self.prg_bank(addr);
+                if bank & PRG_ROM_SELECT != 0 {
+                    let bank = (bank & PRG_BANK_MASK) as usize;
+                    Some(read_bank(&self.prg_rom, bank, PRG_BANK_8K, addr))
+                } else {
+                    Some(self.prg_ram[self.prg_ram_addr(bank, addr)])
+                }
+            }
+            PRG_RAM_START.. => Some(self.prg_ram[self.prg_ram_addr(self.prg_banks[0], addr)]),
+            EXRAM_START.. => match self.exram_mode {
+                ExramMode::Ram | ExramMode::Rom => Some(self.exram[(addr - EXRAM_START) as usize]),
+                _ => None,
+            },
+            0x5204 => Some(self.read_status()),
+            0x5205 => Some((self.multiplicand as u16 * self.multiplier as u16) as u8),
+            0x5206 => Some(((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8),
+            _ => None,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x5100 => self.prg_mode = value & 0b11,
+            0x5101 => self.chr_mode = value & 0b11,
+            0x5102 => self.prg_ram_protect.0 = value & 0b11,
+            0x5103 => self.prg_ram_protect.1 = value & 0b11,
+            0x5104 => self.exram_mode = value.into(),
+            0x5105 => self.nametables = value,
+            0x5106 => self.fill_tile = value,
+            0x5107 => self.fill_attribute = value & 0b11,
+            0x5113..=0x5117 => self.prg_banks[(addr - 0x5113) as usize] = value,
+            0x5120..=0x512b => self.write_chr_bank((addr - 0x5120) as usize, value),
+            0x5130 => self.chr_upper_bits = value & 0b11,
+            0x5200 => self.split_control = value,
+            0x5201 => self.split_scroll = value,
+            0x5202 => self.split_bank = value,
+            0x5203 => self.irq_scanline = value,
+            0x5204 => self.irq_enabled = value & IRQ_ENABLE != 0,
+            0x5205 => self.multiplicand = value,
+            0x5206 => self.multiplier = value,
+            EXRAM_START..PRG_RAM_START => {
+                let i = (addr - EXRAM_START) as usize;
+                match self.exram_mode {
+                    // while used by the PPU, the CPU can only write during the rendering
+                    ExramMode::Nametable | ExramMode::ExtendedAttributes => {
+                        self.exram[i] = if self.in_frame { value } else { 0 }
+                    }
+                    ExramMode::Ram => self.exram[i] = value,
+                    ExramMode::Rom => {}
+                }
+            }
+            PRG_RAM_START..PRG_ROM_START if self.prg_ram_writable() => {
+                let i = self.prg_ram_addr(self.prg_banks[0], addr);
+                self.prg_ram[i] = value;
+            }
+            PRG_ROM_START.. if self.prg_ram_writable() => {
+                let bank = self.prg_bank(addr);
+                if bank & PRG_ROM_SELECT == 0 {
+                    let i = self.prg_ram_addr(bank, addr);
+                    self.prg_ram[i] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        if let Some((_, y)) = self.split_tile() {
+            let addr = (addr & !0b111) | (y % 8);
+            return self.chr.read(self.split_bank as usize, CHR_BANK_4K, addr);
+        }
+        if let Some(attributes) = self.ext_attributes() {
+            let bank = (self.chr_upper_bits as usize) << 6 | (attributes & 0b11_1111) as usize;
+            return self.chr.read(bank, CHR_BANK_4K, addr);
+        }
+        let set_b = if self.sprites_16 && self.in_frame {
+            !self.sprite_fetch()
+        } else {
+            self.last_chr_set_b
+        };
+        let (bank, size) = self.chr_bank(set_b, addr);
+        self.chr.read(bank, size, addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        let (bank, size) = self.chr_bank(self.last_chr_set_b, addr);
+        self.chr.write(bank, size, addr, value)
+    }
+
+    /// Closest standard layout to the nametable mapping, which can be any combination
+    fn mirroring(&self) -> Mirroring {
+        match self.nametables {
+            0x00 => Mirroring::SingleScreenLower,
+            0x55 => Mirroring::SingleScreenUpper,
+            0x44 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn read_nametable(&self, addr: u16, vram: &[u8]) -> u8 {
+        let offset = (addr - VRAM_START) % NAMETABLE_SIZE;
+        let attribute = offset >= ATTRIBUTE_TABLE_OFFSET;
+
+        if let Some((column, y)) = self.split_tile() {
+            let row = y / 8;
+            return if attribute {
+                let i = ATTRIBUTE_TABLE_OFFSET + (row / 4) * 8 + column / 4;
+                let shift = (row & 0b10) << 1 | (column & 0b10);
+                attribute_byte(self.exram[i as usize] >> shift)
+            } else {
+                self.exram[(row * TILES_PER_ROW + column) as usize]
+            };
+        }
+        if attribute {
+            if let Some(attributes) = self.ext_attributes() {
+                return attribute_byte(attributes >> 6);
+            }
+        }
+
+        let nametable = ((addr - VRAM_START) / NAMETABLE_SIZE) % 4;
+        match (self.nametables >> (nametable * 2)) & 0b11 {
+            page @ (0 | 1) => vram[(page as u16 * NAMETABLE_SIZE + offset) as usize],
+            2 if self.exram_nametable() => self.exram[offset as usize],
+            2 => 0,
+            _ if attribute => attribute_byte(self.fill_attribute),
+            _ => self.fill_tile,
+        }
+    }
+
+    fn write_nametable(&mut self, addr: u16, value: u8, vram: &mut [u8]) {
+        let offset = (addr - VRAM_START) % NAMETABLE_SIZE;
+        let nametable = ((addr - VRAM_START) / NAMETABLE_SIZE) % 4;
+        match (self.nametables >> (nametable * 2)) & 0b11 {
+            page @ (0 | 1) => vram[(page as u16 * NAMETABLE_SIZE + offset) as usize] = value,
+            2 if self.exram_nametable() => self.exram[offset as usize] = value,
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        if std::mem::take(&mut self.fetched) {
+            self.idle_cycles = 0;
+        } else {
+            self.idle_cycles = self.idle_cycles.saturating_add(cycles);
+            if self.idle_cycles >= IDLE_CYCLES {
+                self.in_frame = false;
+            }
+        }
+    }
+
+    fn notify_ppu_addr(&mut self, addr: u16) {
+        self.fetched = true;
+        self.fetches = self.fetches.saturating_add(1);
+        let nametable = (VRAM_START..=VRAM_END).contains(&addr);
+        if nametable && addr == self.last_addr {
+            self.repeated_reads += 1;
+            if self.repeated_reads == 2 {
+                self.detect_scanline();
+            }
+        } else {
+            self.repeated_reads = 0;
+        }
+        self.last_addr = addr;
+
+        if nametable && addr % NAMETABLE_SIZE < ATTRIBUTE_TABLE_OFFSET && self.bg_tile().is_some() {
+            self.tile_addr = addr;
+        }
+    }
+
+    fn notify_cpu_write(&mut self, addr: u16, value: u8) {
+        if addr == CTRL {
+            self.sprites_16 = value & SPRITE_SIZE_16 != 0;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_enabled && self.irq_pending
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.prg_ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_memory(&mut self.prg_ram, data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mmc5() -> Mmc5 {
+        Mmc5::new(Rom {
+            prg_rom: (0..16).flat_map(|i| vec![i; PRG_BANK_8K]).collect(),
+            chr_rom: (0..64).flat_map(|i| vec![i; CHR_BANK_1K]).collect(),
+            prg_ram_size: 2 * PRG_BANK_8K,
+            ..Default::default()
+        })
+    }
+
+    /// Feeds the fetches of the PPU until the dot 1 of the `lines`th next scanline, where the
+    /// third read of the same nametable address completes its detection
+    fn render_lines(mmc5: &mut Mmc5, lines: u16) {
+        for _ in 0..lines {
+            for i in 1..168 {
+                mmc5.notify_ppu_addr(if i % 4 == 0 { 0x2000 + i / 4 } else { 0x1000 });
+            }
+            for _ in 0..3 {
+                mmc5.notify_ppu_addr(0x2002);
+            }
+        }
+    }
+
+    #[test]
+    fn prg_modes() {
+        let mut mmc5 = mmc5();
+        assert_eq!(mmc5.read_prg(0xe000), Some(15));
+
+        mmc5.write_prg(0x5100, 0);
+        mmc5.write_prg(0x5117, 0x85);
+        assert_eq!(mmc5.read_prg(0x8000), Some(4));
+        assert_eq!(mmc5.read_prg(0xe000), Some(7));
+
+        mmc5.write_prg(0x5100, 2);
+        mmc5.write_prg(0x5115, 0x83);
+        mmc5.write_prg(0x5116, 0x89);
+        assert_eq!(mmc5.read_prg(0x8000), Some(2));
+        assert_eq!(mmc5.read_prg(0xa000), Some(3));
+        assert_eq!(mmc5.read_prg(0xc000), Some(9));
+        assert_eq!(mmc5.read_prg(0xe000), Some(5));
+    }
+
+    #[test]
+    fn prg_ram() {
+        let mut mmc5 = mmc5();
+        mmc5.write_prg(0x6000, 1);
+        assert_eq!(mmc5.read_prg(0x6000), Some(0));
+
+        mmc5.write_prg(0x5102, 0b10);
+        mmc5.write_prg(0x5103, 0b01);
+        mmc5.write_prg(0x5113, 1);
+        mmc5.write_prg(0x6000, 1);
+        assert_eq!(mmc5.prg_ram[PRG_BANK_8K], 1);
+
+        // RAM bank mapped on the CPU space of the ROM
+        mmc5.write_prg(0x5114, 0x01);
+        assert_eq!(mmc5.read_prg(0x8000), Some(1));
+        mmc5.write_prg(0x8000, 2);
+        assert_eq!(mmc5.read_prg(0x6000), Some(2));
+    }
+
+    #[test]
+    fn chr_sets() {
+        let mut mmc5 = mmc5();
+        mmc5.write_prg(0x5101, 3);
+        mmc5.write_prg(0x5120, 10);
+        mmc5.write_prg(0x5128, 20);
+        assert_eq!(mmc5.read_chr(0x0000), 20);
+        assert_eq!(mmc5.read_chr(0x1000), 20);
+        mmc5.write_prg(0x5120, 10);
+        assert_eq!(mmc5.read_chr(0x0000), 10);
+
+        // with 8x16 sprites the rendering selects the set of each fetch
+        mmc5.notify_cpu_write(CTRL, SPRITE_SIZE_16);
+        render_lines(&mut mmc5, 1);
+        assert_eq!(mmc5.read_chr(0x0000), 20);
+        for _ in 0..SPRITE_FETCHES_START {
+            mmc5.notify_ppu_addr(0x1000);
+        }
+        assert_eq!(mmc5.read_chr(0x0000), 10);
+    }
+
+    #[test]
+    fn chr_modes() {
+        let mut mmc5 = mmc5();
+        mmc5.write_prg(0x5127, 1);
+        assert_eq!(mmc5.read_chr(0x1c00), 15);
+        mmc5.write_prg(0x5101, 1);
+        assert_eq!(mmc5.read_chr(0x1c00), 7);
+        mmc5.write_prg(0x5101, 2);
+        mmc5.write_prg(0x5130, 1);
+        mmc5.write_prg(0x5125, 3);
+        assert_eq!(mmc5.chr_banks_a[5], 0x103);
+        assert_eq!(mmc5.read_chr(0x1400), 7);
+    }
+
+    #[test]
+    fn nametables() {
+        let mut mmc5 = mmc5();
+        let mut vram = [0; 2 * NAMETABLE_SIZE as usize];
+        // CIRAM 0, CIRAM 1, ExRAM and fill mode
+        mmc5.write_prg(0x5105, 0b11_10_01_00);
+        mmc5.write_prg(0x5106, 0x42);
+        mmc5.write_prg(0x5107, 2);
+        mmc5.write_nametable(0x2401, 1, &mut vram);
+        mmc5.write_nametable(0x2801, 2, &mut vram);
+
+        assert_eq!(vram[0x401], 1);
+        assert_eq!(mmc5.read_nametable(0x2401, &vram), 1);
+        assert_eq!(mmc5.read_nametable(0x2801, &vram), 2);
+        assert_eq!(mmc5.read_nametable(0x2c01, &vram), 0x42);
+        assert_eq!(mmc5.read_nametable(0x2fc1, &vram), 0b1010_1010);
+
+        mmc5.write_prg(0x5104, 2);
+        assert_eq!(mmc5.read_prg(0x5c01), Some(2));
+        assert_eq!(mmc5.read_nametable(0x2801, &vram), 0);
+    }
+
+    #[test]
+    fn extended_attributes() {
+        let mut mmc5 = mmc5();
+        let vram = [0; 2 * NAMETABLE_SIZE as usize];
+        mmc5.write_prg(0x5104, 1);
+        render_lines(&mut mmc5, 1);
+        mmc5.write_prg(0x5c03, 0b11_000101);
+
+        // nametable fetch of the fourth tile of the line
+        for _ in 0..3 {
+            mmc5.notify_ppu_addr(0x1000);
+        }
+        mmc5.notify_ppu_addr(0x2003);
+        assert_eq!(mmc5.read_nametable(0x23c0, &vram), 0xff);
+        assert_eq!(mmc5.read_chr(0x0010), 20);
+    }
+
+    #[test]
+    fn vertical_split() {
+        let mut mmc5 = mmc5();
+        let vram = [0; 2 * NAMETABLE_SIZE as usize];
+        mmc5.write_prg(0x5200, SPLIT_ENABLE | SPLIT_RIGHT | 3);
+        mmc5.write_prg(0x5201, 9);
+        mmc5.write_prg(0x5202, 2);
+        render_lines(&mut mmc5, 1);
+        mmc5.write_prg(0x5c20 + 3, 0x77);
+
+        // second tile, on the left of the split
+        assert_eq!(mmc5.read_nametable(0x2002, &vram), 0);
+        for _ in 0..FETCHES_PER_TILE {
+            mmc5.notify_ppu_addr(0x1000);
+        }
+        // line 9 of the split, second row
+        assert_eq!(mmc5.read_nametable(0x2003, &vram), 0x77);
+        assert_eq!(mmc5.read_chr(0x0010), 8);
+    }
+
+    #[test]
+    fn scanline_irq() {
+        let mut mmc5 = mmc5();
+        mmc5.write_prg(0x5203, 3);
+        mmc5.write_prg(0x5204, IRQ_ENABLE);
+        render_lines(&mut mmc5, 3);
+        assert!(!mmc5.irq());
+        assert_eq!(mmc5.read_prg(0x5204), Some(IN_FRAME));
+
+        render_lines(&mut mmc5, 1);
+        assert!(mmc5.irq());
+        assert_eq!(mmc5.read_prg(0x5204), Some(IRQ_PENDING | IN_FRAME));
+        assert!(!mmc5.irq());
+
+        // vertical blank
+        mmc5.tick(1);
+        mmc5.tick(3);
+        assert_eq!(mmc5.read_prg(0x5204), Some(0));
+    }
+
+    #[test]
+    fn multiplier() {
+        let mut mmc5 = mmc5();
+        mmc5.write_prg(0x5205, 200);
+        mmc5.write_prg(0x5206, 100);
+        assert_eq!(mmc5.read_prg(0x5205), Some(0x20));
+        assert_eq!(mmc5.read_prg(0x5206), Some(0x4e));
+    }
+
+    #[test]
+    fn chr_ram_banks() {
+        let mut mmc5 = Mmc5::new(Rom {
+            chr_ram_size: CHR_BANK_8K,
+            ..Default::default()
+        });
+        mmc5.write_prg(0x5101, 3);
+        mmc5.write_prg(0x5120, 3);
+        mmc5.write_prg(0x5121, 3);
+        mmc5.write_chr(0x0012, 5);
+        assert_eq!(mmc5.read_chr(0x0412), 5);
+        mmc5.write_prg(0x5120, 4);
+        assert_eq!(mmc5.read_chr(0x0012), 0);
+    }
+}
@@ -0,0 +1,426 @@
+use crate::addresses::ppu::VRAM_START;
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::chr::Chr;
+use crate::mapper::{
+    bank_from_end, load_memory, new_prg_ram, read_bank, Mapper, APU_PULSE_STEP, PRG_BANK_8K,
+};
+use crate::Program;
+
+const PRG_RAM_START: u16 = 0x6000;
+const CHR_BANK_1K: usize = 0x400;
+const NAMETABLE_SIZE: u16 = 0x400;
+const INTERNAL_RAM_SIZE: usize = 0x80;
+
+const PRG_BANK_MASK: u8 = 0b0011_1111;
+const SOUND_DISABLE: u8 = 0b0100_0000;
+/// Bank values from $E0 select the CIRAM pages instead of the CHR ROM for the nametables
+const CIRAM_BANKS: u8 = 0xe0;
+
+const ADDRESS_MASK: u8 = 0b0111_1111;
+const AUTO_INCREMENT: u8 = 0b1000_0000;
+
+/*
+   PRG RAM write protection ($F800)
+
+   7  bit  0
+   ---- ----
+   KKKK DCBA
+   |||| ||||
+   |||| |||+- Protect $6000-$67FF
+   |||| ||+-- Protect $6800-$6FFF
+   |||| |+--- Protect $7000-$77FF
+   |||| +---- Protect $7800-$7FFF
+   ++++------ Must be 0100 to allow any write
+*/
+const WRITE_KEY_MASK: u8 = 0b1111_0000;
+const WRITE_KEY: u8 = 0b0100_0000;
+const PRG_RAM_PAGE: usize = 0x800;
+
+const IRQ_ENABLE: u8 = 0b1000_0000;
+const IRQ_COUNTER_MAX: u16 = 0x7fff;
+
+/// Wavetable channels are stored from the end of the internal RAM, 8 bytes each
+const CHANNEL_REGISTERS: usize = 8;
+const CHANNELS_START: usize = INTERNAL_RAM_SIZE - 8 * CHANNEL_REGISTERS;
+/// CPU cycles taken by the update of a channel, the chip outputting one channel at a time
+const CHANNEL_CYCLES: u8 = 15;
+/// A wave at full volume swings as much as an APU pulse at full volume
+const SAMPLE_STEP: f32 = APU_PULSE_STEP / 15.0;
+
+/// Mapper 19, the Namco 163. Its 128 bytes of internal RAM, accessed through $4800 with the
+/// address set on $F800, hold the waveforms and registers of up to eight wavetable channels
+/// which are updated one after the other. The CHR ROM can also fill the nametables.
+pub struct Namco163 {
+    prg_rom: Program,
+    chr: Chr,
+    prg_ram: Vec<u8>,
+    internal_ram: [u8; INTERNAL_RAM_SIZE],
+    battery: bool,
+    ram_address: u8,
+    write_protect: u8,
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    nametables: [u8; 4],
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+    channel: usize,
+    channel_cycles: u8,
+    output: i16,
+}
+
+impl Namco163 {
+    pub fn new(rom: Rom) -> Self {
+        let nametables = match rom.screen_mirroring {
+            Mirroring::Vertical => [0, 1, 0, 1],
+            _ => [0, 0, 1, 1],
+        };
+        Self {
+            prg_ram: new_prg_ram(&rom, rom.prg_ram_total().max(PRG_BANK_8K)),
+            chr: Chr::new(&rom),
+            prg_rom: rom.prg_rom,
+            internal_ram: [0; INTERNAL_RAM_SIZE],
+            battery: rom.battery,
+            ram_address: 0,
+            write_protect: 0,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            nametables: nametables.map(|page| CIRAM_BANKS | page),
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            channel: 7,
+            channel_cycles: 0,
+            output: 0,
+        }
+    }
+
+    fn access_internal_ram(&mut self) -> usize {
+        let i = (self.ram_address & ADDRESS_MASK) as usize;
+        if self.ram_address & AUTO_INCREMENT != 0 {
+            self.ram_address =
+                AUTO_INCREMENT | ((self.ram_address & ADDRESS_MASK) + 1) & ADDRESS_MASK;
+        }
+        i
+    }
+
+    fn prg_ram_writable(&self, addr: u16) -> bool {
+        let page = (addr - PRG_RAM_START) as usize / PRG_RAM_PAGE;
+        self.write_protect & WRITE_KEY_MASK == WRITE_KEY && self.write_protect & (1 << page) == 0
+    }
+
+    fn sound_enabled(&self) -> bool {
+        self.prg_banks[0] & SOUND_DISABLE == 0
+    }
+
+    /// Number of channels being updated, the highest ones from 7 down
+    fn enabled_channels(&self) -> usize {
+        ((self.internal_ram[INTERNAL_RAM_SIZE - 1] >> 4) & 0b111) as usize + 1
+    }
+
+    /// Advances the phase of the current channel and outputs its sample
+    fn update_channel(&mut self) {
+        let base = CHANNELS_START + self.channel * CHANNEL_REGISTERS;
+        let registers = &mut self.internal_ram[base..base + CHANNEL_REGISTERS];
+        let frequency = u32::from_le_bytes([registers[0], registers[2], registers[4] & 0b11, 0]);
+        let phase = u32::from_le_bytes([registers[1], registers[3], registers[5], 0]);
+        let length = (256 - (registers[4] & 0b1111_1100) as u32) << 16;
+        let phase = (phase + frequency) % length;
+        [registers[1], registers[3], registers[5], _] = phase.to_le_bytes();
+
+        let address = (registers[6] as u32 + (phase >> 16)) as usize & 0xff;
+        let volume = (registers[7] & 0x0f) as i16;
+        let byte = self.internal_ram[address / 2];
+        let sample = if address.is_multiple_of(2) {
+            byte & 0x0f
+        } else {
+            byte >> 4
+        };
+        self.output = (sample as i16 - 8) * volume;
+
+        let first = 8 - self.enabled_channels();
+        self.channel = if self.channel <= first {
+            7
+        } else {
+            self.channel - 1
+        };
+    }
+}
+
+impl Mapper for Namco163 {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            0xe000.. => Some(read_bank(
+                &self.prg_rom,
+                bank_from_end(&self.prg_rom, PRG_BANK_8K, 1),
+                PRG_BANK_8K,
+                addr,
+            )),
+            PRG_ROM_START.. => {
+                let slot = (addr - PRG_ROM_START) as usize / PRG_BANK_8K;
+                let bank = (self.prg_banks[slot] & PRG_BANK_MASK) as usize;
+                Some(read_bank(&self.prg_rom, bank, PRG_BANK_8K, addr))
+            }
+            PRG_RAM_START.. => {
+                Some(self.prg_ram[(addr - PRG_RAM_START) as usize % self.prg_ram.len()])
+            }
+            0x5800..=0x5fff => {
+                Some((self.irq_counter >> 8) as u8 | if self.irq_enabled { IRQ_ENABLE } else { 0 })
+            }
+            0x5000..=0x57ff => Some(self.irq_counter as u8),
+            0x4800..=0x4fff => {
+                let i = self.access_internal_ram();
+                Some(self.internal_ram[i])
+            }
+            _ => None,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4800..=0x4fff => {
+                let i = self.access_internal_ram();
+                self.internal_ram[i] = value;
+            }
+            0x5000..=0x57ff => {
+                self.irq_counter = (self.irq_counter & 0x7f00) | value as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5fff => {
+                self.irq_counter = (self.irq_counter & 0x00ff) | ((value & 0x7f) as u16) << 8;
+                self.irq_enabled = value & IRQ_ENABLE != 0;
+                self.irq_pending = false;
+            }
+            PRG_RAM_START..PRG_ROM_START if self.prg_ram_writable(addr) => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - PRG_RAM_START) as usize % len] = value;
+            }
+            0x8000..=0xbfff => self.chr_banks[(addr - 0x8000) as usize / 0x800] = value,
+            0xc000..=0xdfff => self.nametables[(addr - 0xc000) as usize / 0x800] = value,
+            0xe000..=0xf7ff => self.prg_banks[(addr - 0xe000) as usize / 0x800] = value,
+            0xf800.. => {
+                self.ram_address = value;
+                self.write_protect = value;
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        let bank = self.chr_banks[addr as usize / CHR_BANK_1K] as usize;
+        self.chr.read(bank, CHR_BANK_1K, addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        let bank = self.chr_banks[addr as usize / CHR_BANK_1K] as usize;
+        self.chr.write(bank, CHR_BANK_1K, addr, value)
+    }
+
+    /// Closest standard layout to the nametable banks
+    fn mirroring(&self) -> Mirroring {
+        match self.nametables.map(|bank| bank & 1) {
+            [0, 1, 0, 1] => Mirroring::Vertical,
+            [0, 0, 0, 0] => Mirroring::SingleScreenLower,
+            [1, 1, 1, 1] => Mirroring::SingleScreenUpper,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn read_nametable(&self, addr: u16, vram: &[u8]) -> u8 {
+        let offset = (addr - VRAM_START) % NAMETABLE_SIZE;
+        let bank = self.nametables[((addr - VRAM_START) / NAMETABLE_SIZE) as usize % 4];
+        if bank >= CIRAM_BANKS {
+            vram[((bank & 1) as u16 * NAMETABLE_SIZE + offset) as usize]
+        } else {
+            self.chr.read(bank as usize, CHR_BANK_1K, offset)
+        }
+    }
+
+    fn write_nametable(&mut self, addr: u16, value: u8, vram: &mut [u8]) {
+        let offset = (addr - VRAM_START) % NAMETABLE_SIZE;
+        let bank = self.nametables[((addr - VRAM_START) / NAMETABLE_SIZE) as usize % 4];
+        if bank >= CIRAM_BANKS {
+            vram[((bank & 1) as u16 * NAMETABLE_SIZE + offset) as usize] = value;
+        }
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        if self.irq_enabled && self.irq_counter < IRQ_COUNTER_MAX {
+            self.irq_counter = (self.irq_counter + cycles as u16).min(IRQ_COUNTER_MAX);
+            self.irq_pending = self.irq_counter == IRQ_COUNTER_MAX;
+        }
+
+        if !self.sound_enabled() {
+            return;
+        }
+        self.channel_cycles += cycles;
+        while self.channel_cycles >= CHANNEL_CYCLES {
+            self.channel_cycles -= CHANNEL_CYCLES;
+            self.update_channel();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery
+            .then(|| [self.prg_ram.as_slice(), &self.internal_ram].concat())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let (prg_ram, internal_ram) = data.split_at(data.len().min(self.prg_ram.len()));
+        load_memory(&mut self.prg_ram, prg_ram);
+        load_memory(&mut self.internal_ram, internal_ram);
+    }
+
+    fn audio_output(&self) -> f32 {
+        if self.sound_enabled() {
+            self.output as f32 * SAMPLE_STEP
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::CHR_BANK_8K;
+
+    fn namco163(battery: bool) -> Namco163 {
+        Namco163::new(Rom {
+            prg_rom: (0..32).flat_map(|i| vec![i; PRG_BANK_8K]).collect(),
+            chr_rom: (0..=255).flat_map(|i| vec![i; CHR_BANK_1K]).collect(),
+            battery,
+            ..Default::default()
+        })
+    }
+
+    fn write_internal_ram(namco: &mut Namco163, addr: u8, data: &[u8]) {
+        namco.write_prg(0xf800, AUTO_INCREMENT | addr);
+        for value in data {
+            namco.write_prg(0x4800, *value);
+        }
+    }
+
+    #[test]
+    fn banks() {
+        let mut namco = namco163(false);
+        namco.write_prg(0xe000, 3);
+        namco.write_prg(0xe800, 4);
+        namco.write_prg(0xf000, 5);
+        namco.write_prg(0xb800, 200);
+        assert_eq!(namco.read_prg(0x8000), Some(3));
+        assert_eq!(namco.read_prg(0xa000), Some(4));
+        assert_eq!(namco.read_prg(0xc000), Some(5));
+        assert_eq!(namco.read_prg(0xe000), Some(31));
+        assert_eq!(namco.read_chr(0x1c00), 200);
+    }
+
+    #[test]
+    fn nametables() {
+        let mut namco = namco163(false);
+        let mut vram = [0; 2 * NAMETABLE_SIZE as usize];
+        namco.write_prg(0xc000, 0xe1);
+        namco.write_prg(0xc800, 0x30);
+        namco.write_nametable(0x2005, 7, &mut vram);
+        assert_eq!(vram[0x405], 7);
+        assert_eq!(namco.read_nametable(0x2005, &vram), 7);
+        assert_eq!(namco.read_nametable(0x2405, &vram), 0x30);
+    }
+
+    #[test]
+    fn prg_ram_protection() {
+        let mut namco = namco163(false);
+        namco.write_prg(0x6000, 1);
+        assert_eq!(namco.read_prg(0x6000), Some(0));
+        namco.write_prg(0xf800, WRITE_KEY | 0b0010);
+        namco.write_prg(0x6000, 1);
+        namco.write_prg(0x6800, 2);
+        assert_eq!(namco.read_prg(0x6000), Some(1));
+        assert_eq!(namco.read_prg(0x6800), Some(0));
+    }
+
+    #[test]
+    fn internal_ram() {
+        let mut namco = namco163(false);
+        write_internal_ram(&mut namco, 0x7e, &[1, 2, 3]);
+        assert_eq!(namco.internal_ram[0x7e..], [1, 2]);
+        assert_eq!(namco.internal_ram[0], 3);
+
+        namco.write_prg(0xf800, 0x7f);
+        assert_eq!(namco.read_prg(0x4800), Some(2));
+        assert_eq!(namco.read_prg(0x4800), Some(2));
+    }
+
+    #[test]
+    fn irq() {
+        let mut namco = namco163(false);
+        namco.write_prg(0x5000, 0xfd);
+        namco.write_prg(0x5800, IRQ_ENABLE | 0x7f);
+        namco.tick(1);
+        assert!(!namco.irq());
+        assert_eq!(namco.read_prg(0x5000), Some(0xfe));
+        namco.tick(3);
+        assert!(namco.irq());
+        assert_eq!(namco.read_prg(0x5800), Some(IRQ_ENABLE | 0x7f));
+        namco.write_prg(0x5800, IRQ_ENABLE | 0x7f);
+        assert!(!namco.irq());
+    }
+
+    #[test]
+    fn battery() {
+        let mut namco = namco163(true);
+        namco.write_prg(0xf800, WRITE_KEY);
+        namco.write_prg(0x6001, 1);
+        write_internal_ram(&mut namco, 0x10, &[2]);
+        let data = namco.save_data().unwrap();
+        assert_eq!(data.len(), PRG_BANK_8K + INTERNAL_RAM_SIZE);
+
+        let mut loaded = namco163(true);
+        loaded.load_save_data(&data);
+        assert_eq!(loaded.read_prg(0x6001), Some(1));
+        assert_eq!(loaded.internal_ram[0x10], 2);
+        assert_eq!(namco163(false).save_data(), None);
+    }
+
+    #[test]
+    fn channel_multiplexing() {
+        let mut namco = namco163(false);
+        // waveform of 4 samples at $00: 15, 0, 15, 0
+        write_internal_ram(&mut namco, 0x00, &[0x0f, 0x0f]);
+        // channels 6 and 7 enabled, frequency of a sample per update
+        let channel = |volume: u8| [0x00, 0x00, 0x00, 0x00, 0x01 | 0xfc, 0x00, 0x00, volume];
+        write_internal_ram(&mut namco, 0x70, &channel(0x0f));
+        write_internal_ram(&mut namco, 0x78, &channel(0x1f));
+
+        // channel 7 first and then channel 6, both starting on the second sample
+        namco.tick(CHANNEL_CYCLES);
+        assert_eq!(namco.output, -8 * 15);
+        namco.tick(CHANNEL_CYCLES);
+        assert_eq!(namco.output, -8 * 15);
+        namco.tick(CHANNEL_CYCLES);
+        assert_eq!(namco.output, 7 * 15);
+        assert_eq!(namco.channel, 6);
+
+        namco.write_prg(0xe000, SOUND_DISABLE);
+        assert_eq!(namco.audio_output(), 0.0);
+    }
+
+    #[test]
+    fn chr_ram_banks() {
+        let mut namco = Namco163::new(Rom {
+            chr_ram_size: CHR_BANK_8K,
+            ..Default::default()
+        });
+        namco.write_prg(0x8000, 3);
+        namco.write_prg(0x8800, 3);
+        namco.write_chr(0x0012, 5);
+        assert_eq!(namco.read_chr(0x0412), 5);
+        namco.write_prg(0x8000, 4);
+        assert_eq!(namco.read_chr(0x0012), 0);
+    }
+}
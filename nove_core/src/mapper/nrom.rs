@@ -0,0 +1,102 @@
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::chr::Chr;
+use crate::mapper::prg_ram::PrgRam;
+use crate::mapper::{read_bank, Mapper, CHR_BANK_8K, PRG_BANK_32K};
+use crate::Program;
+use log::info;
+
+/// Mapper 0, no bank switching. Boards with 16 KiB of PRG ROM mirror it into $C000-$FFFF.
+pub struct Nrom {
+    prg_rom: Program,
+    chr: Chr,
+    prg_ram: PrgRam,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(rom: Rom) -> Self {
+        Self {
+            chr: Chr::new(&rom),
+            prg_ram: PrgRam::new(&rom),
+            prg_rom: rom.prg_rom,
+            mirroring: rom.screen_mirroring,
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START.. => Some(read_bank(&self.prg_rom, 0, PRG_BANK_32K, addr)),
+            _ => self.prg_ram.read(addr),
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        if addr >= PRG_ROM_START {
+            info!("attempt to write on NROM PRG ROM address {addr:x}")
+        } else {
+            self.prg_ram.write(addr, value);
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr.read(0, CHR_BANK_8K, addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        self.chr.write(0, CHR_BANK_8K, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.prg_ram.save_data()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.prg_ram.load_save_data(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn half_rom_mirroring() {
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[0x0123] = 0x45;
+        let mut nrom = Nrom::new(Rom {
+            prg_rom,
+            ..Default::default()
+        });
+        assert_eq!(nrom.read_prg(0x8123), Some(0x45));
+        assert_eq!(nrom.read_prg(0xc123), Some(0x45));
+        assert_eq!(nrom.read_prg(0x6123), None);
+    }
+
+    #[test]
+    fn battery_prg_ram() {
+        let mut nrom = Nrom::new(Rom {
+            prg_ram_size: 0x2000,
+            battery: true,
+            ..Default::default()
+        });
+        nrom.write_prg(0x6123, 0x45);
+        assert_eq!(nrom.read_prg(0x6123), Some(0x45));
+
+        let save = nrom.save_data().unwrap();
+        assert_eq!(save.len(), 0x2000);
+        let mut loaded = Nrom::new(Rom {
+            prg_ram_size: 0x2000,
+            battery: true,
+            ..Default::default()
+        });
+        loaded.load_save_data(&save);
+        assert_eq!(loaded.read_prg(0x6123), Some(0x45));
+    }
+}
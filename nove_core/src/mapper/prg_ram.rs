@@ -0,0 +1,64 @@
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::Rom;
+use crate::mapper::{load_memory, new_prg_ram};
+
+const PRG_RAM_START: u16 = 0x6000;
+
+/// Unbanked work RAM at $6000-$7FFF of the boards without a mapper chip to control it, sized
+/// by the header and kept by the battery when the cartridge has one
+pub struct PrgRam {
+    data: Vec<u8>,
+    battery: bool,
+}
+
+impl PrgRam {
+    pub fn new(rom: &Rom) -> Self {
+        Self {
+            data: new_prg_ram(rom, rom.prg_ram_total()),
+            battery: rom.battery,
+        }
+    }
+
+    /// Reads the RAM, `None` outside of its range or when the board has none
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        if !(PRG_RAM_START..PRG_ROM_START).contains(&addr) || self.data.is_empty() {
+            return None;
+        }
+        Some(self.data[(addr - PRG_RAM_START) as usize % self.data.len()])
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        if (PRG_RAM_START..PRG_ROM_START).contains(&addr) && !self.data.is_empty() {
+            let len = self.data.len();
+            self.data[(addr - PRG_RAM_START) as usize % len] = value;
+        }
+    }
+
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.data.clone())
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        load_memory(&mut self.data, data);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_and_write() {
+        let mut ram = PrgRam::new(&Rom {
+            prg_ram_size: 0x800,
+            ..Default::default()
+        });
+        ram.write(0x6001, 1);
+        assert_eq!(ram.read(0x6801), Some(1));
+        assert_eq!(ram.read(0x5fff), None);
+        assert_eq!(ram.save_data(), None);
+
+        let empty = PrgRam::new(&Rom::default());
+        assert_eq!(empty.read(0x6000), None);
+    }
+}
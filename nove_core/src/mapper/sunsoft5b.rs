@@ -0,0 +1,304 @@
+use crate::mapper::APU_PULSE_STEP;
+
+/// CPU cycles per clock of the generators, the 5B dividing its input clock by 16 like the AY
+const CLOCK_DIVIDER: u8 = 16;
+const CHANNELS: usize = 3;
+const ENVELOPE_STEPS: u8 = 32;
+const MAX_LEVEL: u8 = ENVELOPE_STEPS - 1;
+/// Attenuation of each of the 32 levels of the logarithmic DAC
+const LEVEL_DB: f32 = 1.5;
+/// A channel at full volume sounds about as loud as an APU pulse at full volume
+const FULL_VOLUME: f32 = 15.0 * APU_PULSE_STEP;
+
+const VOLUME_MASK: u8 = 0b0000_1111;
+const VOLUME_ENVELOPE: u8 = 0b0001_0000;
+
+/*
+   Envelope shape ($0D)
+
+   7  bit  0
+   ---- ----
+   xxxx CAaH
+        ||||
+        |||+- Hold the last level after the first cycle
+        ||+-- Alternate the direction every cycle
+        |+--- Attack, rising instead of falling
+        +---- Continue after the first cycle, otherwise the level falls to zero
+*/
+const ENVELOPE_HOLD: u8 = 0b0001;
+const ENVELOPE_ALTERNATE: u8 = 0b0010;
+const ENVELOPE_ATTACK: u8 = 0b0100;
+const ENVELOPE_CONTINUE: u8 = 0b1000;
+
+#[derive(Debug, Default)]
+struct Tone {
+    period: u16,
+    counter: u16,
+    high: bool,
+}
+
+impl Tone {
+    fn clock(&mut self) {
+        self.counter += 1;
+        if self.counter >= self.period.max(1) {
+            self.counter = 0;
+            self.high = !self.high;
+        }
+    }
+}
+
+/// 17 bit LFSR shifted at half the rate of the tone generators
+#[derive(Debug)]
+struct Noise {
+    period: u8,
+    counter: u8,
+    half: bool,
+    lfsr: u32,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            period: 0,
+            counter: 0,
+            half: false,
+            lfsr: 1,
+        }
+    }
+}
+
+impl Noise {
+    fn clock(&mut self) {
+        self.half = !self.half;
+        if self.half {
+            return;
+        }
+        self.counter += 1;
+        if self.counter >= self.period.max(1) {
+            self.counter = 0;
+            let feedback = (self.lfsr ^ (self.lfsr >> 3)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (feedback << 16);
+        }
+    }
+
+    fn high(&self) -> bool {
+        self.lfsr & 1 != 0
+    }
+}
+
+#[derive(Debug, Default)]
+struct Envelope {
+    period: u16,
+    counter: u16,
+    shape: u8,
+    step: u8,
+    attack: bool,
+    holding: bool,
+}
+
+impl Envelope {
+    fn write_shape(&mut self, shape: u8) {
+        self.shape = shape;
+        self.attack = shape & ENVELOPE_ATTACK != 0;
+        self.step = 0;
+        self.counter = 0;
+        self.holding = false;
+    }
+
+    fn clock(&mut self) {
+        self.counter += 1;
+        if self.counter < self.period.max(1) {
+            return;
+        }
+        self.counter = 0;
+        if self.holding {
+            return;
+        }
+        self.step += 1;
+        if self.step < ENVELOPE_STEPS {
+            return;
+        }
+
+        let alternate = self.shape & ENVELOPE_ALTERNATE != 0;
+        if self.shape & ENVELOPE_CONTINUE == 0 {
+            self.holding = true;
+            self.attack = false;
+            self.step = MAX_LEVEL;
+        } else if self.shape & ENVELOPE_HOLD != 0 {
+            self.holding = true;
+            self.attack ^= alternate;
+            self.step = MAX_LEVEL;
+        } else {
+            self.attack ^= alternate;
+            self.step = 0;
+        }
+    }
+
+    fn level(&self) -> u8 {
+        if self.attack {
+            self.step
+        } else {
+            MAX_LEVEL - self.step
+        }
+    }
+}
+
+/// Sunsoft 5B audio, a licensed YM2149 with three square channels that can be mixed with noise
+/// and an envelope generator. Its 16 registers are selected through $C000 and written to $E000.
+#[derive(Debug, Default)]
+pub struct Sunsoft5b {
+    register: u8,
+    tones: [Tone; CHANNELS],
+    noise: Noise,
+    envelope: Envelope,
+    /// Tone disable on the bits 0-2 and noise disable on the bits 3-5
+    mixer: u8,
+    volumes: [u8; CHANNELS],
+    divider: u8,
+}
+
+impl Sunsoft5b {
+    pub fn select(&mut self, register: u8) {
+        self.register = register;
+    }
+
+    pub fn write(&mut self, value: u8) {
+        match self.register {
+            0..=5 => {
+                let tone = &mut self.tones[self.register as usize / 2];
+                tone.period = if self.register.is_multiple_of(2) {
+                    (tone.period & 0xf00) | value as u16
+                } else {
+                    (tone.period & 0x0ff) | ((value & 0x0f) as u16) << 8
+                };
+            }
+            6 => self.noise.period = value & 0b0001_1111,
+            7 => self.mixer = value,
+            8..=0xa => self.volumes[self.register as usize - 8] = value,
+            0xb => self.envelope.period = (self.envelope.period & 0xff00) | value as u16,
+            0xc => self.envelope.period = (self.envelope.period & 0x00ff) | (value as u16) << 8,
+            0xd => self.envelope.write_shape(value),
+            _ => {}
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u8) {
+        for _ in 0..cycles {
+            self.divider += 1;
+            if self.divider < CLOCK_DIVIDER {
+                continue;
+            }
+            self.divider = 0;
+            self.tones.iter_mut().for_each(Tone::clock);
+            self.noise.clock();
+            self.envelope.clock();
+        }
+    }
+
+    pub fn output(&self) -> f32 {
+        (0..CHANNELS)
+            .map(|channel| {
+                let tone = self.tones[channel].high || self.mixer & (1 << channel) != 0;
+                let noise = self.noise.high() || self.mixer & (1 << (channel + 3)) != 0;
+                if tone && noise {
+                    amplitude(self.level(channel))
+                } else {
+                    0.0
+                }
+            })
+            .sum::<f32>()
+            * FULL_VOLUME
+    }
+
+    /// Level of the 32 of the DAC, the fixed volumes using every other one
+    fn level(&self, channel: usize) -> u8 {
+        let volume = self.volumes[channel];
+        match volume & VOLUME_MASK {
+            _ if volume & VOLUME_ENVELOPE != 0 => self.envelope.level(),
+            0 => 0,
+            volume => volume * 2 + 1,
+        }
+    }
+}
+
+/// Relative amplitude of a level of the logarithmic DAC
+fn amplitude(level: u8) -> f32 {
+    if level == 0 {
+        return 0.0;
+    }
+    10f32.powf(-((MAX_LEVEL - level) as f32) * LEVEL_DB / 20.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write(audio: &mut Sunsoft5b, register: u8, value: u8) {
+        audio.select(register);
+        audio.write(value);
+    }
+
+    #[test]
+    fn tone() {
+        let mut audio = Sunsoft5b::default();
+        write(&mut audio, 0, 2);
+        write(&mut audio, 7, 0b11_1110);
+        write(&mut audio, 8, 15);
+        assert_eq!(audio.output(), 0.0);
+
+        audio.tick(2 * CLOCK_DIVIDER);
+        assert_eq!(audio.output(), FULL_VOLUME);
+        audio.tick(2 * CLOCK_DIVIDER);
+        assert_eq!(audio.output(), 0.0);
+    }
+
+    #[test]
+    fn volume_levels() {
+        assert_eq!(amplitude(0), 0.0);
+        assert_eq!(amplitude(MAX_LEVEL), 1.0);
+        // 3 dB for each step of the fixed volume
+        let ratio = amplitude(29) / amplitude(31);
+        assert!((ratio - 0.708).abs() < 0.001);
+    }
+
+    #[test]
+    fn envelope_shapes() {
+        let cycle = |shape: u8| {
+            let mut envelope = Envelope {
+                period: 1,
+                ..Default::default()
+            };
+            envelope.write_shape(shape);
+            let start = envelope.level();
+            (0..ENVELOPE_STEPS).for_each(|_| envelope.clock());
+            let second = envelope.level();
+            (0..ENVELOPE_STEPS).for_each(|_| envelope.clock());
+            (start, second, envelope.level())
+        };
+        // decay and stay silent
+        assert_eq!(cycle(0b0000), (31, 0, 0));
+        // sawtooth
+        assert_eq!(cycle(0b1100), (0, 0, 0));
+        // triangle
+        assert_eq!(cycle(0b1110), (0, 31, 0));
+        // attack and hold
+        assert_eq!(cycle(0b1101), (0, 31, 31));
+        // decay and hold high
+        assert_eq!(cycle(0b1011), (31, 31, 31));
+    }
+
+    #[test]
+    fn noise_mixing() {
+        let mut audio = Sunsoft5b::default();
+        write(&mut audio, 7, 0b11_0111);
+        write(&mut audio, 8, 15);
+        let outputs: Vec<bool> = (0..64)
+            .map(|_| {
+                audio.tick(2 * CLOCK_DIVIDER);
+                audio.output() > 0.0
+            })
+            .collect();
+        assert!(outputs.contains(&true));
+        assert!(outputs.contains(&false));
+    }
+}
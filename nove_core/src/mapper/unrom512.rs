@@ -0,0 +1,303 @@
+use crate::addresses::ppu::VRAM_START;
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::{read_bank, Mapper, CHR_BANK_8K, PRG_BANK_16K};
+use crate::Program;
+
+const FIXED_BANK_START: u16 = 0xc000;
+const CHR_RAM_SIZE: usize = 4 * CHR_BANK_8K;
+/// With four-screen mirroring the nametables are taken from the last 8 KiB of the CHR RAM
+const NAMETABLES_START: usize = CHR_RAM_SIZE - CHR_BANK_8K;
+
+/*
+   Bank register ($8000-$FFFF, $C000-$FFFF on flashable boards)
+
+   7  bit  0
+   ---- ----
+   MCCP PPPP
+   |||+-++++- 16 KiB PRG bank at $8000
+   |++------- 8 KiB CHR RAM bank
+   +--------- Nametable page with the switchable single screen mirroring
+*/
+const PRG_BANK_MASK: u8 = 0b0001_1111;
+const CHR_BANK_SHIFT: u8 = 5;
+const CHR_BANK_MASK: u8 = 0b11;
+const SCREEN_SELECT: u8 = 0b1000_0000;
+
+/// The SST39SF040 decodes its commands with the address lines A0-A14
+const COMMAND_ADDR_MASK: usize = 0x7fff;
+const UNLOCK_ADDR_1: usize = 0x5555;
+const UNLOCK_ADDR_2: usize = 0x2aaa;
+const SECTOR_SIZE: usize = 0x1000;
+const MANUFACTURER_ID: u8 = 0xbf;
+const DEVICE_ID: u8 = 0xb7;
+
+/// Progress of the command sequences of the flash memory
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum FlashState {
+    #[default]
+    Ready,
+    Unlocked,
+    Command,
+    Program,
+    Erase,
+    EraseUnlocked,
+    EraseCommand,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Nametables {
+    Fixed(Mirroring),
+    SingleScreen,
+    FourScreen,
+}
+
+/// Mapper 30, the UNROM 512. A UxROM with 32 KiB of banked CHR RAM whose flashable boards can
+/// reprogram their own PRG ROM, an SST39SF040, to keep the progress of the games.
+pub struct Unrom512 {
+    prg_rom: Program,
+    chr_ram: Program,
+    nametables: Nametables,
+    flashable: bool,
+    bank: u8,
+    flash: FlashState,
+    software_id: bool,
+}
+
+impl Unrom512 {
+    pub fn new(rom: Rom) -> Self {
+        let nametables = match (rom.screen_mirroring, rom.mirroring_bit) {
+            (Mirroring::FourScreen, false) => Nametables::SingleScreen,
+            (Mirroring::FourScreen, true) => Nametables::FourScreen,
+            (mirroring, _) => Nametables::Fixed(mirroring),
+        };
+        let mut chr_ram = rom.chr_rom;
+        chr_ram.resize(CHR_RAM_SIZE, 0);
+        Self {
+            prg_rom: rom.prg_rom,
+            chr_ram,
+            nametables,
+            flashable: rom.battery,
+            bank: 0,
+            flash: FlashState::Ready,
+            software_id: false,
+        }
+    }
+
+    fn last_bank(&self) -> usize {
+        (self.prg_rom.len() / PRG_BANK_16K).saturating_sub(1)
+    }
+
+    fn chr_addr(&self, addr: u16) -> usize {
+        let bank = ((self.bank >> CHR_BANK_SHIFT) & CHR_BANK_MASK) as usize;
+        bank * CHR_BANK_8K + addr as usize % CHR_BANK_8K
+    }
+
+    /// Address in the flash memory of a CPU address of the switchable bank
+    fn flash_addr(&self, addr: u16) -> usize {
+        let bank = (self.bank & PRG_BANK_MASK) as usize;
+        (bank * PRG_BANK_16K + (addr - PRG_ROM_START) as usize) % self.prg_rom.len()
+    }
+
+    fn write_flash(&mut self, addr: usize, value: u8) {
+        let command = addr & COMMAND_ADDR_MASK;
+        self.flash = match (self.flash, command, value) {
+            (_, _, 0xf0) => {
+                self.software_id = false;
+                FlashState::Ready
+            }
+            (FlashState::Ready, UNLOCK_ADDR_1, 0xaa) => FlashState::Unlocked,
+            (FlashState::Unlocked, UNLOCK_ADDR_2, 0x55) => FlashState::Command,
+            (FlashState::Command, UNLOCK_ADDR_1, 0xa0) => FlashState::Program,
+            (FlashState::Command, UNLOCK_ADDR_1, 0x80) => FlashState::Erase,
+            (FlashState::Command, UNLOCK_ADDR_1, 0x90) => {
+                self.software_id = true;
+                FlashState::Ready
+            }
+            (FlashState::Program, _, _) => {
+                // programming can only clear bits, erasing is needed to set them again
+                self.prg_rom[addr] &= value;
+                FlashState::Ready
+            }
+            (FlashState::Erase, UNLOCK_ADDR_1, 0xaa) => FlashState::EraseUnlocked,
+            (FlashState::EraseUnlocked, UNLOCK_ADDR_2, 0x55) => FlashState::EraseCommand,
+            (FlashState::EraseCommand, UNLOCK_ADDR_1, 0x10) => {
+                self.prg_rom.fill(0xff);
+                FlashState::Ready
+            }
+            (FlashState::EraseCommand, _, 0x30) => {
+                let sector = addr - addr % SECTOR_SIZE;
+                self.prg_rom[sector..sector + SECTOR_SIZE].fill(0xff);
+                FlashState::Ready
+            }
+            _ => FlashState::Ready,
+        };
+    }
+}
+
+impl Mapper for Unrom512 {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START.. if self.software_id => Some(if addr.is_multiple_of(2) {
+                MANUFACTURER_ID
+            } else {
+                DEVICE_ID
+            }),
+            PRG_ROM_START..FIXED_BANK_START => Some(self.prg_rom[self.flash_addr(addr)]),
+            FIXED_BANK_START.. => Some(read_bank(
+                &self.prg_rom,
+                self.last_bank(),
+                PRG_BANK_16K,
+                addr,
+            )),
+            _ => None,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        match addr {
+            PRG_ROM_START..FIXED_BANK_START if self.flashable => {
+                self.write_flash(self.flash_addr(addr), value)
+            }
+            PRG_ROM_START.. => self.bank = value,
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr_ram[self.chr_addr(addr)]
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        let i = self.chr_addr(addr);
+        self.chr_ram[i] = value;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.nametables {
+            Nametables::Fixed(mirroring) => mirroring,
+            Nametables::SingleScreen if self.bank & SCREEN_SELECT != 0 => {
+                Mirroring::SingleScreenUpper
+            }
+            Nametables::SingleScreen => Mirroring::SingleScreenLower,
+            Nametables::FourScreen => Mirroring::FourScreen,
+        }
+    }
+
+    fn read_nametable(&self, addr: u16, vram: &[u8]) -> u8 {
+        match self.nametables {
+            Nametables::FourScreen => {
+                self.chr_ram[NAMETABLES_START + (addr - VRAM_START) as usize % CHR_BANK_8K]
+            }
+            _ => vram[self.mirroring().vram_addr(addr)],
+        }
+    }
+
+    fn write_nametable(&mut self, addr: u16, value: u8, vram: &mut [u8]) {
+        match self.nametables {
+            Nametables::FourScreen => {
+                self.chr_ram[NAMETABLES_START + (addr - VRAM_START) as usize % CHR_BANK_8K] = value
+            }
+            _ => vram[self.mirroring().vram_addr(addr)] = value,
+        }
+    }
+
+    /// The flash memory is the whole PRG ROM, saved when the board is flashable
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.flashable.then(|| self.prg_rom.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        if data.len() == self.prg_rom.len() {
+            self.prg_rom.copy_from_slice(data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn unrom512(flashable: bool) -> Unrom512 {
+        Unrom512::new(Rom {
+            prg_rom: (0..32).flat_map(|i| vec![i; PRG_BANK_16K]).collect(),
+            screen_mirroring: Mirroring::FourScreen,
+            battery: flashable,
+            ..Default::default()
+        })
+    }
+
+    /// Writes to a flash address through the CPU, switching to its bank
+    fn write_flash(unrom: &mut Unrom512, addr: usize, value: u8) {
+        unrom.write_prg(0xc000, (addr / PRG_BANK_16K) as u8);
+        unrom.write_prg(PRG_ROM_START + (addr % PRG_BANK_16K) as u16, value);
+    }
+
+    fn command(unrom: &mut Unrom512, command: u8) {
+        write_flash(unrom, UNLOCK_ADDR_1, 0xaa);
+        write_flash(unrom, UNLOCK_ADDR_2, 0x55);
+        write_flash(unrom, UNLOCK_ADDR_1, command);
+    }
+
+    #[test]
+    fn banks() {
+        let mut unrom = unrom512(false);
+        unrom.write_prg(0x8000, 0b1100_0011);
+        assert_eq!(unrom.read_prg(0x8000), Some(3));
+        assert_eq!(unrom.read_prg(0xc000), Some(31));
+        unrom.write_chr(0x0010, 5);
+        assert_eq!(unrom.chr_ram[2 * CHR_BANK_8K + 0x10], 5);
+        assert_eq!(unrom.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn four_screen() {
+        let mut unrom = Unrom512::new(Rom {
+            screen_mirroring: Mirroring::FourScreen,
+            mirroring_bit: true,
+            ..Default::default()
+        });
+        let mut vram = [0; 0x800];
+        unrom.write_nametable(0x2c01, 7, &mut vram);
+        assert_eq!(unrom.read_nametable(0x2c01, &vram), 7);
+        assert_eq!(unrom.chr_ram[NAMETABLES_START + 0xc01], 7);
+        assert_eq!(vram, [0; 0x800]);
+    }
+
+    #[test]
+    fn program_and_erase() {
+        let mut unrom = unrom512(true);
+        command(&mut unrom, 0x80);
+        write_flash(&mut unrom, UNLOCK_ADDR_1, 0xaa);
+        write_flash(&mut unrom, UNLOCK_ADDR_2, 0x55);
+        write_flash(&mut unrom, 5 * PRG_BANK_16K + 0x1234, 0x30);
+        assert_eq!(unrom.prg_rom[5 * PRG_BANK_16K + 0x0fff], 5);
+        assert_eq!(unrom.prg_rom[5 * PRG_BANK_16K + 0x1000], 0xff);
+        assert_eq!(unrom.prg_rom[5 * PRG_BANK_16K + 0x1fff], 0xff);
+        assert_eq!(unrom.prg_rom[5 * PRG_BANK_16K + 0x2000], 5);
+
+        command(&mut unrom, 0xa0);
+        write_flash(&mut unrom, 5 * PRG_BANK_16K + 0x1000, 0x42);
+        assert_eq!(unrom.read_prg(0x9000), Some(0x42));
+        // without a command the writes to the flash are ignored
+        write_flash(&mut unrom, 5 * PRG_BANK_16K + 0x1000, 0x00);
+        assert_eq!(unrom.read_prg(0x9000), Some(0x42));
+
+        let save = unrom.save_data().unwrap();
+        let mut loaded = unrom512(true);
+        loaded.load_save_data(&save);
+        assert_eq!(loaded.prg_rom, unrom.prg_rom);
+        assert_eq!(unrom512(false).save_data(), None);
+    }
+
+    #[test]
+    fn software_id() {
+        let mut unrom = unrom512(true);
+        command(&mut unrom, 0x90);
+        assert_eq!(unrom.read_prg(0x8000), Some(MANUFACTURER_ID));
+        assert_eq!(unrom.read_prg(0x8001), Some(DEVICE_ID));
+        unrom.write_prg(0x8000, 0xf0);
+        // the last command left the bank of $5555 selected
+        assert_eq!(unrom.read_prg(0x8000), Some(1));
+    }
+}
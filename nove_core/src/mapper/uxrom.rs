@@ -0,0 +1,97 @@
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::chr::Chr;
+use crate::mapper::prg_ram::PrgRam;
+use crate::mapper::{read_bank, Mapper, CHR_BANK_8K, PRG_BANK_16K};
+use crate::Program;
+
+const FIXED_BANK_START: u16 = 0xc000;
+
+/// Mapper 2, switchable 16 KiB bank at $8000-$BFFF and the last bank fixed at $C000-$FFFF
+pub struct Uxrom {
+    prg_rom: Program,
+    chr: Chr,
+    prg_ram: PrgRam,
+    mirroring: Mirroring,
+    bank: usize,
+}
+
+impl Uxrom {
+    pub fn new(rom: Rom) -> Self {
+        Self {
+            chr: Chr::new(&rom),
+            prg_ram: PrgRam::new(&rom),
+            prg_rom: rom.prg_rom,
+            mirroring: rom.screen_mirroring,
+            bank: 0,
+        }
+    }
+
+    fn last_bank(&self) -> usize {
+        (self.prg_rom.len() / PRG_BANK_16K).saturating_sub(1)
+    }
+}
+
+impl Mapper for Uxrom {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START..FIXED_BANK_START => {
+                Some(read_bank(&self.prg_rom, self.bank, PRG_BANK_16K, addr))
+            }
+            FIXED_BANK_START.. => Some(read_bank(
+                &self.prg_rom,
+                self.last_bank(),
+                PRG_BANK_16K,
+                addr,
+            )),
+            _ => self.prg_ram.read(addr),
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        if addr >= PRG_ROM_START {
+            self.bank = value as usize;
+        } else {
+            self.prg_ram.write(addr, value);
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr.read(0, CHR_BANK_8K, addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        self.chr.write(0, CHR_BANK_8K, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.prg_ram.save_data()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.prg_ram.load_save_data(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bank_switching() {
+        let prg_rom: Program = (0..8).flat_map(|i| vec![i; PRG_BANK_16K]).collect();
+        let mut uxrom = Uxrom::new(Rom {
+            prg_rom,
+            ..Default::default()
+        });
+        assert_eq!(uxrom.read_prg(0x8000), Some(0));
+        assert_eq!(uxrom.read_prg(0xc000), Some(7));
+        uxrom.write_prg(0x8000, 5);
+        assert_eq!(uxrom.read_prg(0xbfff), Some(5));
+        assert_eq!(uxrom.read_prg(0xffff), Some(7));
+    }
+}
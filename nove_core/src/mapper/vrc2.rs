@@ -0,0 +1,311 @@
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::chr::Chr;
+use crate::mapper::vrc_irq::VrcIrq;
+use crate::mapper::{bank_from_end, load_memory, new_prg_ram, read_bank, Mapper, PRG_BANK_8K};
+use crate::Program;
+
+const PRG_RAM_START: u16 = 0x6000;
+const MICROWIRE_END: u16 = 0x6fff;
+const CHR_BANK_1K: usize = 0x400;
+
+const PRG_BANK_MASK: u8 = 0b0001_1111;
+const VRC4_SWAP_MODE: u8 = 0b0000_0010;
+
+/// Both chips share the banking, the VRC4 adding the PRG swap mode, the single screen mirroring,
+/// an extra CHR bank bit and the IRQ counter
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Chip {
+    Vrc2,
+    Vrc4,
+}
+
+/// Konami VRC2 and VRC4, mappers 21, 22, 23 and 25. Each board wires two of the CPU address
+/// lines to the register select pins of the chip, the submapper telling which ones.
+pub struct Vrc2 {
+    prg_rom: Program,
+    chr: Chr,
+    prg_ram: Vec<u8>,
+    battery: bool,
+    chip: Chip,
+    /// CPU address lines connected to the A0 and A1 pins, several when the submapper is unknown
+    address_lines: (u16, u16),
+    /// VRC2a ignores the lowest bit of the CHR banks
+    chr_shift: u8,
+    prg_banks: [u8; 2],
+    prg_swap: bool,
+    chr_banks: [u16; 8],
+    mirroring: Mirroring,
+    microwire: u8,
+    irq: VrcIrq,
+}
+
+impl Vrc2 {
+    pub fn new(rom: Rom) -> Self {
+        use Chip::*;
+        let (chip, address_lines, chr_shift) = match (rom.mapper, rom.submapper) {
+            (21, 1) => (Vrc4, (0x02, 0x04), 0),
+            (21, 2) => (Vrc4, (0x40, 0x80), 0),
+            (21, _) => (Vrc4, (0x42, 0x84), 0),
+            (22, _) => (Vrc2, (0x02, 0x01), 1),
+            (23, 1) => (Vrc4, (0x01, 0x02), 0),
+            (23, 2) => (Vrc4, (0x04, 0x08), 0),
+            (23, 3) => (Vrc2, (0x01, 0x02), 0),
+            (23, _) => (Vrc4, (0x05, 0x0a), 0),
+            (25, 1) => (Vrc4, (0x02, 0x01), 0),
+            (25, 2) => (Vrc4, (0x08, 0x04), 0),
+            (25, 3) => (Vrc2, (0x02, 0x01), 0),
+            (_, _) => (Vrc4, (0x0a, 0x05), 0),
+        };
+        let prg_ram = match chip {
+            Vrc2 => vec![],
+            Vrc4 => new_prg_ram(&rom, rom.prg_ram_total().max(PRG_BANK_8K)),
+        };
+        Self {
+            chr: Chr::new(&rom),
+            prg_rom: rom.prg_rom,
+            prg_ram,
+            battery: rom.battery,
+            chip,
+            address_lines,
+            chr_shift,
+            prg_banks: [0; 2],
+            prg_swap: false,
+            chr_banks: [0; 8],
+            mirroring: rom.screen_mirroring,
+            microwire: 0,
+            irq: Default::default(),
+        }
+    }
+
+    /// Register of the chip selected by the wiring of the address lines, from 0 to 3
+    fn register(&self, addr: u16) -> u16 {
+        let (a0, a1) = self.address_lines;
+        (addr & a0 != 0) as u16 | ((addr & a1 != 0) as u16) << 1
+    }
+
+    fn prg_bank(&self, addr: u16) -> usize {
+        match ((addr - PRG_ROM_START) as usize / PRG_BANK_8K, self.prg_swap) {
+            (0, false) | (2, true) => self.prg_banks[0] as usize,
+            (0, true) | (2, false) => bank_from_end(&self.prg_rom, PRG_BANK_8K, 2),
+            (1, _) => self.prg_banks[1] as usize,
+            _ => bank_from_end(&self.prg_rom, PRG_BANK_8K, 1),
+        }
+    }
+
+    fn chr_bank(&self, addr: u16) -> usize {
+        (self.chr_banks[addr as usize / CHR_BANK_1K] >> self.chr_shift) as usize
+    }
+
+    fn write_chr_bank(&mut self, slot: usize, high: bool, value: u8) {
+        let bank = &mut self.chr_banks[slot];
+        *bank = if high {
+            let mask = match self.chip {
+                Chip::Vrc2 => 0x0f,
+                Chip::Vrc4 => 0x1f,
+            };
+            (*bank & 0x0f) | ((value & mask) as u16) << 4
+        } else {
+            (*bank & !0x0f) | (value & 0x0f) as u16
+        };
+    }
+
+    fn write_mirroring(&mut self, value: u8) {
+        self.mirroring = match (self.chip, value & 0b11) {
+            (Chip::Vrc2, value) if value & 1 == 0 => Mirroring::Vertical,
+            (Chip::Vrc2, _) => Mirroring::Horizontal,
+            (Chip::Vrc4, 0) => Mirroring::Vertical,
+            (Chip::Vrc4, 1) => Mirroring::Horizontal,
+            (Chip::Vrc4, 2) => Mirroring::SingleScreenLower,
+            (Chip::Vrc4, _) => Mirroring::SingleScreenUpper,
+        }
+    }
+}
+
+impl Mapper for Vrc2 {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START.. => Some(read_bank(
+                &self.prg_rom,
+                self.prg_bank(addr),
+                PRG_BANK_8K,
+                addr,
+            )),
+            PRG_RAM_START.. if !self.prg_ram.is_empty() => {
+                Some(self.prg_ram[(addr - PRG_RAM_START) as usize % self.prg_ram.len()])
+            }
+            PRG_RAM_START..=MICROWIRE_END if self.chip == Chip::Vrc2 => Some(self.microwire),
+            _ => None,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        let register = self.register(addr);
+        match (addr & 0xf000, register) {
+            (0x8000, _) => self.prg_banks[0] = value & PRG_BANK_MASK,
+            (0x9000, 0) => self.write_mirroring(value),
+            (0x9000, _) if self.chip == Chip::Vrc2 => self.write_mirroring(value),
+            (0x9000, 2) => self.prg_swap = value & VRC4_SWAP_MODE != 0,
+            (0xa000, _) => self.prg_banks[1] = value & PRG_BANK_MASK,
+            (0xb000..=0xe000, _) => {
+                let slot = ((addr & 0xf000) - 0xb000) as usize / 0x800 + register as usize / 2;
+                self.write_chr_bank(slot, register & 1 != 0, value);
+            }
+            (0xf000, _) if self.chip == Chip::Vrc2 => {}
+            (0xf000, 0) => self.irq.write_latch_nibble(false, value),
+            (0xf000, 1) => self.irq.write_latch_nibble(true, value),
+            (0xf000, 2) => self.irq.write_control(value),
+            (0xf000, 3) => self.irq.acknowledge(),
+            (0x6000 | 0x7000, _) if !self.prg_ram.is_empty() => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - PRG_RAM_START) as usize % len] = value;
+            }
+            (0x6000, _) if self.chip == Chip::Vrc2 => self.microwire = value & 1,
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr.read(self.chr_bank(addr), CHR_BANK_1K, addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        self.chr
+            .write(self.chr_bank(addr), CHR_BANK_1K, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        self.irq.tick(cycles);
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.irq()
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        (self.battery && !self.prg_ram.is_empty()).then(|| self.prg_ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_memory(&mut self.prg_ram, data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::CHR_BANK_8K;
+
+    fn vrc(mapper: u16, submapper: u8) -> Vrc2 {
+        Vrc2::new(Rom {
+            prg_rom: (0..16).flat_map(|i| vec![i; PRG_BANK_8K]).collect(),
+            chr_rom: (0..=255).flat_map(|i| vec![i; CHR_BANK_1K]).collect(),
+            mapper,
+            submapper,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn prg_banks() {
+        let mut vrc4 = vrc(25, 1);
+        vrc4.write_prg(0x8000, 3);
+        vrc4.write_prg(0xa000, 4);
+        assert_eq!(vrc4.read_prg(0x8000), Some(3));
+        assert_eq!(vrc4.read_prg(0xa000), Some(4));
+        assert_eq!(vrc4.read_prg(0xc000), Some(14));
+        assert_eq!(vrc4.read_prg(0xe000), Some(15));
+
+        // register 2 is $9001 on VRC4b, whose A0 line drives the A1 pin
+        vrc4.write_prg(0x9001, VRC4_SWAP_MODE);
+        assert_eq!(vrc4.read_prg(0x8000), Some(14));
+        assert_eq!(vrc4.read_prg(0xc000), Some(3));
+    }
+
+    #[test]
+    fn address_lines() {
+        for (mapper, submapper, addr) in [(21, 1, 0xb006), (21, 2, 0xb0c0), (23, 2, 0xb00c)] {
+            let mut vrc4 = vrc(mapper, submapper);
+            vrc4.write_prg(addr & !0xff, 0x5);
+            vrc4.write_prg(addr, 0x1f);
+            assert_eq!(vrc4.chr_banks[1], 0x1f0, "mapper {mapper}.{submapper}");
+            assert_eq!(vrc4.chr_banks[0], 0x005, "mapper {mapper}.{submapper}");
+        }
+
+        // unknown submapper, both wirings are decoded
+        let mut vrc4 = vrc(21, 0);
+        vrc4.write_prg(0xb004, 1);
+        vrc4.write_prg(0xb0c0, 2);
+        assert_eq!(vrc4.chr_banks[1], 0x21);
+    }
+
+    #[test]
+    fn chr_banks() {
+        let mut vrc2a = vrc(22, 0);
+        vrc2a.write_prg(0xe001, 0x4);
+        vrc2a.write_prg(0xe003, 0x1f);
+        assert_eq!(vrc2a.read_chr(0x1c00), 0xf4 >> 1);
+
+        let mut vrc4 = vrc(23, 1);
+        vrc4.write_prg(0xd000, 0x4);
+        vrc4.write_prg(0xd001, 0x1f);
+        assert_eq!(vrc4.chr_banks[4], 0x1f4);
+    }
+
+    #[test]
+    fn mirroring() {
+        let mut vrc2 = vrc(23, 3);
+        vrc2.write_prg(0x9000, 3);
+        assert_eq!(vrc2.mirroring(), Mirroring::Horizontal);
+
+        let mut vrc4 = vrc(23, 1);
+        vrc4.write_prg(0x9000, 3);
+        assert_eq!(vrc4.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn microwire_latch() {
+        let mut vrc2 = vrc(22, 0);
+        vrc2.write_prg(0x6000, 0xff);
+        assert_eq!(vrc2.read_prg(0x6100), Some(1));
+        assert_eq!(vrc2.read_prg(0x7000), None);
+
+        let mut vrc4 = vrc(21, 1);
+        vrc4.write_prg(0x7000, 0xff);
+        assert_eq!(vrc4.read_prg(0x7000), Some(0xff));
+    }
+
+    #[test]
+    fn irq() {
+        let mut vrc4 = vrc(21, 1);
+        vrc4.write_prg(0xf000, 0xf);
+        vrc4.write_prg(0xf002, 0xe);
+        vrc4.write_prg(0xf004, 0b110);
+        vrc4.tick(16);
+        assert!(!vrc4.irq());
+        vrc4.tick(1);
+        assert!(vrc4.irq());
+        vrc4.write_prg(0xf006, 0);
+        assert!(!vrc4.irq());
+    }
+
+    #[test]
+    fn chr_ram_banks() {
+        let mut vrc4 = Vrc2::new(Rom {
+            chr_ram_size: CHR_BANK_8K,
+            mapper: 23,
+            submapper: 1,
+            ..Default::default()
+        });
+        vrc4.write_prg(0xb000, 3);
+        vrc4.write_prg(0xb002, 3);
+        vrc4.write_chr(0x0012, 5);
+        assert_eq!(vrc4.read_chr(0x0412), 5);
+        vrc4.write_prg(0xb000, 4);
+        assert_eq!(vrc4.read_chr(0x0012), 0);
+    }
+}
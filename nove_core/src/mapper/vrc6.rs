@@ -0,0 +1,419 @@
+use crate::addresses::rom::PRG_ROM_START;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::chr::Chr;
+use crate::mapper::vrc_irq::VrcIrq;
+use crate::mapper::{
+    bank_from_end, load_memory, new_prg_ram, read_bank, Mapper, APU_PULSE_STEP, PRG_BANK_16K,
+    PRG_BANK_8K,
+};
+use crate::Program;
+
+const PRG_RAM_START: u16 = 0x6000;
+const CHR_BANK_1K: usize = 0x400;
+const CHR_BANK_2K: usize = 0x800;
+
+/*
+   Banking control ($B003)
+
+   7  bit  0
+   ---- ----
+   WxxN MMPP
+   |  | ||||
+   |  | ||++- PPU banking mode (0: 8x1 KiB, 1: 4x2 KiB, 2 and 3: 4x1 KiB and 2x2 KiB)
+   |  | ++--- Mirroring (0: vertical, 1: horizontal, 2: single screen lower, 3: upper)
+   |  +------ Nametables from CHR ROM, unused by the released games
+   +--------- PRG RAM enable
+*/
+const PPU_BANKING_MASK: u8 = 0b0000_0011;
+const MIRRORING_MASK: u8 = 0b0000_1100;
+const PRG_RAM_ENABLE: u8 = 0b1000_0000;
+
+const CHANNEL_ENABLE: u8 = 0b1000_0000;
+const PULSE_IGNORE_DUTY: u8 = 0b1000_0000;
+const PULSE_VOLUME_MASK: u8 = 0b0000_1111;
+const SAWTOOTH_RATE_MASK: u8 = 0b0011_1111;
+const AUDIO_HALT: u8 = 0b0000_0001;
+const FREQUENCY_SHIFT_4: u8 = 0b0000_0010;
+const FREQUENCY_SHIFT_8: u8 = 0b0000_0100;
+/// Accumulations of the sawtooth before it resets, each one taking two clocks of its divider
+const SAWTOOTH_STEPS: u8 = 14;
+
+/// Square channel with 16 steps and 8 duty cycles
+#[derive(Debug, Default)]
+struct Pulse {
+    control: u8,
+    period: u16,
+    enabled: bool,
+    divider: u16,
+    step: u8,
+}
+
+impl Pulse {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => self.control = value,
+            1 => self.period = (self.period & 0xf00) | value as u16,
+            _ => {
+                self.period = (self.period & 0x0ff) | ((value & 0x0f) as u16) << 8;
+                self.enabled = value & CHANNEL_ENABLE != 0;
+                if !self.enabled {
+                    self.step = 15;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.divider == 0 {
+            self.divider = self.period >> shift;
+            self.step = self.step.wrapping_sub(1) & 0x0f;
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        let duty = (self.control >> 4) & 0b111;
+        let high = self.control & PULSE_IGNORE_DUTY != 0 || self.step <= duty;
+        if self.enabled && high {
+            self.control & PULSE_VOLUME_MASK
+        } else {
+            0
+        }
+    }
+}
+
+/// Sawtooth channel adding the accumulator rate every other clock
+#[derive(Debug, Default)]
+struct Sawtooth {
+    rate: u8,
+    period: u16,
+    enabled: bool,
+    divider: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Sawtooth {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => self.rate = value & SAWTOOTH_RATE_MASK,
+            1 => self.period = (self.period & 0xf00) | value as u16,
+            _ => {
+                self.period = (self.period & 0x0ff) | ((value & 0x0f) as u16) << 8;
+                self.enabled = value & CHANNEL_ENABLE != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.divider > 0 {
+            self.divider -= 1;
+            return;
+        }
+        self.divider = self.period >> shift;
+        self.step += 1;
+        if self.step == SAWTOOTH_STEPS {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step.is_multiple_of(2) {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+/// Konami VRC6, mappers 24 (VRC6a) and 26 (VRC6b, with the A0 and A1 lines swapped). Along with
+/// the banking and the VRC IRQ counter it has two pulse channels and a sawtooth channel.
+pub struct Vrc6 {
+    prg_rom: Program,
+    chr: Chr,
+    prg_ram: Vec<u8>,
+    battery: bool,
+    swapped_lines: bool,
+    prg_banks: [u8; 2],
+    chr_banks: [u8; 8],
+    banking: u8,
+    pulses: [Pulse; 2],
+    sawtooth: Sawtooth,
+    audio_control: u8,
+    irq: VrcIrq,
+}
+
+impl Vrc6 {
+    pub fn new(rom: Rom) -> Self {
+        Self {
+            swapped_lines: rom.mapper == 26,
+            prg_ram: new_prg_ram(&rom, rom.prg_ram_total().max(PRG_BANK_8K)),
+            battery: rom.battery,
+            chr: Chr::new(&rom),
+            prg_rom: rom.prg_rom,
+            prg_banks: [0; 2],
+            chr_banks: [0; 8],
+            banking: 0,
+            pulses: Default::default(),
+            sawtooth: Default::default(),
+            audio_control: 0,
+            irq: Default::default(),
+        }
+    }
+
+    /// Register selected by the A0 and A1 pins of the chip
+    fn register(&self, addr: u16) -> u16 {
+        if self.swapped_lines {
+            (addr & 1) << 1 | (addr & 2) >> 1
+        } else {
+            addr & 0b11
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.banking & PRG_RAM_ENABLE != 0
+    }
+
+    fn chr_bank(&self, addr: u16) -> (usize, usize) {
+        let slot = addr as usize / CHR_BANK_1K;
+        let banks = &self.chr_banks;
+        match (self.banking & PPU_BANKING_MASK, slot) {
+            (0, slot) => (banks[slot] as usize, CHR_BANK_1K),
+            (1, slot) => (banks[slot / 2] as usize, CHR_BANK_2K),
+            (_, 0..4) => (banks[slot] as usize, CHR_BANK_1K),
+            (_, slot) => (banks[4 + (slot - 4) / 2] as usize, CHR_BANK_2K),
+        }
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn read_prg(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            0xe000.. => Some(read_bank(
+                &self.prg_rom,
+                bank_from_end(&self.prg_rom, PRG_BANK_8K, 1),
+                PRG_BANK_8K,
+                addr,
+            )),
+            0xc000.. => Some(read_bank(
+                &self.prg_rom,
+                self.prg_banks[1] as usize,
+                PRG_BANK_8K,
+                addr,
+            )),
+            PRG_ROM_START.. => Some(read_bank(
+                &self.prg_rom,
+                self.prg_banks[0] as usize,
+                PRG_BANK_16K,
+                addr,
+            )),
+            PRG_RAM_START.. if self.prg_ram_enabled() => {
+                Some(self.prg_ram[(addr - PRG_RAM_START) as usize % self.prg_ram.len()])
+            }
+            _ => None,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, value: u8) {
+        let register = self.register(addr);
+        match (addr & 0xf000, register) {
+            (0x8000, _) => self.prg_banks[0] = value & 0x0f,
+            (0x9000, 3) => self.audio_control = value,
+            (0x9000, _) => self.pulses[0].write(register, value),
+            (0xa000, 3) => {}
+            (0xa000, _) => self.pulses[1].write(register, value),
+            (0xb000, 3) => self.banking = value,
+            (0xb000, _) => self.sawtooth.write(register, value),
+            (0xc000, _) => self.prg_banks[1] = value & 0x1f,
+            (0xd000, _) => self.chr_banks[register as usize] = value,
+            (0xe000, _) => self.chr_banks[4 + register as usize] = value,
+            (0xf000, 0) => self.irq.write_latch(value),
+            (0xf000, 1) => self.irq.write_control(value),
+            (0xf000, 2) => self.irq.acknowledge(),
+            (0x6000 | 0x7000, _) if self.prg_ram_enabled() => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - PRG_RAM_START) as usize % len] = value;
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        let (bank, size) = self.chr_bank(addr);
+        self.chr.read(bank, size, addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        let (bank, size) = self.chr_bank(addr);
+        self.chr.write(bank, size, addr, value)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match (self.banking & MIRRORING_MASK) >> 2 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        self.irq.tick(cycles);
+        if self.audio_control & AUDIO_HALT != 0 {
+            return;
+        }
+        let shift = if self.audio_control & FREQUENCY_SHIFT_8 != 0 {
+            8
+        } else if self.audio_control & FREQUENCY_SHIFT_4 != 0 {
+            4
+        } else {
+            0
+        };
+        for _ in 0..cycles {
+            self.pulses[0].clock(shift);
+            self.pulses[1].clock(shift);
+            self.sawtooth.clock(shift);
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.irq()
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.prg_ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_memory(&mut self.prg_ram, data)
+    }
+
+    /// Each step of the channels is as loud as a step of the pulses of the APU
+    fn audio_output(&self) -> f32 {
+        let output = self.pulses[0].output() + self.pulses[1].output() + self.sawtooth.output();
+        output as f32 * APU_PULSE_STEP
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::CHR_BANK_8K;
+
+    fn vrc6(mapper: u16) -> Vrc6 {
+        Vrc6::new(Rom {
+            prg_rom: (0..16).flat_map(|i| vec![i; PRG_BANK_8K]).collect(),
+            chr_rom: (0..64).flat_map(|i| vec![i; CHR_BANK_1K]).collect(),
+            mapper,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn prg_banks() {
+        let mut vrc6 = vrc6(24);
+        vrc6.write_prg(0x8000, 2);
+        vrc6.write_prg(0xc000, 9);
+        assert_eq!(vrc6.read_prg(0x8000), Some(4));
+        assert_eq!(vrc6.read_prg(0xa000), Some(5));
+        assert_eq!(vrc6.read_prg(0xc000), Some(9));
+        assert_eq!(vrc6.read_prg(0xe000), Some(15));
+
+        assert_eq!(vrc6.read_prg(0x6000), None);
+        vrc6.write_prg(0xb003, PRG_RAM_ENABLE);
+        vrc6.write_prg(0x6000, 1);
+        assert_eq!(vrc6.read_prg(0x6000), Some(1));
+    }
+
+    #[test]
+    fn chr_banks() {
+        let mut vrc6 = vrc6(26);
+        // $D002 selects the register 1 on VRC6b
+        vrc6.write_prg(0xd002, 7);
+        vrc6.write_prg(0xe001, 20);
+        vrc6.write_prg(0xe002, 30);
+        vrc6.write_prg(0xe003, 40);
+        assert_eq!(vrc6.read_chr(0x0400), 7);
+        assert_eq!(vrc6.read_chr(0x1800), 20);
+
+        vrc6.write_prg(0xb003, 0b0110);
+        assert_eq!(vrc6.mirroring(), Mirroring::Horizontal);
+        assert_eq!(vrc6.read_chr(0x1800), 30 * 2);
+        assert_eq!(vrc6.read_chr(0x1c00), 30 * 2 + 1);
+    }
+
+    #[test]
+    fn pulse() {
+        let mut vrc6 = vrc6(24);
+        // duty 2 of 16 at volume 10
+        vrc6.write_prg(0x9000, 0x2a);
+        vrc6.write_prg(0x9001, 1);
+        vrc6.write_prg(0x9002, CHANNEL_ENABLE);
+        let mut outputs = Vec::new();
+        for _ in 0..32 {
+            vrc6.tick(2);
+            outputs.push(vrc6.pulses[0].output());
+        }
+        assert_eq!(outputs.iter().filter(|&&o| o == 10).count(), 6);
+        assert_eq!(outputs.iter().filter(|&&o| o == 0).count(), 26);
+
+        vrc6.write_prg(0x9003, AUDIO_HALT);
+        let step = vrc6.pulses[0].step;
+        vrc6.tick(10);
+        assert_eq!(vrc6.pulses[0].step, step);
+    }
+
+    #[test]
+    fn sawtooth() {
+        let mut vrc6 = vrc6(24);
+        vrc6.write_prg(0xb000, 40);
+        vrc6.write_prg(0xb002, CHANNEL_ENABLE);
+        let outputs: Vec<u8> = (0..14)
+            .map(|_| {
+                vrc6.tick(1);
+                vrc6.sawtooth.output()
+            })
+            .collect();
+        assert_eq!(
+            outputs,
+            [0, 5, 5, 10, 10, 15, 15, 20, 20, 25, 25, 30, 30, 0]
+        );
+        assert_eq!(vrc6.audio_output(), 0.0);
+    }
+
+    #[test]
+    fn irq() {
+        let mut vrc6 = vrc6(24);
+        vrc6.write_prg(0xf000, 0xfe);
+        vrc6.write_prg(0xf001, 0b110);
+        vrc6.tick(2);
+        assert!(vrc6.irq());
+        vrc6.write_prg(0xf002, 0);
+        assert!(!vrc6.irq());
+    }
+
+    #[test]
+    fn chr_ram_banks() {
+        let mut vrc6 = Vrc6::new(Rom {
+            chr_ram_size: CHR_BANK_8K,
+            mapper: 24,
+            ..Default::default()
+        });
+        vrc6.write_prg(0xd000, 3);
+        vrc6.write_prg(0xd001, 3);
+        vrc6.write_chr(0x0012, 5);
+        assert_eq!(vrc6.read_chr(0x0412), 5);
+        vrc6.write_prg(0xd000, 4);
+        assert_eq!(vrc6.read_chr(0x0012), 0);
+    }
+}
@@ -0,0 +1,127 @@
+const PRESCALER_PERIOD: i16 = 341;
+const PRESCALER_STEP: i16 = 3;
+
+/*
+   IRQ control
+
+   7  bit  0
+   ---- ----
+   xxxx xMEA
+         |||
+         ||+- IRQ enable after acknowledgement
+         |+-- IRQ enable
+         +--- Mode (0: scanline, 1: CPU cycle)
+*/
+const ENABLE_AFTER_ACK: u8 = 0b001;
+const ENABLE: u8 = 0b010;
+const CYCLE_MODE: u8 = 0b100;
+
+/// IRQ counter shared by the Konami VRC chips. It counts up from a reload value either every
+/// CPU cycle or every scanline, with a prescaler dividing the CPU clock by 113.667.
+#[derive(Debug, Default)]
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    control: u8,
+    pending: bool,
+}
+
+impl VrcIrq {
+    pub fn write_latch(&mut self, value: u8) {
+        self.latch = value;
+    }
+
+    /// Sets one of the nibbles of the reload value, for the chips that write it in two parts
+    pub fn write_latch_nibble(&mut self, high: bool, value: u8) {
+        self.latch = if high {
+            (self.latch & 0x0f) | (value << 4)
+        } else {
+            (self.latch & 0xf0) | (value & 0x0f)
+        };
+    }
+
+    pub fn write_control(&mut self, value: u8) {
+        self.control = value;
+        self.pending = false;
+        if value & ENABLE != 0 {
+            self.counter = self.latch;
+            self.prescaler = PRESCALER_PERIOD;
+        }
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        let enable = (self.control & ENABLE_AFTER_ACK) << 1;
+        self.control = (self.control & !ENABLE) | enable;
+    }
+
+    pub fn tick(&mut self, cycles: u8) {
+        if self.control & ENABLE == 0 {
+            return;
+        }
+        for _ in 0..cycles {
+            if self.control & CYCLE_MODE != 0 {
+                self.clock();
+                continue;
+            }
+            self.prescaler -= PRESCALER_STEP;
+            if self.prescaler <= 0 {
+                self.prescaler += PRESCALER_PERIOD;
+                self.clock();
+            }
+        }
+    }
+
+    pub fn irq(&self) -> bool {
+        self.pending
+    }
+
+    fn clock(&mut self) {
+        if self.counter == 0xff {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cycle_mode() {
+        let mut irq = VrcIrq::default();
+        irq.write_latch_nibble(false, 0xd);
+        irq.write_latch_nibble(true, 0xf);
+        irq.write_control(ENABLE | CYCLE_MODE);
+        irq.tick(2);
+        assert!(!irq.irq());
+        irq.tick(1);
+        assert!(irq.irq());
+
+        irq.acknowledge();
+        assert!(!irq.irq());
+        // disabled, the acknowledgement copies the A bit
+        irq.tick(3);
+        assert!(!irq.irq());
+    }
+
+    #[test]
+    fn scanline_mode() {
+        let mut irq = VrcIrq::default();
+        irq.write_latch_nibble(false, 0xe);
+        irq.write_latch_nibble(true, 0xf);
+        irq.write_control(ENABLE | ENABLE_AFTER_ACK);
+        // two scanlines of 113.667 CPU cycles
+        irq.tick(227);
+        assert!(!irq.irq());
+        irq.tick(1);
+        assert!(irq.irq());
+
+        irq.acknowledge();
+        assert_eq!(irq.control & ENABLE, ENABLE);
+    }
+}
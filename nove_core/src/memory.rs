@@ -8,8 +8,11 @@ pub trait Memory {
 
     fn write(&mut self, addr: u16, value: u8);
 
+    /// Read-modify-write of an address. Like the 6502, the unmodified value is written back
+    /// before the result, which is noticeable on memory mapped registers.
     fn update(&mut self, addr: u16, update_fn: fn(u8) -> u8) {
         let val = self.read(addr);
+        self.write(addr, val);
         self.write(addr, update_fn(val));
     }
 
@@ -26,4 +29,9 @@ pub trait Memory {
     }
 
     fn tick(&mut self, _cycles: u8) {}
+
+    /// Level of the IRQ line, driven low by any device of the memory map
+    fn irq(&self) -> bool {
+        false
+    }
 }
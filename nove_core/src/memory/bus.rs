@@ -1,63 +1,155 @@
 use crate::addresses::*;
-use crate::cartridge::Rom;
+use crate::cartridge::{Cartridge, Rom};
+use crate::exception::NoveError;
 use crate::interrupt::InterruptFlag;
+use crate::mapper;
 use crate::memory::Memory;
 use crate::ppu::Ppu;
-use crate::register::{RegRead, RegWrite};
-use crate::Program;
+use crate::register::RegWrite;
+use crate::SAMPLE_RATE;
 use log::{debug, info};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::rc::Rc;
 
 const VRAM_SIZE: usize = 2048;
-const HALF_ROM_SIZE: usize = 0x4000;
 
 const PPU_CYCLES_PER_CPU: u8 = 3;
+/// Clock rate of the NTSC CPU, in Hz
+const CPU_FREQUENCY: u32 = 1_789_773;
+/// Samples kept until they are taken, a second of audio. The oldest are dropped past it.
+const MAX_SAMPLES: usize = SAMPLE_RATE as usize;
+/// Bits of the joypad ports not driven by the standard controllers
+const JOYPAD_OPEN_BUS: u8 = 0b1110_0000;
+const OAM_DMA_PAGE_SIZE: u16 = 256;
+/// CPU cycles halted by the OAM DMA: one to wait for the write, and a read and a write for
+/// each byte. Another one is needed to align the reads when it starts on an odd cycle.
+const OAM_DMA_CYCLES: u16 = 1 + 2 * OAM_DMA_PAGE_SIZE;
 
 pub struct Bus {
     vram: [u8; VRAM_SIZE],
-    prg_rom: Program,
+    cartridge: Cartridge,
     pub(crate) ppu: RefCell<Ppu>,
+    /// Last value driven on the data bus, read back from the addresses nothing answers
+    open_bus: Cell<u8>,
+    /// CPU cycles elapsed since the power up
+    cycles: u64,
+    dma_pending: bool,
+    /// Audio output sampled at `SAMPLE_RATE` since the last time they were taken
+    samples: VecDeque<f32>,
+    /// CPU cycles since the last sample, scaled by the sample rate
+    sample_clock: u32,
 }
 
 impl Bus {
-    pub fn new(rom: Rom, cpu_interrupt: Rc<RefCell<InterruptFlag>>) -> Self {
-        let ppu = Ppu::new(rom.chr_rom, rom.screen_mirroring, cpu_interrupt);
-        Self {
+    pub fn new(rom: Rom, cpu_interrupt: Rc<RefCell<InterruptFlag>>) -> Result<Self, NoveError> {
+        let cartridge = mapper::load(rom)?;
+        let ppu = Ppu::new(cartridge.clone(), cpu_interrupt);
+        Ok(Self {
             vram: [Default::default(); VRAM_SIZE],
-            prg_rom: rom.prg_rom,
+            cartridge,
             ppu: RefCell::new(ppu),
-        }
+            open_bus: Cell::new(0),
+            cycles: 0,
+            dma_pending: false,
+            samples: VecDeque::with_capacity(MAX_SAMPLES),
+            sample_clock: 0,
+        })
+    }
+
+    /// Copies a page of the CPU memory into the OAM through OAMDATA, starting at OAMADDR
+    fn oam_dma(&mut self, page: u8) {
+        let start = (page as u16) << 8;
+        let data: Vec<u8> = (start..start + OAM_DMA_PAGE_SIZE)
+            .map(|addr| self.read(addr))
+            .collect();
+        let mut ppu = self.ppu.borrow_mut();
+        data.into_iter().for_each(|value| ppu.oam.write(value));
+        self.dma_pending = true;
     }
 
-    pub fn read_rom(&self, addr: u16) -> u8 {
-        let mut addr = (addr - rom::PRG_ROM_START) as usize;
-        if self.prg_rom.len() == HALF_ROM_SIZE && addr >= HALF_ROM_SIZE {
-            addr %= HALF_ROM_SIZE;
+    fn step(&mut self, cpu_cycles: u8) {
+        self.cycles += cpu_cycles as u64;
+        self.cartridge.borrow_mut().tick(cpu_cycles);
+        for _ in 0..(cpu_cycles * PPU_CYCLES_PER_CPU) {
+            self.ppu.borrow_mut().tick();
+        }
+
+        self.sample_clock += cpu_cycles as u32 * SAMPLE_RATE;
+        while self.sample_clock >= CPU_FREQUENCY {
+            self.sample_clock -= CPU_FREQUENCY;
+            if self.samples.len() == MAX_SAMPLES {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(self.audio_output());
         }
-        self.prg_rom[addr]
+    }
+
+    /// Current level of the audio output of the console. The APU is not emulated yet, so it
+    /// only carries the expansion audio of the cartridge.
+    fn audio_output(&self) -> f32 {
+        self.cartridge.borrow().audio_output()
+    }
+
+    /// Takes the audio samples produced since the last call, at `SAMPLE_RATE`, up to the last
+    /// second of them
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.samples.drain(..).collect()
+    }
+
+    /// Battery backed memory of the cartridge, `None` if it doesn't keep any
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        self.cartridge.borrow().save_data()
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        self.cartridge.borrow_mut().load_save_data(data)
+    }
+
+    /// Number of sides of the disks in the Famicom Disk System, zero for the rest of cartridges
+    pub fn disk_sides(&self) -> usize {
+        self.cartridge.borrow().disk_sides()
+    }
+
+    pub fn insert_disk(&mut self, side: Option<usize>) {
+        self.cartridge.borrow_mut().insert_disk(side)
     }
 }
 
 impl Memory for Bus {
     fn read(&self, addr: u16) -> u8 {
-        match addr {
+        let value = match addr {
             ram::START..=ram::MIRRORS_END => self.vram[addr as usize & 0b00000111_11111111],
             ppu::STATUS => self.ppu.borrow_mut().read_status(),
-            ppu::OAM_DATA => self.ppu.borrow().oam.read(),
+            ppu::OAM_DATA => self.ppu.borrow_mut().read_oam_data(),
             ppu::DATA => self.ppu.borrow_mut().read_data(),
             ppu::REGISTERS_START..=ppu::REGISTERS_MIRRORS_END => self.read(addr & ppu::DATA),
-            rom::PRG_ROM_START..=rom::PRG_ROM_END => self.read_rom(addr),
-            ppu::CTRL | ppu::MASK | ppu::OAM_ADDR | ppu::SCROLL | ppu::ADDR | ppu::OAM_DMA => {
+            rom::CARTRIDGE_START..=rom::PRG_ROM_END => self
+                .cartridge
+                .borrow_mut()
+                .read_prg(addr)
+                .unwrap_or(self.open_bus.get()),
+            ppu::CTRL | ppu::MASK | ppu::OAM_ADDR | ppu::SCROLL | ppu::ADDR => {
                 info!("invalid attempt to read from write-only PPU address {addr:x}");
-                0
+                self.ppu.borrow().open_bus.get()
             }
-            _ => 0,
-        }
+            // the controllers only drive the lowest bits
+            input::JOYPAD_1 | input::JOYPAD_2 => self.open_bus.get() & JOYPAD_OPEN_BUS,
+            _ => self.open_bus.get(),
+        };
+        self.open_bus.set(value);
+        value
     }
 
     fn write(&mut self, addr: u16, value: u8) {
         debug!("write: {addr:#04x}={value}");
+        self.open_bus.set(value);
+        if addr < rom::CARTRIDGE_START {
+            self.cartridge.borrow_mut().notify_cpu_write(addr, value);
+        }
+        if let ppu::CTRL..=ppu::DATA = addr {
+            self.ppu.borrow_mut().open_bus.drive(value, 0xff);
+        }
         match addr {
             ram::START..=ram::MIRRORS_END => self.vram[addr as usize & 0b0111_1111_1111] = value,
             ppu::CTRL => self.ppu.borrow_mut().write_to_ctrl(value),
@@ -67,11 +159,15 @@ impl Memory for Bus {
             ppu::SCROLL => self.ppu.borrow_mut().scroll.write(value),
             ppu::ADDR => self.ppu.borrow_mut().addr.write(value),
             ppu::DATA => self.ppu.borrow_mut().write_to_data(value),
+            ppu::OAM_DMA => self.oam_dma(value),
             ppu::REGISTERS_START..=ppu::REGISTERS_MIRRORS_END => {
                 self.write(addr & ppu::DATA, value)
             }
-            ppu::STATUS | rom::PRG_ROM_START..=rom::PRG_ROM_END => {
-                panic!("invalid attempt to write to read-only PPU address {addr:x}");
+            rom::CARTRIDGE_START..=rom::PRG_ROM_END => {
+                self.cartridge.borrow_mut().write_prg(addr, value)
+            }
+            ppu::STATUS => {
+                info!("attempt to write to read-only PPU address {addr:x}");
             }
             _ => {
                 info!("attempt to write on non-write PPU address, {addr:x}")
@@ -79,9 +175,86 @@ impl Memory for Bus {
         }
     }
 
+    fn irq(&self) -> bool {
+        self.cartridge.borrow().irq()
+    }
+
     fn tick(&mut self, cpu_cycles: u8) {
-        for _ in 0..(cpu_cycles * PPU_CYCLES_PER_CPU) {
-            self.ppu.borrow_mut().tick();
+        self.step(cpu_cycles);
+        if std::mem::take(&mut self.dma_pending) {
+            // the CPU is halted after the write, but the PPU and the cartridge keep running
+            let stall = OAM_DMA_CYCLES + (self.cycles % 2) as u16;
+            (0..stall).for_each(|_| self.step(1));
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bus() -> Bus {
+        let rom = Rom {
+            prg_rom: vec![0; 0x8000],
+            ..Default::default()
+        };
+        Bus::new(rom, Rc::new(RefCell::new(InterruptFlag::None))).unwrap()
+    }
+
+    #[test]
+    fn oam_dma() {
+        let mut bus = bus();
+        (0..=255).for_each(|i| bus.write(0x0200 + i as u16, i));
+        bus.write(ppu::OAM_ADDR, 0x10);
+        bus.write(ppu::OAM_DMA, 0x02);
+
+        // the copy starts at OAMADDR, wrapping around the OAM
+        bus.write(ppu::OAM_ADDR, 0x10);
+        assert_eq!(bus.read(ppu::OAM_DATA), 0x00);
+        bus.write(ppu::OAM_ADDR, 0x0f);
+        assert_eq!(bus.read(ppu::OAM_DATA), 0xff);
+    }
+
+    #[test]
+    fn audio_samples() {
+        let mut bus = bus();
+        // a second of CPU cycles
+        (0..CPU_FREQUENCY / 3).for_each(|_| bus.step(3));
+        assert_eq!(bus.take_audio_samples().len(), SAMPLE_RATE as usize);
+        assert!(bus.take_audio_samples().is_empty());
+
+        // without anyone taking them only the last second is kept
+        (0..CPU_FREQUENCY).for_each(|_| bus.step(3));
+        assert_eq!(bus.take_audio_samples().len(), MAX_SAMPLES);
+    }
+
+    #[test]
+    fn open_bus() {
+        let mut bus = bus();
+        bus.write(0x0000, 0x5a);
+        assert_eq!(bus.read(0x0000), 0x5a);
+        assert_eq!(bus.read(0x5000), 0x5a);
+        bus.write(0x0001, 0xff);
+        bus.read(0x0001);
+        assert_eq!(bus.read(input::JOYPAD_1), 0xe0);
+
+        // the PPU keeps its own latch, refreshed by its registers
+        bus.write(ppu::SCROLL, 0x33);
+        bus.read(0x0000);
+        assert_eq!(bus.read(ppu::CTRL), 0x33);
+        assert_eq!(bus.read(0x2009), 0x33);
+    }
+
+    #[test]
+    fn oam_dma_stall() {
+        let mut bus = bus();
+        bus.write(ppu::OAM_DMA, 0x02);
+        bus.tick(4);
+        assert_eq!(bus.cycles, 4 + 513);
+
+        // started on an odd cycle
+        bus.write(ppu::OAM_DMA, 0x02);
+        bus.tick(4);
+        assert_eq!(bus.cycles, 4 + 513 + 4 + 514);
+    }
+}
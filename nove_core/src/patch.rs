@@ -0,0 +1,184 @@
+use crate::exception::NoveError;
+use crate::Program;
+
+mod bps;
+mod ips;
+mod ups;
+
+/// Size of the footer of UPS and BPS patches: the CRC32 of the source, target and patch
+const CHECKSUMS_SIZE: usize = 12;
+/// Largest image UPS and BPS patches can produce, well above any NES or FDS dump
+const MAX_TARGET_SIZE: usize = 0x400_0000; // 64 MiB
+
+/// Creates an IPS patch turning `source` into `target`, with RLE records for the runs of
+/// repeated bytes. IPS can't address images over 16 MiB.
+pub fn create_ips(source: &[u8], target: &[u8]) -> Result<Program, NoveError> {
+    ips::create(source, target)
+}
+
+/// Creates a BPS patch turning `source` into `target`, checked by the CRC32 of both
+pub fn create_bps(source: &[u8], target: &[u8]) -> Program {
+    bps::create(source, target)
+}
+
+/// Applies an IPS, UPS or BPS patch to the raw image of a ROM, telling the format by the
+/// magic number of the patch
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Program, NoveError> {
+    if patch.starts_with(ips::MAGIC) {
+        ips::apply(rom, patch)
+    } else if patch.starts_with(ups::MAGIC) {
+        ups::apply(rom, patch)
+    } else if patch.starts_with(bps::MAGIC) {
+        bps::apply(rom, patch)
+    } else {
+        Err(NoveError::WrongPatchFormat)
+    }
+}
+
+/// Reader of the bytes of a patch, failing with `WrongPatchFormat` when running out of them
+struct Reader<'a> {
+    patch: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(patch: &'a [u8], pos: usize) -> Self {
+        Self { patch, pos }
+    }
+
+    fn byte(&mut self) -> Result<u8, NoveError> {
+        let byte = *self
+            .patch
+            .get(self.pos)
+            .ok_or(NoveError::WrongPatchFormat)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], NoveError> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.patch.get(self.pos..end))
+            .ok_or(NoveError::WrongPatchFormat)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Big endian number of `len` bytes, used by IPS
+    fn number(&mut self, len: usize) -> Result<usize, NoveError> {
+        Ok(self
+            .bytes(len)?
+            .iter()
+            .fold(0, |number, &byte| number << 8 | byte as usize))
+    }
+
+    /// Size of the target of UPS and BPS, rejecting the ones no ROM has before allocating them
+    fn target_size(&mut self) -> Result<usize, NoveError> {
+        let size = self.varint()?;
+        if size > MAX_TARGET_SIZE {
+            return Err(NoveError::WrongPatchFormat);
+        }
+        Ok(size)
+    }
+
+    /// Variable length number of UPS and BPS. Each byte carries seven bits, least significant
+    /// first, with the high bit flagging the last one. The encoding skips the redundant
+    /// representations, so every continuation adds one to the next group.
+    fn varint(&mut self) -> Result<usize, NoveError> {
+        let mut number: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.byte()?;
+            number = (byte as usize & 0x7f)
+                .checked_mul(shift)
+                .and_then(|value| number.checked_add(value))
+                .ok_or(NoveError::WrongPatchFormat)?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift.checked_shl(7).ok_or(NoveError::WrongPatchFormat)?;
+            number = number
+                .checked_add(shift)
+                .ok_or(NoveError::WrongPatchFormat)?;
+        }
+    }
+}
+
+/// Writes a number with the variable length encoding read by `Reader::varint`
+fn write_varint(patch: &mut Vec<u8>, mut number: usize) {
+    loop {
+        let bits = (number & 0x7f) as u8;
+        number >>= 7;
+        if number == 0 {
+            patch.push(0x80 | bits);
+            return;
+        }
+        patch.push(bits);
+        number -= 1;
+    }
+}
+
+/// Appends the footer of UPS and BPS patches
+fn write_footer(patch: &mut Vec<u8>, source: &[u8], target: &[u8]) {
+    patch.extend(crc32fast::hash(source).to_le_bytes());
+    patch.extend(crc32fast::hash(target).to_le_bytes());
+    patch.extend(crc32fast::hash(patch).to_le_bytes());
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Checks the footer of UPS and BPS patches against the source and the patched target
+fn check_footer(source: &[u8], target: &[u8], patch: &[u8]) -> Result<(), NoveError> {
+    let footer = &patch[patch.len() - CHECKSUMS_SIZE..];
+    let patch_crc = crc32fast::hash(&patch[..patch.len() - 4]);
+    if read_u32_le(&footer[8..]) != patch_crc {
+        return Err(NoveError::PatchChecksum("patch"));
+    }
+    if read_u32_le(footer) != crc32fast::hash(source) {
+        return Err(NoveError::PatchChecksum("source ROM"));
+    }
+    if read_u32_le(&footer[4..]) != crc32fast::hash(target) {
+        return Err(NoveError::PatchChecksum("patched ROM"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn varint() {
+        let mut reader = Reader::new(&[0x80, 0x00, 0x80, 0x7f, 0x00, 0x80], 0);
+        assert_eq!(reader.varint(), Ok(0));
+        assert_eq!(reader.varint(), Ok(128));
+        assert_eq!(reader.varint(), Ok(0x7f + 128 + 128 * 128));
+        assert_eq!(reader.varint(), Err(NoveError::WrongPatchFormat));
+
+        let mut patch = Vec::new();
+        for number in [0, 127, 128, 0x7f + 128 + 128 * 128, usize::MAX >> 8] {
+            write_varint(&mut patch, number);
+        }
+        let mut reader = Reader::new(&patch, 0);
+        assert_eq!(reader.varint(), Ok(0));
+        assert_eq!(reader.varint(), Ok(127));
+        assert_eq!(reader.varint(), Ok(128));
+        assert_eq!(reader.varint(), Ok(0x7f + 128 + 128 * 128));
+        assert_eq!(reader.varint(), Ok(usize::MAX >> 8));
+    }
+
+    #[test]
+    fn bytes_past_the_end() {
+        let mut reader = Reader::new(&[1, 2, 3], 1);
+        assert_eq!(reader.bytes(usize::MAX), Err(NoveError::WrongPatchFormat));
+        assert_eq!(reader.bytes(2), Ok(&[2, 3][..]));
+    }
+
+    #[test]
+    fn unknown_format() {
+        assert_eq!(apply(&[0; 4], b"NOPE"), Err(NoveError::WrongPatchFormat));
+    }
+}
@@ -0,0 +1,238 @@
+use crate::exception::NoveError;
+use crate::patch::{check_footer, write_footer, write_varint, Reader, CHECKSUMS_SIZE};
+use crate::Program;
+
+pub const MAGIC: &[u8] = b"BPS1";
+
+const SOURCE_READ: usize = 0;
+const TARGET_READ: usize = 1;
+const SOURCE_COPY: usize = 2;
+const TARGET_COPY: usize = 3;
+/// Shorter matches take less space as part of a TargetRead than in their own action
+const MIN_MATCH: usize = 4;
+
+/*
+   BPS patch, with the numbers as varints
+
+   source size
+   target size
+   metadata size, followed by the metadata
+   actions until the footer
+     (length - 1) << 2 | command
+     for TargetRead, the bytes to write
+     for SourceCopy and TargetCopy, the offset relative to the previous copy of the same
+     memory, with the sign in the lowest bit
+   footer with the CRC32 of the source, the target and the patch
+*/
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Program, NoveError> {
+    if patch.len() < MAGIC.len() + CHECKSUMS_SIZE {
+        return Err(NoveError::WrongPatchFormat);
+    }
+    let end = patch.len() - CHECKSUMS_SIZE;
+    let mut reader = Reader::new(&patch[..end], MAGIC.len());
+    let source_size = reader.varint()?;
+    let target_size = reader.target_size()?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+    if rom.len() != source_size {
+        return Err(NoveError::PatchChecksum("source ROM"));
+    }
+
+    let mut target = Vec::with_capacity(target_size);
+    let mut source_offset = 0;
+    let mut target_offset = 0;
+    while reader.pos < end {
+        let action = reader.varint()?;
+        let len = (action >> 2) + 1;
+        if len > target_size - target.len() {
+            return Err(NoveError::WrongPatchFormat);
+        }
+        match action & 0b11 {
+            SOURCE_READ => {
+                let pos = target.len();
+                let bytes = rom.get(pos..pos + len);
+                target.extend_from_slice(bytes.ok_or(NoveError::WrongPatchFormat)?);
+            }
+            TARGET_READ => target.extend_from_slice(reader.bytes(len)?),
+            SOURCE_COPY => {
+                source_offset = relative(source_offset, reader.varint()?)?;
+                let bytes = source_offset
+                    .checked_add(len)
+                    .and_then(|end| rom.get(source_offset..end));
+                target.extend_from_slice(bytes.ok_or(NoveError::WrongPatchFormat)?);
+                source_offset += len;
+            }
+            TARGET_COPY => {
+                target_offset = relative(target_offset, reader.varint()?)?;
+                // the copy can overlap the bytes it writes, repeating them
+                for _ in 0..len {
+                    let byte = *target
+                        .get(target_offset)
+                        .ok_or(NoveError::WrongPatchFormat)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+            _ => unreachable!("the commands have two bits"),
+        }
+    }
+    if target.len() != target_size {
+        return Err(NoveError::WrongPatchFormat);
+    }
+    check_footer(rom, &target, patch)?;
+    Ok(target)
+}
+
+/// Creates a patch of the bytes kept in place with SourceRead, the changed ones with
+/// TargetRead and the runs of repeated bytes with an overlapping TargetCopy
+pub fn create(source: &[u8], target: &[u8]) -> Program {
+    let mut patch = MAGIC.to_vec();
+    write_varint(&mut patch, source.len());
+    write_varint(&mut patch, target.len());
+    write_varint(&mut patch, 0);
+
+    let mut target_offset = 0;
+    let mut literal = 0;
+    let mut i = 0;
+    while i < target.len() {
+        let matching = target[i..]
+            .iter()
+            .zip(source.get(i..).unwrap_or_default())
+            .take_while(|(target, source)| target == source)
+            .count();
+        if matching >= MIN_MATCH {
+            write_target_read(&mut patch, &target[literal..i]);
+            write_action(&mut patch, SOURCE_READ, matching);
+            i += matching;
+            literal = i;
+            continue;
+        }
+        let run = target[i..]
+            .iter()
+            .take_while(|&&byte| byte == target[i])
+            .count();
+        if run > MIN_MATCH {
+            // the first byte of the run is read and the copy repeats it
+            write_target_read(&mut patch, &target[literal..=i]);
+            write_action(&mut patch, TARGET_COPY, run - 1);
+            write_varint(&mut patch, relative_data(target_offset, i));
+            target_offset = i + run - 1;
+            i += run;
+            literal = i;
+            continue;
+        }
+        i += 1;
+    }
+    write_target_read(&mut patch, &target[literal..]);
+    write_footer(&mut patch, source, target);
+    patch
+}
+
+fn write_action(patch: &mut Vec<u8>, command: usize, len: usize) {
+    write_varint(patch, (len - 1) << 2 | command);
+}
+
+fn write_target_read(patch: &mut Vec<u8>, bytes: &[u8]) {
+    if !bytes.is_empty() {
+        write_action(patch, TARGET_READ, bytes.len());
+        patch.extend(bytes);
+    }
+}
+
+/// Encodes the move from the previous copy offset to the next one
+fn relative_data(from: usize, to: usize) -> usize {
+    if to >= from {
+        (to - from) << 1
+    } else {
+        (from - to) << 1 | 1
+    }
+}
+
+fn relative(offset: usize, data: usize) -> Result<usize, NoveError> {
+    let delta = data >> 1;
+    let offset = if data & 1 == 0 {
+        offset.checked_add(delta)
+    } else {
+        offset.checked_sub(delta)
+    };
+    offset.ok_or(NoveError::WrongPatchFormat)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn action(command: usize, len: usize) -> u8 {
+        0x80 | ((len - 1) << 2 | command) as u8
+    }
+
+    fn patch(actions: &[u8], source: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut patch = MAGIC.to_vec();
+        patch.extend([0x80 | source.len() as u8, 0x80 | target.len() as u8]);
+        // two bytes of metadata
+        patch.extend([0x82, b'{', b'}']);
+        patch.extend(actions);
+        patch.extend(crc32fast::hash(source).to_le_bytes());
+        patch.extend(crc32fast::hash(target).to_le_bytes());
+        patch.extend(crc32fast::hash(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn actions() {
+        let source = [1, 2, 3, 4, 5, 6];
+        let target = [1, 2, 9, 8, 5, 6, 9, 8, 5, 6, 9];
+        let actions = [
+            action(SOURCE_READ, 2),
+            action(TARGET_READ, 2),
+            9,
+            8,
+            action(SOURCE_COPY, 2),
+            0x80 | 4 << 1,
+            action(TARGET_COPY, 5),
+            0x80 | 2 << 1,
+        ];
+        let patch = patch(&actions, &source, &target);
+        assert_eq!(apply(&source, &patch), Ok(target.to_vec()));
+    }
+
+    #[test]
+    fn round_trip() {
+        let source: Vec<u8> = (0..=255).cycle().take(0x1000).collect();
+        let mut target = source.clone();
+        target[0x10..0x12].fill(0);
+        target[0x100..0x200].fill(0xee);
+        target[0x800..0x810].fill(0xdd);
+        target.truncate(0xf00);
+        target.extend([1, 2, 3]);
+        let patch = create(&source, &target);
+        assert!(patch.len() < 64);
+        assert_eq!(apply(&source, &patch), Ok(target));
+
+        let longer = [source.as_slice(), &[9; 0x20]].concat();
+        assert_eq!(apply(&source, &create(&source, &longer)), Ok(longer));
+        assert_eq!(apply(&[], &create(&[], &source)), Ok(source.clone()));
+        assert_eq!(apply(&source, &create(&source, &[])), Ok(vec![]));
+    }
+
+    #[test]
+    fn sizes() {
+        let mut patch = MAGIC.to_vec();
+        for size in [2, usize::MAX >> 8, 0] {
+            write_varint(&mut patch, size);
+        }
+        patch.extend([0; CHECKSUMS_SIZE]);
+        assert_eq!(apply(&[1, 2], &patch), Err(NoveError::WrongPatchFormat));
+    }
+
+    #[test]
+    fn checksums() {
+        let source = [1, 2];
+        let patch = patch(&[action(SOURCE_READ, 2)], &source, &source);
+        assert_eq!(apply(&source, &patch), Ok(source.to_vec()));
+        assert_eq!(
+            apply(&[1, 3], &patch),
+            Err(NoveError::PatchChecksum("source ROM"))
+        );
+    }
+}
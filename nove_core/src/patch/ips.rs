@@ -0,0 +1,194 @@
+use crate::exception::NoveError;
+use crate::patch::Reader;
+use crate::Program;
+
+pub const MAGIC: &[u8] = b"PATCH";
+const EOF: usize = 0x454f46;
+const EOF_MARKER: &[u8] = b"EOF";
+/// Offsets are three bytes long
+const MAX_SIZE: usize = 0x1000000;
+const MAX_RECORD_SIZE: usize = 0xffff;
+/// Shorter runs take less space inside a normal record than in their own RLE one
+const RLE_MIN_RUN: usize = 9;
+
+/*
+   Records of an IPS patch, until the EOF offset
+
+   3 bytes  offset, big endian
+   2 bytes  size, big endian
+   size     bytes to write at the offset
+
+   A zero size marks an RLE record instead
+
+   2 bytes  count of repetitions
+   1 byte   value to repeat
+
+   The EOF can be followed by three bytes with the size to truncate the output to
+*/
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Program, NoveError> {
+    let mut target = rom.to_vec();
+    let mut reader = Reader::new(patch, MAGIC.len());
+    loop {
+        let offset = reader.number(3)?;
+        if offset == EOF {
+            break;
+        }
+        let bytes = match reader.number(2)? {
+            0 => {
+                let count = reader.number(2)?;
+                vec![reader.byte()?; count]
+            }
+            size => reader.bytes(size)?.to_vec(),
+        };
+        let end = offset + bytes.len();
+        if target.len() < end {
+            target.resize(end, 0);
+        }
+        target[offset..end].copy_from_slice(&bytes);
+    }
+    if let Ok(size) = reader.number(3) {
+        target.truncate(size);
+    }
+    Ok(target)
+}
+
+pub fn create(source: &[u8], target: &[u8]) -> Result<Program, NoveError> {
+    if target.len() > MAX_SIZE {
+        return Err(NoveError::PatchTooLarge);
+    }
+    let mut patch = MAGIC.to_vec();
+    let differs = |i: usize| source.get(i) != Some(&target[i]);
+    let mut i = 0;
+    while i < target.len() {
+        if !differs(i) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < target.len() && differs(i) {
+            i += 1;
+        }
+        write_records(&mut patch, target, start, i);
+    }
+    patch.extend(EOF_MARKER);
+    if target.len() < source.len() {
+        write_number(&mut patch, target.len(), 3);
+    }
+    Ok(patch)
+}
+
+/// Writes the records of a range of changed bytes, using RLE for the long runs
+fn write_records(patch: &mut Vec<u8>, target: &[u8], start: usize, end: usize) {
+    let mut literal = start;
+    let mut i = start;
+    while i < end {
+        let run = target[i..end]
+            .iter()
+            .take_while(|&&byte| byte == target[i])
+            .count()
+            .min(MAX_RECORD_SIZE);
+        if run >= RLE_MIN_RUN {
+            write_literal(patch, target, literal, i);
+            write_rle(patch, target, i, run);
+            literal = i + run;
+        }
+        i += run;
+    }
+    write_literal(patch, target, literal, end);
+}
+
+fn write_literal(patch: &mut Vec<u8>, target: &[u8], start: usize, end: usize) {
+    let mut offset = start;
+    while offset < end {
+        // a record on the EOF offset would end the patch, so it starts one byte before
+        let record = if offset == EOF { offset - 1 } else { offset };
+        let len = (end - record).min(MAX_RECORD_SIZE);
+        write_number(patch, record, 3);
+        write_number(patch, len, 2);
+        patch.extend(&target[record..record + len]);
+        offset = record + len;
+    }
+}
+
+fn write_rle(patch: &mut Vec<u8>, target: &[u8], mut offset: usize, mut run: usize) {
+    if offset == EOF {
+        write_literal(patch, target, offset, offset + 1);
+        offset += 1;
+        run -= 1;
+    }
+    write_number(patch, offset, 3);
+    write_number(patch, 0, 2);
+    write_number(patch, run, 2);
+    patch.push(target[offset]);
+}
+
+/// Big endian number of `len` bytes
+fn write_number(patch: &mut Vec<u8>, number: usize, len: usize) {
+    patch.extend((0..len).rev().map(|i| (number >> (8 * i)) as u8));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn records() {
+        let mut patch = MAGIC.to_vec();
+        patch.extend([0x00, 0x00, 0x01, 0x00, 0x02, 0xaa, 0xbb]);
+        // RLE past the end of the ROM
+        patch.extend([0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x03, 0xcc]);
+        patch.extend(b"EOF");
+        assert_eq!(
+            apply(&[0, 1, 2, 3], &patch),
+            Ok(vec![0, 0xaa, 0xbb, 3, 0xcc, 0xcc, 0xcc])
+        );
+
+        patch.extend([0x00, 0x00, 0x02]);
+        assert_eq!(apply(&[0, 1, 2, 3], &patch), Ok(vec![0, 0xaa]));
+    }
+
+    #[test]
+    fn round_trip() {
+        let source: Vec<u8> = (0..=255).collect();
+        let mut target = source.clone();
+        target[10] = 0;
+        target[100..150].fill(0xee);
+        target.extend([1, 2, 3]);
+        let patch = create(&source, &target).unwrap();
+        // the run is a single RLE record
+        assert_eq!(
+            patch[11..19],
+            [0x00, 0x00, 0x64, 0x00, 0x00, 0x00, 0x32, 0xee]
+        );
+        assert_eq!(apply(&source, &patch), Ok(target));
+
+        let target = &source[..100];
+        assert_eq!(
+            apply(&source, &create(&source, target).unwrap()),
+            Ok(target.to_vec())
+        );
+        assert_eq!(create(&source, &source).unwrap(), b"PATCHEOF");
+    }
+
+    #[test]
+    fn eof_offset() {
+        let source = vec![0; EOF + 0x20];
+        let mut target = source.clone();
+        target[EOF - 0x10] = 1;
+        target[EOF..EOF + 0x10].fill(2);
+        target[EOF + 0x10] = 3;
+        let patch = create(&source, &target).unwrap();
+        assert_eq!(apply(&source, &patch), Ok(target));
+        assert_eq!(
+            create(&[], &vec![0; MAX_SIZE + 1]),
+            Err(NoveError::PatchTooLarge)
+        );
+    }
+
+    #[test]
+    fn truncated_patch() {
+        let mut patch = MAGIC.to_vec();
+        patch.extend([0x00, 0x00, 0x01, 0x00, 0x02, 0xaa]);
+        assert_eq!(apply(&[0; 4], &patch), Err(NoveError::WrongPatchFormat));
+    }
+}
@@ -0,0 +1,106 @@
+use crate::exception::NoveError;
+use crate::patch::{check_footer, Reader, CHECKSUMS_SIZE};
+use crate::Program;
+
+pub const MAGIC: &[u8] = b"UPS1";
+
+/*
+   UPS patch, with the numbers as varints
+
+   source size
+   target size
+   hunks until the footer
+     offset relative to the end of the previous hunk
+     bytes to XOR with the source, ending with a zero
+   footer with the CRC32 of the source, the target and the patch
+*/
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Program, NoveError> {
+    if patch.len() < MAGIC.len() + CHECKSUMS_SIZE {
+        return Err(NoveError::WrongPatchFormat);
+    }
+    let end = patch.len() - CHECKSUMS_SIZE;
+    let mut reader = Reader::new(&patch[..end], MAGIC.len());
+    let source_size = reader.varint()?;
+    let target_size = reader.target_size()?;
+    if rom.len() != source_size {
+        return Err(NoveError::PatchChecksum("source ROM"));
+    }
+
+    let mut target = rom.to_vec();
+    target.resize(target_size, 0);
+    let mut pos: usize = 0;
+    while reader.pos < end {
+        pos = pos
+            .checked_add(reader.varint()?)
+            .filter(|&pos| pos <= target.len())
+            .ok_or(NoveError::WrongPatchFormat)?;
+        loop {
+            let xor = reader.byte()?;
+            if xor == 0 {
+                pos += 1;
+                break;
+            }
+            *target.get_mut(pos).ok_or(NoveError::WrongPatchFormat)? ^= xor;
+            pos += 1;
+        }
+    }
+    check_footer(rom, &target, patch)?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::patch::write_varint;
+
+    fn patch(hunks: &[u8], source: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut patch = MAGIC.to_vec();
+        patch.push(0x80 | source.len() as u8);
+        patch.push(0x80 | target.len() as u8);
+        patch.extend(hunks);
+        patch.extend(crc32fast::hash(source).to_le_bytes());
+        patch.extend(crc32fast::hash(target).to_le_bytes());
+        patch.extend(crc32fast::hash(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn hunks() {
+        let source = [1, 2, 3, 4];
+        let target = [1, 7, 3, 4, 0, 9];
+        // 2 ^ 5 at 1, then 0 ^ 9 at 5, two bytes past the terminator
+        let patch = patch(&[0x81, 0x05, 0x00, 0x82, 0x09, 0x00], &source, &target);
+        assert_eq!(apply(&source, &patch), Ok(target.to_vec()));
+    }
+
+    #[test]
+    fn sizes() {
+        let mut patch = MAGIC.to_vec();
+        write_varint(&mut patch, 4);
+        write_varint(&mut patch, usize::MAX >> 8);
+        patch.extend([0; CHECKSUMS_SIZE]);
+        assert_eq!(apply(&[0; 4], &patch), Err(NoveError::WrongPatchFormat));
+
+        // a hunk far past the end of the target
+        let mut hunks = Vec::new();
+        write_varint(&mut hunks, usize::MAX >> 8);
+        hunks.extend([0x01, 0x00]);
+        let patch = self::patch(&hunks, &[0; 4], &[0; 4]);
+        assert_eq!(apply(&[0; 4], &patch), Err(NoveError::WrongPatchFormat));
+    }
+
+    #[test]
+    fn checksums() {
+        let source = [1, 2, 3, 4];
+        let mut patch = patch(&[0x81, 0x05, 0x00], &source, &[1, 7, 3, 4]);
+        assert_eq!(
+            apply(&[1, 2, 3, 5], &patch),
+            Err(NoveError::PatchChecksum("source ROM"))
+        );
+        patch[6] ^= 1;
+        assert_eq!(
+            apply(&source, &patch),
+            Err(NoveError::PatchChecksum("patch"))
+        );
+    }
+}
@@ -1,18 +1,19 @@
 use crate::addresses::ppu::{CHROM_END, CHROM_START, LIMIT, PALETTE_START, VRAM_END, VRAM_START};
-use crate::cartridge::Mirroring;
+use crate::cartridge::Cartridge;
 use crate::interrupt::InterruptFlag;
 use crate::ppu::address_register::AddressRegister;
 use crate::ppu::controller_register::{ControlFlags, ControllerRegister};
-use crate::ppu::mask_register::MaskRegister;
+use crate::ppu::mask_register::{MaskFlag, MaskRegister};
 use crate::ppu::oam::Oam;
+use crate::ppu::open_bus::OpenBus;
 use crate::ppu::palette_table::PaletteTable;
 use crate::ppu::scroll_register::ScrollRegister;
 use crate::ppu::status_register::{PpuStatusFlag, StatusRegister};
 use crate::ppu::tile_reader::TileReader;
 use crate::register::{RegRead, RegWrite};
-use crate::{Program, HEIGHT, WIDTH};
+use crate::{HEIGHT, WIDTH};
 pub use frame::Frame;
-use log::{debug, info};
+use log::info;
 use std::cell::RefCell;
 use std::rc::Rc;
 
@@ -21,18 +22,31 @@ mod controller_register;
 mod frame;
 mod mask_register;
 mod oam;
+mod open_bus;
 mod palette_table;
 mod scroll_register;
 mod status_register;
 mod tile_reader;
 
 const VRAM_SIZE: usize = 2048; // 2 KiB
-const NAMETABLE_SIZE: u16 = 1024; // 1KiB
 const TILE_BANK_SIZE: u16 = 4096; // 4 KiB
 
 const SCANLINE_CYCLES: usize = 341;
 const NMI_SCANLINES: u16 = 241;
 const SCANLINES_PER_FRAME: u16 = 262;
+const PRE_RENDER_SCANLINE: u16 = SCANLINES_PER_FRAME - 1;
+
+const ATTRIBUTE_TABLE_OFFSET: u16 = 0x3c0;
+const SPRITE_FETCH_START: usize = 257;
+const SPRITE_FETCH_END: usize = 320;
+const BG_PREFETCH_START: usize = 321;
+const BG_PREFETCH_END: usize = 336;
+const DUMMY_FETCH_START: usize = 337;
+const EMPTY_SPRITE_TILE: u8 = 0xff;
+const PALETTE_ENTRY_MASK: u8 = 0b0011_1111;
+/// Greyscale mode keeps the column of grays of the palette
+const GREYSCALE_MASK: u8 = 0b0011_0000;
+const NAMETABLE_SIZE: u16 = 0x400;
 
 const TILE_WIDTH: u32 = 8;
 const TILE_HEIGHT: u32 = 8;
@@ -41,8 +55,16 @@ const TILE_BYTES_SIZE: u32 = (TILE_WIDTH * TILE_HEIGHT * TILE_COLOR_SPACE) / 8;
 const TILES_PER_ROW: u32 = WIDTH / TILE_WIDTH;
 const TILES_PER_FRAME: u32 = TILES_PER_ROW * HEIGHT / TILE_HEIGHT;
 
+/// Row of a background tile as fetched by the PPU: its palette and the two planes of the
+/// pattern in the order they are stored
+#[derive(Clone, Copy, Default)]
+struct TileRow {
+    palette: u8,
+    pattern: [u8; 2],
+}
+
 pub struct Ppu {
-    chr_rom: Program,
+    cartridge: Cartridge,
     pub ctrl: ControllerRegister, // 0x2000
     pub mask: MaskRegister,       // 0x2001
     pub status: StatusRegister,   // 0x2002
@@ -50,22 +72,23 @@ pub struct Ppu {
     pub scroll: ScrollRegister,   // 0x2005
     pub addr: AddressRegister,    // 0x2006
     palette: PaletteTable,        // 0x3f00..0x3fff
+    pub open_bus: OpenBus,
     vram: [u8; VRAM_SIZE],
-    mirroring: Mirroring,
     internal_data_buffer: u8,
     scanline: u16,
     cycles: usize,
+    /// Index of the tile of the last nametable fetch
+    fetched_tile: u8,
+    fetched_row: TileRow,
+    /// Rows of the background tiles fetched for each line of the screen
+    background: Vec<TileRow>,
     cpu_interrupt: Rc<RefCell<InterruptFlag>>,
 }
 
 impl Ppu {
-    pub fn new(
-        chr_rom: Program,
-        mirroring: Mirroring,
-        cpu_interrupt: Rc<RefCell<InterruptFlag>>,
-    ) -> Self {
+    pub fn new(cartridge: Cartridge, cpu_interrupt: Rc<RefCell<InterruptFlag>>) -> Self {
         Self {
-            chr_rom,
+            cartridge,
             ctrl: Default::default(),
             mask: Default::default(),
             status: Default::default(),
@@ -73,17 +96,21 @@ impl Ppu {
             scroll: Default::default(),
             addr: Default::default(),
             palette: Default::default(),
+            open_bus: Default::default(),
             vram: [Default::default(); VRAM_SIZE],
-            mirroring,
             internal_data_buffer: Default::default(),
             scanline: Default::default(),
             cycles: Default::default(),
+            fetched_tile: Default::default(),
+            fetched_row: Default::default(),
+            background: vec![Default::default(); (TILES_PER_ROW * HEIGHT) as usize],
             cpu_interrupt,
         }
     }
 
     pub fn tick(&mut self) -> bool {
         self.cycles += 1;
+        self.fetch();
         if self.cycles == SCANLINE_CYCLES {
             self.cycles = 0;
             self.scanline += 1;
@@ -101,6 +128,7 @@ impl Ppu {
             if self.scanline == SCANLINES_PER_FRAME {
                 // todo move to function (attempt_close_scanline)
                 self.scanline = 0;
+                self.open_bus.decay();
                 self.nmi_interruption(false);
                 self.status.low(PpuStatusFlag::Sprite0Hit);
                 self.status.low(PpuStatusFlag::VerticalBlankStarted);
@@ -110,48 +138,89 @@ impl Ppu {
         return false;
     }
 
+    /// Draws the background fetched during the last frame. Each pixel takes the value of the
+    /// pattern of its tile row and the palette fetched along with it, so whatever the cartridge
+    /// answered to each fetch is what ends on the screen.
     pub fn render(&self) -> Frame {
         let mut frame = Frame::new();
-        let bank_addr = self.ctrl.get_bit(ControlFlags::BGPatternAddr) as u16 * TILE_BANK_SIZE;
-        debug!("tile_bank_address={bank_addr}");
+        let backdrop = self.color(self.palette.background_color(0, 0));
+        if self.mask.is_lowered(MaskFlag::ShowBG) {
+            frame.buffer.fill(backdrop);
+            return frame;
+        }
 
-        let mut tileset = Vec::new();
         for i in 0..TILES_PER_FRAME {
-            let tile_idx = self.vram[i as usize] as u16;
-            tileset.push(tile_idx);
-            let tile_addr = (bank_addr + tile_idx * TILE_BYTES_SIZE as u16) as usize;
-            let tile = &self.chr_rom[tile_addr..tile_addr + TILE_BYTES_SIZE as usize];
+            let (column, row) = (i % TILES_PER_ROW, i / TILES_PER_ROW);
+            let tile_row = |y: u32| {
+                let line = row * TILE_HEIGHT + y;
+                self.background[(line * TILES_PER_ROW + column) as usize]
+            };
+            let mut tile = [0; TILE_BYTES_SIZE as usize];
+            for y in 0..TILE_HEIGHT {
+                let [low, high] = tile_row(y).pattern;
+                tile[y as usize] = low;
+                tile[(y + TILE_HEIGHT) as usize] = high;
+            }
 
-            let tile_values: Vec<u8> = TileReader::new(tile).collect();
-            frame.set_tile(i % TILES_PER_ROW, i / TILES_PER_ROW, &tile_values);
+            for (pixel, value) in TileReader::new(&tile).enumerate() {
+                let x = column * TILE_WIDTH + pixel as u32 % TILE_WIDTH;
+                let y = pixel as u32 / TILE_WIDTH;
+                let color = if x < TILE_WIDTH && self.mask.is_lowered(MaskFlag::ShowBGLeft) {
+                    backdrop
+                } else {
+                    let palette = tile_row(y).palette;
+                    self.color(self.palette.background_color(palette, value))
+                };
+                frame.set_pixel(x, row * TILE_HEIGHT + y, color);
+            }
         }
-        tileset.dedup();
-        let tiles: String = tileset.iter().map(|x| format!("{x} ")).collect();
-        println!("tiles >> {tiles}");
 
         frame
     }
 
+    /// Final color index of a palette entry, only keeping the grays in greyscale mode
+    fn color(&self, entry: u8) -> u8 {
+        if self.mask.is_raised(MaskFlag::Greyscale) {
+            entry & GREYSCALE_MASK
+        } else {
+            entry & PALETTE_ENTRY_MASK
+        }
+    }
+
     pub fn read_data(&mut self) -> u8 {
         let addr = self.addr.get();
+        self.cartridge.borrow_mut().notify_ppu_addr(addr);
         self.inc_vram_addr();
         use crate::addresses::ppu::*;
-        match addr {
-            CHROM_START..=CHROM_END => self.read_and_store(self.chr_rom[addr as usize]),
+        let (val, driven) = match addr {
+            CHROM_START..=CHROM_END => {
+                let val = self.cartridge.borrow_mut().read_chr(addr);
+                (self.read_and_store(val), 0xff)
+            }
             VRAM_START..=VRAM_END => {
-                self.read_and_store(self.vram[self.mirror_vram(addr) as usize])
+                let val = self.read_nametable(addr);
+                (self.read_and_store(val), 0xff)
             }
-            PALETTE_START..=LIMIT => self.palette.read(addr),
+            // the palette entries only have six bits
+            PALETTE_START..=LIMIT => (self.palette.read(addr), PALETTE_ENTRY_MASK),
             _ => panic!("invalid PPU read access to {}", self.addr.get()),
-        }
+        };
+        self.open_bus.drive(val, driven);
+        self.open_bus.get()
     }
 
     pub fn read_status(&mut self) -> u8 {
-        let val = self.status.read();
+        let flags = !u8::from(PpuStatusFlag::OpenBus);
+        self.open_bus.drive(self.status.read(), flags);
         self.status.low(PpuStatusFlag::VerticalBlankStarted);
         self.addr.reset();
         self.scroll.reset();
-        val
+        self.open_bus.get()
+    }
+
+    pub fn read_oam_data(&mut self) -> u8 {
+        self.open_bus.drive(self.oam.read(), 0xff);
+        self.open_bus.get()
     }
 
     pub fn write_to_ctrl(&mut self, value: u8) {
@@ -166,30 +235,130 @@ impl Ppu {
 
     pub fn write_to_data(&mut self, value: u8) {
         let addr = self.addr.get();
+        self.cartridge.borrow_mut().notify_ppu_addr(addr);
         match addr {
-            CHROM_START..=CHROM_END => { /* ignore attempt to right on CHR ROM space */ }
-            VRAM_START..=VRAM_END => self.vram[self.mirror_vram(addr) as usize] = value,
+            CHROM_START..=CHROM_END => self.cartridge.borrow_mut().write_chr(addr, value),
+            VRAM_START..=VRAM_END => {
+                self.cartridge
+                    .borrow_mut()
+                    .write_nametable(addr, value, &mut self.vram)
+            }
             PALETTE_START..=LIMIT => self.palette.write(addr, value),
             _ => panic!("invalid PPU write access to {}", addr),
         }
         self.inc_vram_addr();
     }
 
+    /// Puts on the PPU bus the address of the memory access of the current dot of the
+    /// rendering. Each access takes two dots: nametable, attribute and the two pattern planes
+    /// of the background tiles and, during the horizontal blank, the patterns of the sprites of
+    /// the next scanline. The background fetches are latched for the rendering.
+    fn fetch(&mut self) {
+        let rendering =
+            self.mask.is_raised(MaskFlag::ShowBG) || self.mask.is_raised(MaskFlag::ShowSprites);
+        let line = self.scanline;
+        let dot = self.cycles;
+        if !rendering
+            || (line >= HEIGHT as u16 && line != PRE_RENDER_SCANLINE)
+            || dot.is_multiple_of(2)
+        {
+            return;
+        }
+
+        let next_line = (line + 1) % SCANLINES_PER_FRAME;
+        let addr = match (dot, dot % 8) {
+            (SPRITE_FETCH_START..=SPRITE_FETCH_END, 5 | 7) => {
+                let addr = self.sprite_pattern_addr(line, (dot - SPRITE_FETCH_START) / 8);
+                if dot % 8 == 7 {
+                    addr + TILE_HEIGHT as u16
+                } else {
+                    addr
+                }
+            }
+            (SPRITE_FETCH_START..=SPRITE_FETCH_END, _) => VRAM_START,
+            (BG_PREFETCH_START..=BG_PREFETCH_END, _) => {
+                return self.fetch_background(next_line, dot - BG_PREFETCH_START);
+            }
+            // unused fetches of the nametable byte of the third tile of the next line
+            (DUMMY_FETCH_START.., _) => {
+                return self.fetch_background(next_line, 2 * TILE_WIDTH as usize);
+            }
+            (1..SPRITE_FETCH_START, _) => return self.fetch_background(line, dot + 15),
+            _ => VRAM_START,
+        };
+        self.cartridge.borrow_mut().notify_ppu_addr(addr);
+    }
+
+    /// Fetch of the background pipeline, `x` being the pixel of the line being fetched. The
+    /// cartridge sees the address before answering, as the PPU puts it on the bus first.
+    fn fetch_background(&mut self, line: u16, x: usize) {
+        let row = (line / TILE_HEIGHT as u16) % (HEIGHT / TILE_HEIGHT) as u16;
+        let column = (x as u16 / TILE_WIDTH as u16) % TILES_PER_ROW as u16;
+        let nametable = (self.ctrl.0 & u8::from(ControlFlags::Nametable)) as u16;
+        let nametable_addr = VRAM_START + nametable * NAMETABLE_SIZE;
+        match x % 8 {
+            0 | 1 => {
+                let addr = nametable_addr + row * TILES_PER_ROW as u16 + column;
+                self.cartridge.borrow_mut().notify_ppu_addr(addr);
+                self.fetched_tile = self.read_nametable(addr);
+            }
+            // each byte of the attribute table covers 4x4 tiles, two bits for each quadrant
+            2 | 3 => {
+                let addr = nametable_addr + ATTRIBUTE_TABLE_OFFSET + (row / 4) * 8 + column / 4;
+                self.cartridge.borrow_mut().notify_ppu_addr(addr);
+                let shift = (row % 4 / 2) * 4 + (column % 4 / 2) * 2;
+                self.fetched_row.palette = (self.read_nametable(addr) >> shift) & 0b11;
+            }
+            _ => {
+                let plane = x % 8 / 6;
+                let bank = self.ctrl.get_bit(ControlFlags::BGPatternAddr) as u16 * TILE_BANK_SIZE;
+                let addr = bank
+                    + self.fetched_tile as u16 * TILE_BYTES_SIZE as u16
+                    + plane as u16 * TILE_HEIGHT as u16
+                    + line % TILE_HEIGHT as u16;
+                self.cartridge.borrow_mut().notify_ppu_addr(addr);
+                self.fetched_row.pattern[plane] = self.cartridge.borrow_mut().read_chr(addr);
+                // the last two tiles of the line are the first ones of the next
+                let on_screen = (line as u32) < HEIGHT && (x as u32 / TILE_WIDTH) < TILES_PER_ROW;
+                if plane == 1 && on_screen {
+                    let i = line as usize * TILES_PER_ROW as usize + column as usize;
+                    self.background[i] = self.fetched_row;
+                }
+            }
+        }
+    }
+
+    /// Pattern address of one of the eight sprites fetched for the line after `line`
+    fn sprite_pattern_addr(&self, line: u16, slot: usize) -> u16 {
+        let tall = self.ctrl.is_raised(ControlFlags::SpriteSize);
+        let height = if tall { 2 } else { 1 } * TILE_HEIGHT as u16;
+        let (tile, row) = match self.oam.scanline_sprites(line, height).get(slot) {
+            Some(sprite) if line != PRE_RENDER_SCANLINE => {
+                let row = line.wrapping_sub(sprite[0] as u16);
+                let flipped = sprite[2] & 0b1000_0000 != 0;
+                (sprite[1], if flipped { height - 1 - row } else { row })
+            }
+            _ => (EMPTY_SPRITE_TILE, 0),
+        };
+        let tile = tile as u16;
+        if tall {
+            let bank = (tile & 1) * TILE_BANK_SIZE;
+            let tile = (tile & !1) + row / TILE_HEIGHT as u16;
+            bank + tile * TILE_BYTES_SIZE as u16 + row % TILE_HEIGHT as u16
+        } else {
+            let bank = self.ctrl.get_bit(ControlFlags::SpritePatternAddr) as u16 * TILE_BANK_SIZE;
+            bank + tile * TILE_BYTES_SIZE as u16 + row
+        }
+    }
+
     fn read_and_store(&mut self, val: u8) -> u8 {
         let prev = self.internal_data_buffer;
         self.internal_data_buffer = val;
         prev
     }
 
-    fn mirror_vram(&self, addr: u16) -> u16 {
-        let vram = (addr & VRAM_END) - VRAM_START;
-        let name_table = vram / NAMETABLE_SIZE;
-        vram - match (&self.mirroring, name_table) {
-            (Mirroring::Vertical, 2) | (Mirroring::Vertical, 3) => 2 * NAMETABLE_SIZE,
-            (Mirroring::Horizontal, 1) | (Mirroring::Horizontal, 2) => NAMETABLE_SIZE,
-            (Mirroring::Horizontal, 3) => 2 * NAMETABLE_SIZE,
-            _ => 0,
-        }
+    fn read_nametable(&self, addr: u16) -> u8 {
+        self.cartridge.borrow().read_nametable(addr, &self.vram)
     }
 
     fn nmi_interruption(&mut self, trigger: bool) {
@@ -216,19 +385,71 @@ impl Ppu {
 
 #[cfg(test)]
 mod test {
-    use crate::cartridge::Mirroring;
+    use crate::cartridge::{Cartridge, Mirroring, Rom};
     use crate::interrupt::InterruptFlag;
+    use crate::mapper;
     use crate::ppu::controller_register::ControlFlags;
-    use crate::ppu::{Ppu, NMI_SCANLINES, SCANLINE_CYCLES};
+    use crate::ppu::status_register::PpuStatusFlag;
+    use crate::ppu::{Ppu, NMI_SCANLINES, SCANLINES_PER_FRAME, SCANLINE_CYCLES};
+    use crate::register::RegWrite;
+    use crate::Program;
     use std::cell::RefCell;
     use std::rc::Rc;
 
     #[test]
     fn read_chrom() {
-        let mut ppu = Ppu::new(vec![0, 1, 2, 3], Mirroring::Horizontal, Default::default());
+        let mut ppu = Ppu::new(
+            cartridge(vec![0, 1, 2, 3], Mirroring::Horizontal),
+            Default::default(),
+        );
         assert_read(&mut ppu, 0x00, 0x01, 1);
     }
 
+    #[test]
+    fn write_chr_ram() {
+        let rom = Rom {
+            chr_ram_size: 0x2000,
+            ..Default::default()
+        };
+        let mut ppu = Ppu::new(mapper::load(rom).unwrap(), Default::default());
+        assert_write(&mut ppu, 0x01, 0x23, 0x45);
+        assert_write(&mut ppu, 0x1f, 0xff, 0x67);
+    }
+
+    #[test]
+    fn read_vram_single_screen() {
+        let mut ppu = preloaded_ppu(Mirroring::SingleScreenLower);
+        assert_read(&mut ppu, 0x20, 0x02, 3);
+        assert_read(&mut ppu, 0x24, 0x02, 3);
+        assert_read(&mut ppu, 0x28, 0x20, 4);
+        assert_read(&mut ppu, 0x2c, 0x20, 4);
+
+        let mut ppu = preloaded_ppu(Mirroring::SingleScreenUpper);
+        assert_read(&mut ppu, 0x20, 0x02, 5);
+        assert_read(&mut ppu, 0x2c, 0x20, 6);
+    }
+
+    #[test]
+    fn read_vram_cartridge_nametable() {
+        let rom = Rom {
+            mapper: 5,
+            prg_rom: vec![0; 0x8000],
+            ..Default::default()
+        };
+        let mut ppu = Ppu::new(
+            mapper::load(rom).unwrap(),
+            Rc::new(RefCell::new(InterruptFlag::None)),
+        );
+        // MMC5 with the lower nametable in VRAM and the upper ones in fill mode
+        ppu.cartridge.borrow_mut().write_prg(0x5105, 0b11_11_11_00);
+        ppu.cartridge.borrow_mut().write_prg(0x5106, 9);
+        ppu.cartridge.borrow_mut().write_prg(0x5107, 1);
+        ppu.vram[0x0002] = 3;
+        assert_read(&mut ppu, 0x20, 0x02, 3);
+        assert_read(&mut ppu, 0x24, 0x02, 9);
+        assert_read(&mut ppu, 0x2f, 0xc0, 0b0101_0101);
+    }
+
     #[test]
     fn read_vram_horizontal() {
         let mut ppu = preloaded_ppu(Mirroring::Horizontal);
@@ -254,15 +475,28 @@ mod test {
 
     #[test]
     fn read_palette() {
-        let mut ppu = Ppu::new(vec![], Mirroring::Horizontal, Default::default());
+        let mut ppu = Ppu::new(cartridge(vec![], Mirroring::Horizontal), Default::default());
         ppu.palette.0[0x12] = 0x34;
         ppu.palette.0[0x04] = 0x56;
 
         ppu.set_addr(0x3f, 0x12);
         assert_eq!(ppu.read_data(), 0x34);
 
+        // only six bits are driven, the upper two come from the open bus
         ppu.set_addr(0x3f, 0x14);
-        assert_eq!(ppu.read_data(), 0x56);
+        assert_eq!(ppu.read_data(), 0x16);
+        ppu.open_bus.drive(0xc0, 0xff);
+        ppu.set_addr(0x3f, 0x14);
+        assert_eq!(ppu.read_data(), 0xd6);
+    }
+
+    #[test]
+    fn read_status_open_bus() {
+        let mut ppu = Ppu::new(cartridge(vec![], Mirroring::Horizontal), Default::default());
+        ppu.status.raise(PpuStatusFlag::VerticalBlankStarted);
+        ppu.open_bus.drive(0x5f, 0xff);
+        assert_eq!(ppu.read_status(), 0x9f);
+        assert_eq!(ppu.read_status(), 0x1f);
     }
 
     #[test]
@@ -285,7 +519,7 @@ mod test {
 
     #[test]
     fn write_palette() {
-        let mut ppu = Ppu::new(vec![], Mirroring::Horizontal, Default::default());
+        let mut ppu = Ppu::new(cartridge(vec![], Mirroring::Horizontal), Default::default());
         ppu.set_addr(0x3f, 0x13);
         ppu.write_to_data(0x12);
         assert_eq!(ppu.palette.0[0x13], 0x12);
@@ -298,7 +532,7 @@ mod test {
     #[test]
     fn nmi_interrupt() {
         let interrupt: Rc<RefCell<InterruptFlag>> = Default::default();
-        let mut ppu = Ppu::new(vec![], Mirroring::Horizontal, interrupt.clone());
+        let mut ppu = Ppu::new(cartridge(vec![], Mirroring::Horizontal), interrupt.clone());
         ppu.ctrl.raise(ControlFlags::GenerateNMI);
 
         assert_eq!(*interrupt.borrow(), InterruptFlag::None);
@@ -308,6 +542,133 @@ mod test {
         assert_eq!(*interrupt.borrow(), InterruptFlag::NMI);
     }
 
+    #[test]
+    fn a12_scanline_counter() {
+        let rom = Rom {
+            mapper: 4,
+            ..Default::default()
+        };
+        let cartridge = mapper::load(rom).unwrap_or_else(|_| panic!("MMC3 is supported"));
+        let mut ppu = Ppu::new(cartridge.clone(), Default::default());
+        ppu.mask.write(0b0001_1000);
+        ppu.ctrl.raise(ControlFlags::SpritePatternAddr);
+        {
+            let mut mmc3 = cartridge.borrow_mut();
+            mmc3.write_prg(0xc000, 9);
+            mmc3.write_prg(0xc001, 0);
+            mmc3.write_prg(0xe001, 0);
+        }
+
+        // the first line reloads the counter and each of the next ones decrements it
+        for _ in 0..(SCANLINE_CYCLES * 9) {
+            ppu.tick();
+        }
+        assert!(!cartridge.borrow().irq());
+        for _ in 0..SCANLINE_CYCLES {
+            ppu.tick();
+        }
+        assert!(cartridge.borrow().irq());
+    }
+
+    #[test]
+    fn mmc2_sprite_latch() {
+        let rom = Rom {
+            mapper: 9,
+            prg_rom: vec![0; 0x8000],
+            chr_rom: (0..32).flat_map(|i| vec![i; 0x1000]).collect(),
+            ..Default::default()
+        };
+        let cartridge = mapper::load(rom).unwrap_or_else(|_| panic!("MMC2 is supported"));
+        let mut ppu = Ppu::new(cartridge.clone(), Default::default());
+        ppu.mask.write(0b0001_0000);
+        ppu.ctrl.raise(ControlFlags::SpritePatternAddr);
+        cartridge.borrow_mut().write_prg(0xd000, 3);
+        cartridge.borrow_mut().write_prg(0xe000, 4);
+        // a sprite of the tile $FD on the line 17, the rest hidden
+        for value in [0x10, 0xfd, 0x00, 0x00].into_iter().chain([0xff; 252]) {
+            ppu.oam.write(value);
+        }
+
+        for _ in 0..(SCANLINE_CYCLES * 0x10) {
+            ppu.tick();
+        }
+        assert_eq!(cartridge.borrow_mut().read_chr(0x1000), 4);
+        for _ in 0..SCANLINE_CYCLES {
+            ppu.tick();
+        }
+        assert_eq!(cartridge.borrow_mut().read_chr(0x1000), 3);
+    }
+
+    #[test]
+    fn render_background() {
+        let mut chr_rom = vec![0; 0x2000];
+        chr_rom[0x10..0x20].fill(0xff);
+        let mut ppu = Ppu::new(cartridge(chr_rom, Mirroring::Vertical), Default::default());
+        ppu.palette.write(0x3f00, 0x0f);
+        ppu.palette.write(0x3f07, 0x16);
+        ppu.palette.write(0x3f03, 0x21);
+        // tiles at the first and third columns of the second nametable, and at the first
+        // nametable which is not rendered
+        for (hi, lo) in [(0x24, 0x00), (0x24, 0x02), (0x20, 0x04)] {
+            ppu.set_addr(hi, lo);
+            ppu.write_to_data(1);
+        }
+        // top-right quadrant of the first attribute byte
+        ppu.set_addr(0x27, 0xc0);
+        ppu.write_to_data(0b0000_0100);
+        ppu.ctrl.write(0b0000_0001);
+        assert_eq!(ppu.render().get_pixel(16, 0), 0x0f);
+
+        ppu.mask.write(0b0000_1000);
+        tick_frame(&mut ppu);
+        let frame = ppu.render();
+        assert_eq!(frame.get_pixel(16, 0), 0x16);
+        assert_eq!(frame.get_pixel(23, 7), 0x16);
+        assert_eq!(frame.get_pixel(24, 0), 0x0f);
+        assert_eq!(frame.get_pixel(32, 0), 0x0f);
+        // the leftmost tile is hidden
+        assert_eq!(frame.get_pixel(0, 0), 0x0f);
+
+        ppu.mask.write(0b0000_1011);
+        let frame = ppu.render();
+        assert_eq!(frame.get_pixel(0, 0), 0x20);
+        assert_eq!(frame.get_pixel(16, 0), 0x10);
+    }
+
+    #[test]
+    fn render_extended_attributes() {
+        let mut chr_rom = vec![0; 0x2000];
+        chr_rom[0x1000..].fill(0xff);
+        let rom = Rom {
+            mapper: 5,
+            prg_rom: vec![0; 0x8000],
+            chr_rom,
+            ..Default::default()
+        };
+        let mut ppu = Ppu::new(mapper::load(rom).unwrap(), Default::default());
+        ppu.palette.write(0x3f0f, 0x2a);
+        // the first tile takes the fourth palette and the second 4 KiB bank from the ExRAM
+        {
+            let mut mmc5 = ppu.cartridge.borrow_mut();
+            mmc5.write_prg(0x5104, 2);
+            mmc5.write_prg(0x5c00, 0b11_000001);
+            mmc5.write_prg(0x5104, 1);
+        }
+        ppu.mask.write(0b0000_1010);
+        tick_frame(&mut ppu);
+
+        let frame = ppu.render();
+        assert_eq!(frame.get_pixel(0, 0), 0x2a);
+        assert_eq!(frame.get_pixel(7, 7), 0x2a);
+        assert_eq!(frame.get_pixel(8, 0), 0x00);
+    }
+
+    fn tick_frame(ppu: &mut Ppu) {
+        for _ in 0..(SCANLINE_CYCLES * SCANLINES_PER_FRAME as usize) {
+            ppu.tick();
+        }
+    }
+
     fn assert_read(ppu: &mut Ppu, hi: u8, lo: u8, val: u8) {
         ppu.set_addr(hi, lo);
         assert_ne!(val, ppu.read_data());
@@ -322,8 +683,17 @@ mod test {
         assert_eq!(ppu.read_data(), val);
     }
 
+    fn cartridge(chr_rom: Program, screen_mirroring: Mirroring) -> Cartridge {
+        let rom = Rom {
+            chr_rom,
+            screen_mirroring,
+            ..Default::default()
+        };
+        mapper::load(rom).unwrap_or_else(|_| panic!("NROM is always supported"))
+    }
+
     fn preloaded_ppu(mirroring: Mirroring) -> Ppu {
-        let mut ppu = Ppu::new(vec![], mirroring, Default::default());
+        let mut ppu = Ppu::new(cartridge(vec![], mirroring), Default::default());
         ppu.vram[0x0002] = 3;
         ppu.vram[0x0020] = 4;
         ppu.vram[0x0402] = 5;
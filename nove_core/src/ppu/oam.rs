@@ -1,12 +1,26 @@
 use crate::register::{RegRead, RegWrite, Register};
 
 const OAM_SIZE: usize = 256;
+const SPRITE_SIZE: usize = 4;
+const SPRITES_PER_SCANLINE: usize = 8;
 
 pub struct Oam {
     pub addr: Register,
     data: [u8; OAM_SIZE],
 }
 
+impl Oam {
+    /// First sprites in OAM order overlapping the scanline after `line`, the ones the PPU
+    /// fetches during `line`. Each sprite is returned as its four bytes: y, tile, attributes, x.
+    pub fn scanline_sprites(&self, line: u16, height: u16) -> Vec<&[u8]> {
+        self.data
+            .chunks(SPRITE_SIZE)
+            .filter(|sprite| line.wrapping_sub(sprite[0] as u16) < height)
+            .take(SPRITES_PER_SCANLINE)
+            .collect()
+    }
+}
+
 impl RegWrite for Oam {
     fn write(&mut self, val: u8) {
         self.data[self.addr.read() as usize] = val;
@@ -44,4 +58,16 @@ mod test {
         oam.addr.write(0x00);
         assert_eq!(oam.read(), 0x22);
     }
+
+    #[test]
+    fn scanline_sprites() {
+        let mut oam = Oam::default();
+        oam.data.fill(0xff);
+        oam.data[0..4].copy_from_slice(&[0x10, 0x01, 0x00, 0x00]);
+        oam.data[8..12].copy_from_slice(&[0x14, 0x02, 0x00, 0x00]);
+        assert_eq!(oam.scanline_sprites(0x10, 8).len(), 1);
+        assert_eq!(oam.scanline_sprites(0x17, 8).len(), 2);
+        assert_eq!(oam.scanline_sprites(0x1b, 8)[0][1], 0x02);
+        assert_eq!(oam.scanline_sprites(0x24, 16).len(), 0);
+    }
 }
@@ -0,0 +1,53 @@
+/// Frames it takes a bit of the open bus to decay to zero without being refreshed, around the
+/// 600 ms measured on the hardware
+const DECAY_FRAMES: u8 = 36;
+
+/// Latch of the data bus between the CPU and the PPU. Every access to the registers refreshes
+/// the bits driven by it, and reading the bits no register drives returns the stale value.
+#[derive(Default)]
+pub struct OpenBus {
+    value: u8,
+    /// Frames since each bit was last refreshed
+    ages: [u8; 8],
+}
+
+impl OpenBus {
+    pub fn get(&self) -> u8 {
+        self.value
+    }
+
+    /// Refreshes the bits of `mask` with the ones of `value`
+    pub fn drive(&mut self, value: u8, mask: u8) {
+        self.value = (self.value & !mask) | (value & mask);
+        (0..8)
+            .filter(|bit| mask & (1 << bit) != 0)
+            .for_each(|bit| self.ages[bit] = 0);
+    }
+
+    /// Ages the bits by a frame, clearing the ones that weren't refreshed in time
+    pub fn decay(&mut self) {
+        for bit in 0..8 {
+            self.ages[bit] = self.ages[bit].saturating_add(1);
+            if self.ages[bit] >= DECAY_FRAMES {
+                self.value &= !(1 << bit);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decay() {
+        let mut bus = OpenBus::default();
+        bus.drive(0xff, 0xff);
+        (0..DECAY_FRAMES - 1).for_each(|_| bus.decay());
+        bus.drive(0x00, 0x0f);
+        bus.drive(0x01, 0x01);
+        assert_eq!(bus.get(), 0xf1);
+        bus.decay();
+        assert_eq!(bus.get(), 0x01);
+    }
+}
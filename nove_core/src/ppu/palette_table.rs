@@ -1,6 +1,7 @@
 use crate::addresses::ppu::PALETTE_START;
 
 const PALETTE_SIZE: usize = 32;
+const COLORS_PER_PALETTE: u16 = 4;
 
 pub const MIRROR_UBG_COLOR: u16 = 0x3f10;
 pub const MIRROR_UU_COLOR_1: u16 = 0x3f14;
@@ -25,6 +26,15 @@ impl PaletteTable {
         }
     }
 
+    /// Color of a pixel of the background from its palette (0-3) and the value of its pattern.
+    /// Transparent pixels take the backdrop color of $3F00.
+    pub fn background_color(&self, palette: u8, value: u8) -> u8 {
+        match value {
+            0 => self.read(PALETTE_START),
+            _ => self.read(PALETTE_START + palette as u16 * COLORS_PER_PALETTE + value as u16),
+        }
+    }
+
     pub fn write(&mut self, addr: u16, val: u8) {
         match addr {
             MIRROR_UBG_COLOR | MIRROR_UU_COLOR_1 | MIRROR_UU_COLOR_2 | MIRROR_UU_COLOR_3 => {
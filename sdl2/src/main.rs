@@ -1,23 +1,33 @@
 use std::error::Error;
 use std::fmt::{Debug, Display};
+use std::path::Path;
 
+use sdl2::audio::AudioSpecDesired;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
 use structopt::StructOpt;
 
-use nove_core::cartridge::Rom;
+use nove_core::cartridge::{is_fds_image, Rom};
 use nove_core::core::NesNoveCore;
 use nove_core::interrupt::InterruptFlag;
-use nove_core::{HEIGHT, WIDTH};
+use nove_core::{patch, Program, HEIGHT, SAMPLE_RATE, WIDTH};
 
 use crate::rgb::RgbFrame;
+use crate::save::SaveFile;
 
 mod rgb;
+mod save;
 mod tile_render;
 
 const RGB_SPACE: u32 = 3;
 const SCALE: u32 = 3;
+/// Frames between the writes of the save file, to keep the progress if the emulator crashes
+const SAVE_INTERVAL: u32 = 600;
+/// Extensions of the patches applied to the ROM when found next to it with the same name
+const PATCH_EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+/// Audio queued before dropping samples, a few frames to keep the latency low
+const MAX_QUEUED_SAMPLES: u32 = SAMPLE_RATE / 10;
 
 const TILES_PER_BANK: usize = 256;
 
@@ -25,6 +35,9 @@ const TILES_PER_BANK: usize = 256;
 pub struct Args {
     /// The ROM file to read
     pub file: String,
+    /// BIOS of the Famicom Disk System, needed to run disk images
+    #[structopt(long)]
+    pub bios: Option<String>,
 }
 
 pub static SYSTEM_PALLETE: [(u8, u8, u8); 64] = [
@@ -119,6 +132,23 @@ fn main() -> Result<(), Box<dyn Error>> {
         .unwrap();
     canvas.set_scale(SCALE as f32, SCALE as f32).unwrap();
 
+    // the emulation goes on muted on machines without audio
+    let audio = sdl_context
+        .audio()
+        .and_then(|audio| {
+            audio.open_queue::<f32, _>(
+                None,
+                &AudioSpecDesired {
+                    freq: Some(SAMPLE_RATE as i32),
+                    channels: Some(1),
+                    samples: None,
+                },
+            )
+        })
+        .inspect(|audio| audio.resume())
+        .inspect_err(|e| log::warn!("failed to open audio queue, running without audio: {e}"))
+        .ok();
+
     let mut event_pump = sdl_context
         .event_pump()
         .expect("failed to create event pump");
@@ -128,16 +158,39 @@ fn main() -> Result<(), Box<dyn Error>> {
         .create_texture_target(PixelFormatEnum::RGB24, WIDTH, HEIGHT)
         .unwrap();
 
-    let content = std::fs::read(args.file).expect("failed to read rom file");
-    let rom = Rom::new(&content).unwrap();
-
-    let mut core = NesNoveCore::new(rom);
+    let content = std::fs::read(&args.file).expect("failed to read rom file");
+    let content = apply_patch(&args.file, content)?;
+    let rom = if is_fds_image(&content) {
+        let bios = args
+            .bios
+            .as_ref()
+            .ok_or("disk images need the FDS BIOS, pass it with --bios")?;
+        Rom::fds(&content, &std::fs::read(bios)?)?
+    } else {
+        Rom::new(&content)?
+    };
+
+    let mut core = NesNoveCore::new(rom)?;
+    let mut save_file = SaveFile::new(&args.file);
+    save_file.load(&mut core);
     core.reset();
 
+    let mut frames: u32 = 0;
+    let mut disk_side = 0;
+
     loop {
-        let interrupt = core.tick()?;
+        let interrupt = match core.tick() {
+            Ok(interrupt) => interrupt,
+            Err(e) => {
+                // keep the progress of the game even if the emulation can't go on
+                if let Err(save_error) = save_file.flush(&core) {
+                    log::error!("failed to write the save file: {save_error}");
+                }
+                return Err(e.into());
+            }
+        };
         if interrupt == InterruptFlag::BRK {
-            return Ok(());
+            return Ok(save_file.flush(&core)?);
         }
         if interrupt == InterruptFlag::NMI {
             let frame = core.render();
@@ -145,6 +198,18 @@ fn main() -> Result<(), Box<dyn Error>> {
             texture.update(None, &rgb_frame.data, 256 * 3).unwrap();
             canvas.copy(&texture, None, None).unwrap();
             canvas.present();
+
+            let samples = core.take_audio_samples();
+            if let Some(audio) = &audio {
+                if audio.size() / (std::mem::size_of::<f32>() as u32) < MAX_QUEUED_SAMPLES {
+                    audio.queue_audio(&samples)?;
+                }
+            }
+
+            frames = frames.wrapping_add(1);
+            if frames.is_multiple_of(SAVE_INTERVAL) {
+                save_file.flush(&core)?;
+            }
         }
         // todo move into NMI
         for event in event_pump.poll_iter() {
@@ -153,9 +218,29 @@ fn main() -> Result<(), Box<dyn Error>> {
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
-                } => return Ok(()),
+                } => return Ok(save_file.flush(&core)?),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } if core.disk_sides() > 0 => {
+                    disk_side = (disk_side + 1) % core.disk_sides();
+                    log::info!("inserting disk side {disk_side}");
+                    core.insert_disk(Some(disk_side));
+                }
                 _ => { /* do nothing */ }
             }
         }
     }
 }
+
+/// Applies the first patch found next to the ROM file with its same name
+fn apply_patch(rom_file: &str, content: Program) -> Result<Program, Box<dyn Error>> {
+    for extension in PATCH_EXTENSIONS {
+        let path = Path::new(rom_file).with_extension(extension);
+        if let Ok(patch) = std::fs::read(&path) {
+            log::info!("applying patch {}", path.display());
+            return Ok(patch::apply(&content, &patch)?);
+        }
+    }
+    Ok(content)
+}
@@ -1,4 +1,4 @@
-use crate::RGB_SPACE;
+use crate::{RGB_SPACE, SYSTEM_PALLETE};
 use nove_core::core::Frame;
 use nove_core::{HEIGHT, WIDTH};
 
@@ -32,12 +32,8 @@ impl From<Frame> for RgbFrame {
     }
 }
 
-fn to_rgb(val: &u8) -> Vec<u8> {
-    match val {
-        0 => vec![0x00, 0x3D, 0xA6],
-        1 => vec![0xD4, 0x80, 0xFF],
-        2 => vec![0xFF, 0x9C, 0x12],
-        3 => vec![0x2B, 0xF0, 0x35],
-        _ => panic!("nes only supports for possible colors per pixel"),
-    }
+/// RGB of a NES color index of the frame
+fn to_rgb(val: &u8) -> [u8; 3] {
+    let (r, g, b) = SYSTEM_PALLETE[(*val & 0x3f) as usize];
+    [r, g, b]
 }
@@ -0,0 +1,39 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use nove_core::core::NesNoveCore;
+
+/// Battery backed memory of the cartridge, kept raw next to the ROM with the same name like
+/// other emulators do, so the saves can be moved between them
+pub struct SaveFile {
+    path: PathBuf,
+    written: Option<Vec<u8>>,
+}
+
+impl SaveFile {
+    pub fn new(rom_file: &str) -> Self {
+        Self {
+            path: Path::new(rom_file).with_extension("sav"),
+            written: None,
+        }
+    }
+
+    pub fn load(&mut self, core: &mut NesNoveCore) {
+        if let Ok(save) = std::fs::read(&self.path) {
+            core.load_save_data(&save);
+            self.written = Some(save);
+        }
+    }
+
+    /// Writes the save file if the memory changed since the last write
+    pub fn flush(&mut self, core: &NesNoveCore) -> io::Result<()> {
+        match core.save_data() {
+            Some(save) if self.written.as_ref() != Some(&save) => {
+                std::fs::write(&self.path, &save)?;
+                self.written = Some(save);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}